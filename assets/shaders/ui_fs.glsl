@@ -0,0 +1,16 @@
+#version 450
+
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_ARB_shading_language_450pack : enable
+
+// plain quads sample a white pixel, glyphs are white with their coverage as alpha
+layout(set = 0, binding = 0) uniform sampler2D tex;
+
+layout(location = 0) in vec2 v_uv;
+layout(location = 1) in vec4 v_color;
+
+layout(location = 0) out vec4 f_color;
+
+void main() {
+    f_color = v_color * texture(tex, v_uv);
+}
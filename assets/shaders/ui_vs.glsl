@@ -0,0 +1,22 @@
+#version 450
+
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_ARB_shading_language_420pack : enable
+
+// positions are in pixels from the top left of the window
+layout(location = 0) in vec2 position;
+layout(location = 1) in vec2 uv;
+layout(location = 2) in vec4 color;
+
+layout(location = 0) out vec2 v_uv;
+layout(location = 1) out vec4 v_color;
+
+layout(push_constant) uniform PushConstants {
+    vec2 screen_size;
+} push_constants;
+
+void main() {
+    gl_Position = vec4(position / push_constants.screen_size * 2.0 - 1.0, 0.0, 1.0);
+    v_uv = uv;
+    v_color = color;
+}
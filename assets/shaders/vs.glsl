@@ -10,17 +10,15 @@ layout(location = 2) in vec2 uv;
 layout(location = 0) out vec3 v_normal;
 layout(location = 1) out vec2 v_uv;
 
-layout(set = 0, binding = 1) uniform Data {
-    mat4 proj;
-} uniforms;
-
+// projection is pushed alongside the model matrix, as it varies per viewport
 layout(push_constant) uniform PushConstants {
     mat4 model_mat;
+    mat4 proj;
 } push_constants;
 
 void main() {
     mat4 mat = push_constants.model_mat;
     v_normal = transpose(inverse(mat3(mat))) * normal;
-    gl_Position = uniforms.proj * mat * vec4(position, 1.0);
+    gl_Position = push_constants.proj * mat * vec4(position, 1.0);
     v_uv = uv;
 }
@@ -1,60 +1,433 @@
-use std::sync::{Arc, Mutex, Weak};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 
-/// Since we want cross-thread handling of input, we choose Arc
-pub type EventHandler<T> = dyn Fn(T) -> ();
-pub type ArcEventHandler<T> = Arc<Mutex<Box<EventHandler<T>>>>;
-pub type WeakEventHandler<T> = Weak<Mutex<Box<EventHandler<T>>>>;
+/// Handlers may be called from whichever thread dispatches the bus
+pub type EventHandler<T> = dyn Fn(&T) + Send + Sync;
 
-pub trait EventProducer<T>
-where
-    T: Clone,
-{
-    fn add_handler(&mut self, id: String, handler: &ArcEventHandler<T>);
-    fn remove_handler(&mut self, id: &str);
-    fn publish(&mut self, event: T);
+struct Registered<T> {
+    id: String,
+    priority: i32,
+    handler: Weak<EventHandler<T>>,
 }
 
-pub struct CopyingEventProducer<T> {
-    handlers: HashMap<String, WeakEventHandler<T>>,
+struct BusInner<T> {
+    /// highest priority first, in order of subscription within a priority
+    handlers: Vec<Registered<T>>,
+    queue: VecDeque<T>,
 }
 
-impl<T> CopyingEventProducer<T> {
-    pub fn new() -> Self {
-        CopyingEventProducer {
-            handlers: HashMap::new(),
+/// A typed event bus. Events are queued as they're published, from any thread, and handed to
+/// the handlers when the owner calls `dispatch`, at a known point in the frame rather than in
+/// the middle of whatever published them.
+///
+/// The bus only keeps weak references to handlers, the `Subscription` returned when subscribing
+/// owns the handler and unsubscribes it when dropped, e.g. with the mod that subscribed.
+pub struct EventBus<T> {
+    inner: Arc<Mutex<BusInner<T>>>,
+}
+
+impl<T> Clone for EventBus<T> {
+    fn clone(&self) -> Self {
+        EventBus {
+            inner: self.inner.clone(),
         }
     }
-    pub fn create_handler<F: Fn(T) -> ()>(func: F) -> ArcEventHandler<T>
+}
+
+impl<T> Default for EventBus<T> {
+    fn default() -> Self {
+        EventBus {
+            inner: Arc::new(Mutex::new(BusInner {
+                handlers: Vec::new(),
+                queue: VecDeque::new(),
+            })),
+        }
+    }
+}
+
+impl<T> EventBus<T> {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    // a handler that panicked doesn't take the bus down with it
+    fn lock(&self) -> MutexGuard<'_, BusInner<T>> {
+        lock(&self.inner)
+    }
+
+    /// Handlers with a higher priority see each event first. Fails if the id is in use.
+    pub fn subscribe<F>(
+        &self,
+        id: &str,
+        priority: i32,
+        handler: F,
+    ) -> Result<Subscription<T>, String>
     where
-        F: 'static,
+        F: Fn(&T) + Send + Sync + 'static,
     {
-        Arc::new(Mutex::new(Box::new(func)))
+        let handler: Arc<EventHandler<T>> = Arc::new(handler);
+        let mut inner = self.lock();
+        inner.handlers.retain(|r| r.handler.strong_count() > 0);
+        if inner.handlers.iter().any(|r| r.id == id) {
+            return Err(format!("an event handler '{}' is already subscribed", id));
+        }
+        let i = inner
+            .handlers
+            .iter()
+            .position(|r| r.priority < priority)
+            .unwrap_or(inner.handlers.len());
+        inner.handlers.insert(
+            i,
+            Registered {
+                id: id.to_string(),
+                priority,
+                handler: Arc::downgrade(&handler),
+            },
+        );
+        Ok(Subscription {
+            id: id.to_string(),
+            bus: Arc::downgrade(&self.inner),
+            _handler: handler,
+        })
+    }
+
+    pub fn is_subscribed(&self, id: &str) -> bool {
+        self.lock()
+            .handlers
+            .iter()
+            .any(|r| r.id == id && r.handler.strong_count() > 0)
+    }
+
+    /// Queues the event for the next `dispatch`
+    pub fn publish(&self, event: T) {
+        self.lock().queue.push_back(event);
+    }
+
+    pub fn pending(&self) -> usize {
+        self.lock().queue.len()
+    }
+
+    /// Hands every queued event to every handler, in order, and returns how many events were
+    /// delivered. Events published by the handlers wait for the next call, and so do handlers
+    /// they subscribe, while handlers unsubscribed by an earlier one aren't called.
+    pub fn dispatch(&self) -> usize {
+        let (events, handlers) = {
+            let mut inner = self.lock();
+            inner.handlers.retain(|r| r.handler.strong_count() > 0);
+            let handlers = inner
+                .handlers
+                .iter()
+                .map(|r| r.handler.clone())
+                .collect::<Vec<_>>();
+            (inner.queue.drain(..).collect::<Vec<_>>(), handlers)
+        };
+        // the bus isn't locked while handlers run, they may publish or subscribe. Each handler is
+        // only upgraded as it's reached, so one dropped meanwhile isn't kept alive and called.
+        for event in events.iter() {
+            for handler in handlers.iter() {
+                if let Some(handler) = handler.upgrade() {
+                    handler(event);
+                }
+            }
+        }
+        events.len()
+    }
+}
+
+fn lock<T>(inner: &Mutex<BusInner<T>>) -> MutexGuard<'_, BusInner<T>> {
+    inner.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Keeps a handler subscribed to an `EventBus`, until dropped
+pub struct Subscription<T> {
+    id: String,
+    bus: Weak<Mutex<BusInner<T>>>,
+    _handler: Arc<EventHandler<T>>,
+}
+
+impl<T> Subscription<T> {
+    pub fn id(&self) -> &str {
+        &self.id
     }
 }
 
-impl<T> EventProducer<T> for CopyingEventProducer<T>
+impl<T> Drop for Subscription<T> {
+    fn drop(&mut self) {
+        if let Some(bus) = self.bus.upgrade() {
+            lock(&bus).handlers.retain(|r| r.id != self.id);
+        }
+    }
+}
+
+/// A broadcast queue of events, where each named reader keeps its own read cursor, so one
+/// consumer reading (or ignoring) events never hides them from another.
+///
+/// Events are retained until every registered reader has read them, or until they have
+/// outlived a frame boundary (see `end_frame`), whichever comes first. Readers are named
+/// rather than handed an id so that a mod can pick its cursor back up after a hot reload.
+pub struct EventChannel<T> {
+    events: VecDeque<T>,
+    /// sequence number of the event at the front of `events`
+    first_seq: u64,
+    /// sequence number of the first event published since the last frame boundary
+    frame_start_seq: u64,
+    /// next sequence number each reader will read
+    readers: HashMap<String, u64>,
+}
+
+impl<T> Default for EventChannel<T> {
+    fn default() -> Self {
+        EventChannel {
+            events: VecDeque::new(),
+            first_seq: 0,
+            frame_start_seq: 0,
+            readers: HashMap::new(),
+        }
+    }
+}
+
+impl<T> EventChannel<T>
 where
     T: Clone,
 {
-    fn add_handler(&mut self, id: String, handler: &ArcEventHandler<T>) {
-        let wh: WeakEventHandler<T> = Arc::downgrade(handler);
-        self.handlers.entry(id).or_insert(wh);
+    pub fn new() -> Self {
+        Default::default()
     }
 
-    fn remove_handler(&mut self, id: &str) {
-        self.handlers.remove(id);
+    fn next_seq(&self) -> u64 {
+        self.first_seq + self.events.len() as u64
     }
 
-    fn publish(&mut self, event: T) {
-        for (_id, handler) in self.handlers.iter() {
-            match handler.upgrade() {
-                Some(a) => {
-                    (*a.lock().unwrap())(event.clone());
-                }
-                None => {} //arc has been dropped, TODO: notify or log?
-            }
+    /// Register a reader, which will see every event published from now on.
+    /// Registering an existing reader keeps its cursor.
+    pub fn register_reader(&mut self, reader: &str) {
+        let next = self.next_seq();
+        self.readers.entry(reader.to_string()).or_insert(next);
+    }
+
+    pub fn unregister_reader(&mut self, reader: &str) {
+        self.readers.remove(reader);
+        self.prune();
+    }
+
+    pub fn publish(&mut self, event: T) {
+        self.events.push_back(event);
+    }
+
+    /// Returns the events this reader has not yet seen and advances its cursor.
+    /// An unregistered reader is registered, and sees everything still retained.
+    pub fn read(&mut self, reader: &str) -> Vec<T> {
+        let first_seq = self.first_seq;
+        let cursor = *self.readers.entry(reader.to_string()).or_insert(first_seq);
+        let skip = cursor.saturating_sub(first_seq) as usize;
+        let events = self.events.iter().skip(skip).cloned().collect::<Vec<_>>();
+
+        let next = self.next_seq();
+        self.readers.insert(reader.to_string(), next);
+        self.prune();
+        events
+    }
+
+    pub fn has_unread(&self, reader: &str) -> bool {
+        match self.readers.get(reader) {
+            Some(cursor) => *cursor < self.next_seq(),
+            None => !self.events.is_empty(),
+        }
+    }
+
+    /// Mark a frame boundary. Events published before the previous boundary are dropped,
+    /// even if a reader has not seen them yet, so that a reader which stops reading (e.g. an
+    /// unloaded mod) cannot grow the queue forever.
+    pub fn end_frame(&mut self) {
+        self.drop_until(self.frame_start_seq);
+        self.frame_start_seq = self.next_seq();
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    // drop events every registered reader has already read
+    fn prune(&mut self) {
+        if let Some(min_cursor) = self.readers.values().min().cloned() {
+            self.drop_until(min_cursor);
+        }
+    }
+
+    fn drop_until(&mut self, seq: u64) {
+        while self.first_seq < seq && self.events.pop_front().is_some() {
+            self.first_seq += 1;
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn bus_delivers_queued_events_by_priority() {
+        let bus = EventBus::<u32>::new();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let (low, high) = (seen.clone(), seen.clone());
+        let _low = bus
+            .subscribe("low", 0, move |e| low.lock().unwrap().push(("low", *e)))
+            .unwrap();
+        let _high = bus
+            .subscribe("high", 10, move |e| high.lock().unwrap().push(("high", *e)))
+            .unwrap();
+        assert!(bus.subscribe("low", 5, |_| {}).is_err());
+
+        bus.publish(1);
+        bus.publish(2);
+        assert!(seen.lock().unwrap().is_empty());
+        assert_eq!(bus.dispatch(), 2);
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![("high", 1), ("low", 1), ("high", 2), ("low", 2)]
+        );
+        assert_eq!(bus.pending(), 0);
+    }
+
+    #[test]
+    fn dropping_a_subscription_unsubscribes() {
+        let bus = EventBus::<u32>::new();
+        let count = Arc::new(AtomicUsize::new(0));
+        let counted = count.clone();
+        let subscription = bus
+            .subscribe("counter", 0, move |_| {
+                counted.fetch_add(1, Ordering::SeqCst);
+            })
+            .unwrap();
+        bus.publish(1);
+        bus.dispatch();
+        drop(subscription);
+        assert!(!bus.is_subscribed("counter"));
+        bus.publish(2);
+        bus.dispatch();
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        // the id is free again
+        assert!(bus.subscribe("counter", 0, |_| {}).is_ok());
+    }
+
+    #[test]
+    fn handlers_unsubscribed_during_dispatch_are_not_called() {
+        let bus = EventBus::<u32>::new();
+        let count = Arc::new(AtomicUsize::new(0));
+        let counted = count.clone();
+        let victim = bus
+            .subscribe("victim", 0, move |_| {
+                counted.fetch_add(1, Ordering::SeqCst);
+            })
+            .unwrap();
+        let victim = Arc::new(Mutex::new(Some(victim)));
+        let dropped = victim.clone();
+        let _dropper = bus
+            .subscribe("dropper", 10, move |_| {
+                dropped.lock().unwrap().take();
+            })
+            .unwrap();
+
+        bus.publish(1);
+        bus.publish(2);
+        assert_eq!(bus.dispatch(), 2);
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+        assert!(!bus.is_subscribed("victim"));
+        assert!(victim.lock().unwrap().is_none());
+    }
+
+    #[test]
+    fn bus_is_shared_across_threads() {
+        let bus = EventBus::<u32>::new();
+        let sum = Arc::new(AtomicUsize::new(0));
+        let total = sum.clone();
+        let _sum = bus
+            .subscribe("sum", 0, move |e| {
+                total.fetch_add(*e as usize, Ordering::SeqCst);
+            })
+            .unwrap();
+        let publishers = (1..=4)
+            .map(|i| {
+                let bus = bus.clone();
+                std::thread::spawn(move || bus.publish(i))
+            })
+            .collect::<Vec<_>>();
+        for publisher in publishers {
+            publisher.join().unwrap();
+        }
+        // a handler publishing waits for the next dispatch
+        let again = bus.clone();
+        let _echo = bus
+            .subscribe("echo", -1, move |e| {
+                if *e == 4 {
+                    again.publish(100);
+                }
+            })
+            .unwrap();
+        assert_eq!(bus.dispatch(), 4);
+        assert_eq!(sum.load(Ordering::SeqCst), 10);
+        assert_eq!(bus.dispatch(), 1);
+        assert_eq!(sum.load(Ordering::SeqCst), 110);
+    }
+
+    #[test]
+    fn each_reader_sees_every_event() {
+        let mut channel = EventChannel::<u32>::new();
+        channel.register_reader("a");
+        channel.register_reader("b");
+
+        channel.publish(1);
+        channel.publish(2);
+        assert_eq!(channel.read("a"), vec![1, 2]);
+
+        // "b" has not read yet, so nothing may be dropped
+        assert_eq!(channel.len(), 2);
+        channel.publish(3);
+        assert_eq!(channel.read("b"), vec![1, 2, 3]);
+        assert_eq!(channel.read("a"), vec![3]);
+        assert!(channel.is_empty());
+    }
+
+    #[test]
+    fn late_reader_only_sees_new_events() {
+        let mut channel = EventChannel::<u32>::new();
+        channel.register_reader("a");
+        channel.publish(1);
+        channel.register_reader("b");
+        channel.publish(2);
+        assert!(channel.has_unread("b"));
+        assert_eq!(channel.read("b"), vec![2]);
+        assert!(!channel.has_unread("b"));
+        assert_eq!(channel.read("a"), vec![1, 2]);
+    }
+
+    #[test]
+    fn events_outlive_only_one_frame_boundary() {
+        let mut channel = EventChannel::<u32>::new();
+        channel.register_reader("stalled");
+        channel.publish(1);
+        channel.end_frame();
+        channel.publish(2);
+        assert_eq!(channel.len(), 2);
+        channel.end_frame();
+        assert_eq!(channel.read("stalled"), vec![2]);
+    }
+
+    #[test]
+    fn unregistering_releases_events() {
+        let mut channel = EventChannel::<u32>::new();
+        channel.register_reader("a");
+        channel.register_reader("b");
+        channel.publish(1);
+        channel.read("a");
+        channel.unregister_reader("b");
+        assert!(channel.is_empty());
+    }
+}
@@ -0,0 +1,735 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use sdl2::keyboard::Keycode;
+
+use crate::input::events::{InputEvent, JoyAxis, JoyButton, MouseButton};
+
+//
+// Action mapping: physical inputs (keys, mouse buttons and axes, joystick buttons and axes) are
+// bound to named actions, so gameplay asks "is `jump` pressed?" rather than matching keycodes.
+//
+// Bindings are declared in a config file, one action per line:
+//
+//   # kind   name         = binding, binding, ...
+//   digital  jump         = key:Space, joy:South
+//   digital  quick_save   = ctrl+key:S
+//   analog   move_x       = key:D, key:A*-1, joy_axis:LeftStickX
+//   analog   look_x       = mouse_axis:X
+//
+// A binding is `[ctrl+][shift+][alt+][gui+]<device>:<name>[*<scale>]`, where device is one of
+// `key`, `mouse`, `mouse_axis`, `mouse_wheel`, `joy` or `joy_axis`. Of the bindings on one input
+// whose modifiers are held, only the most specific apply, so `ctrl+key:S` doesn't also press `key:S`.
+//
+
+/// Magnitude at which an input counts as pressed, e.g. a stick bound to a digital action
+const PRESS_THRESHOLD: f32 = 0.5;
+
+const JOY_BUTTONS: [JoyButton; 20] = [
+    JoyButton::South,
+    JoyButton::East,
+    JoyButton::North,
+    JoyButton::West,
+    JoyButton::C,
+    JoyButton::Z,
+    JoyButton::LeftTrigger,
+    JoyButton::LeftTrigger2,
+    JoyButton::RightTrigger,
+    JoyButton::RightTrigger2,
+    JoyButton::Select,
+    JoyButton::Start,
+    JoyButton::Mode,
+    JoyButton::LeftThumb,
+    JoyButton::RightThumb,
+    JoyButton::DPadUp,
+    JoyButton::DPadDown,
+    JoyButton::DPadLeft,
+    JoyButton::DPadRight,
+    JoyButton::Unknown,
+];
+
+pub(crate) const JOY_AXES: [JoyAxis; 9] = [
+    JoyAxis::LeftStickX,
+    JoyAxis::LeftStickY,
+    JoyAxis::LeftZ,
+    JoyAxis::RightStickX,
+    JoyAxis::RightStickY,
+    JoyAxis::RightZ,
+    JoyAxis::DPadX,
+    JoyAxis::DPadY,
+    JoyAxis::Unknown,
+];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum MouseAxis {
+    X,
+    Y,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PhysicalInput {
+    Key(u32),
+    MouseButton(MouseButton),
+    MouseAxis(MouseAxis),
+    MouseWheel(MouseAxis),
+    JoyButton(JoyButton),
+    JoyAxis(JoyAxis),
+}
+
+impl PhysicalInput {
+    /// Relative inputs report motion since the last frame, rather than a held value
+    pub fn is_relative(&self) -> bool {
+        matches!(
+            self,
+            PhysicalInput::MouseAxis(_) | PhysicalInput::MouseWheel(_)
+        )
+    }
+
+    fn is_modifier_key(&self) -> bool {
+        match self {
+            PhysicalInput::Key(code) => {
+                MODIFIER_KEYS.iter().any(|(k, _)| *k as i32 as u32 == *code)
+            }
+            _ => false,
+        }
+    }
+
+    /// The physical inputs and values carried by an event, if any
+    pub fn from_event(event: &InputEvent) -> Vec<(PhysicalInput, f32)> {
+        match event {
+            InputEvent::KeyDown(_, code) => vec![(PhysicalInput::Key(*code), 1.0)],
+            InputEvent::KeyUp(_, code) => vec![(PhysicalInput::Key(*code), 0.0)],
+            InputEvent::MouseDown(_, b) => vec![(PhysicalInput::MouseButton(b.clone()), 1.0)],
+            InputEvent::MouseUp(_, b) => vec![(PhysicalInput::MouseButton(b.clone()), 0.0)],
+            InputEvent::MouseMotion(_, x, y) => vec![
+                (PhysicalInput::MouseAxis(MouseAxis::X), *x),
+                (PhysicalInput::MouseAxis(MouseAxis::Y), *y),
+            ],
+            InputEvent::MouseWheel(_, d) => vec![
+                (PhysicalInput::MouseWheel(MouseAxis::X), d.delta_x as f32),
+                (PhysicalInput::MouseWheel(MouseAxis::Y), d.delta_y as f32),
+            ],
+            InputEvent::JoyButtonDown(_, _, b) => vec![(PhysicalInput::JoyButton(b.clone()), 1.0)],
+            InputEvent::JoyButtonUp(_, _, b) => vec![(PhysicalInput::JoyButton(b.clone()), 0.0)],
+            InputEvent::JoyButtonChanged(_, _, b, v) => {
+                vec![(PhysicalInput::JoyButton(b.clone()), *v)]
+            }
+            InputEvent::JoyAxisChanged(_, _, a, v) => vec![(PhysicalInput::JoyAxis(a.clone()), *v)],
+            _ => vec![],
+        }
+    }
+}
+
+impl fmt::Display for PhysicalInput {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PhysicalInput::Key(code) => match Keycode::from_i32(*code as i32) {
+                Some(key) => write!(f, "key:{}", key.name()),
+                None => write!(f, "key:{}", code),
+            },
+            PhysicalInput::MouseButton(MouseButton::Other(n)) => write!(f, "mouse:{}", n),
+            PhysicalInput::MouseButton(b) => write!(f, "mouse:{:?}", b),
+            PhysicalInput::MouseAxis(a) => write!(f, "mouse_axis:{:?}", a),
+            PhysicalInput::MouseWheel(a) => write!(f, "mouse_wheel:{:?}", a),
+            PhysicalInput::JoyButton(b) => write!(f, "joy:{:?}", b),
+            PhysicalInput::JoyAxis(a) => write!(f, "joy_axis:{:?}", a),
+        }
+    }
+}
+
+impl FromStr for PhysicalInput {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, ':');
+        let device = parts.next().unwrap_or("").trim();
+        let name = parts
+            .next()
+            .ok_or_else(|| format!("expected <device>:<name>, got '{}'", s))?
+            .trim();
+        let unknown = || format!("unknown {} '{}'", device, name);
+
+        let mouse_axis = |name: &str| match name {
+            "X" | "x" => Some(MouseAxis::X),
+            "Y" | "y" => Some(MouseAxis::Y),
+            _ => None,
+        };
+
+        match device {
+            "key" => Keycode::from_name(name)
+                .map(|k| PhysicalInput::Key(k as i32 as u32))
+                .or_else(|| name.parse::<u32>().ok().map(PhysicalInput::Key))
+                .ok_or_else(unknown),
+            "mouse" => match name {
+                "Left" => Ok(PhysicalInput::MouseButton(MouseButton::Left)),
+                "Middle" => Ok(PhysicalInput::MouseButton(MouseButton::Middle)),
+                "Right" => Ok(PhysicalInput::MouseButton(MouseButton::Right)),
+                other => other
+                    .parse::<u8>()
+                    .map(|n| PhysicalInput::MouseButton(MouseButton::Other(n)))
+                    .map_err(|_| unknown()),
+            },
+            "mouse_axis" => mouse_axis(name)
+                .map(PhysicalInput::MouseAxis)
+                .ok_or_else(unknown),
+            "mouse_wheel" => mouse_axis(name)
+                .map(PhysicalInput::MouseWheel)
+                .ok_or_else(unknown),
+            "joy" => JOY_BUTTONS
+                .iter()
+                .find(|b| format!("{:?}", b) == name)
+                .map(|b| PhysicalInput::JoyButton(b.clone()))
+                .ok_or_else(unknown),
+            "joy_axis" => JOY_AXES
+                .iter()
+                .find(|a| format!("{:?}", a) == name)
+                .map(|a| PhysicalInput::JoyAxis(a.clone()))
+                .ok_or_else(unknown),
+            _ => Err(format!("unknown input device '{}'", device)),
+        }
+    }
+}
+
+const MODIFIER_KEYS: [(Keycode, Modifier); 8] = [
+    (Keycode::LCtrl, Modifier::Ctrl),
+    (Keycode::RCtrl, Modifier::Ctrl),
+    (Keycode::LShift, Modifier::Shift),
+    (Keycode::RShift, Modifier::Shift),
+    (Keycode::LAlt, Modifier::Alt),
+    (Keycode::RAlt, Modifier::Alt),
+    (Keycode::LGui, Modifier::Gui),
+    (Keycode::RGui, Modifier::Gui),
+];
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Modifier {
+    Ctrl,
+    Shift,
+    Alt,
+    Gui,
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Modifiers {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+    pub gui: bool,
+}
+
+impl Modifiers {
+    /// True if every modifier in `required` is held in `self`
+    pub fn satisfies(&self, required: &Modifiers) -> bool {
+        (self.ctrl || !required.ctrl)
+            && (self.shift || !required.shift)
+            && (self.alt || !required.alt)
+            && (self.gui || !required.gui)
+    }
+
+    /// True if `self` has every modifier in `other`, and more
+    pub fn is_more_specific_than(&self, other: &Modifiers) -> bool {
+        self.satisfies(other) && self != other
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Binding {
+    pub input: PhysicalInput,
+    /// Modifiers that must be held for this binding to apply
+    pub modifiers: Modifiers,
+    /// Multiplier applied to the input's value, e.g. -1.0 to bind `A` to the negative x axis
+    pub scale: f32,
+}
+
+impl Binding {
+    pub fn new(input: PhysicalInput) -> Self {
+        Binding {
+            input,
+            modifiers: Default::default(),
+            scale: 1.0,
+        }
+    }
+
+    pub fn with_modifiers(mut self, modifiers: Modifiers) -> Self {
+        self.modifiers = modifiers;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: f32) -> Self {
+        self.scale = scale;
+        self
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.modifiers.ctrl {
+            write!(f, "ctrl+")?;
+        }
+        if self.modifiers.shift {
+            write!(f, "shift+")?;
+        }
+        if self.modifiers.alt {
+            write!(f, "alt+")?;
+        }
+        if self.modifiers.gui {
+            write!(f, "gui+")?;
+        }
+        write!(f, "{}", self.input)?;
+        if (self.scale - 1.0).abs() > f32::EPSILON {
+            write!(f, "*{}", self.scale)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Binding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rest = s.trim();
+        let mut modifiers = Modifiers::default();
+        loop {
+            let lower = rest.to_lowercase();
+            if lower.starts_with("ctrl+") {
+                modifiers.ctrl = true;
+            } else if lower.starts_with("shift+") {
+                modifiers.shift = true;
+            } else if lower.starts_with("alt+") {
+                modifiers.alt = true;
+            } else if lower.starts_with("gui+") {
+                modifiers.gui = true;
+            } else {
+                break;
+            }
+            rest = &rest[rest.find('+').unwrap() + 1..];
+        }
+
+        // an optional trailing `*<scale>`; `key:*` on its own is the asterisk key
+        let (input, scale) = match rest.rfind('*') {
+            Some(i) => match rest[i + 1..].trim().parse::<f32>() {
+                Ok(scale) => (&rest[..i], scale),
+                Err(_) => (rest, 1.0),
+            },
+            None => (rest, 1.0),
+        };
+
+        Ok(Binding {
+            input: input.parse()?,
+            modifiers,
+            scale,
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ActionKind {
+    /// On or off, e.g. jump
+    Digital,
+    /// A value, e.g. a movement axis. Held inputs sum to -1.0..=1.0, relative inputs (mouse
+    /// motion) are added on top as-is.
+    Analog,
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct ActionState {
+    pub value: f32,
+    pub pressed: bool,
+    /// pressed this frame
+    pub just_pressed: bool,
+    /// released this frame
+    pub just_released: bool,
+}
+
+struct Action {
+    kind: ActionKind,
+    bindings: Vec<Binding>,
+    state: ActionState,
+}
+
+/// A pending runtime rebind: the next input pressed is bound to `action`
+struct Capture {
+    action: String,
+    replace: bool,
+}
+
+#[derive(Default)]
+pub struct ActionMap {
+    actions: HashMap<String, Action>,
+    /// current value of every physical input seen, held buttons are 1.0
+    inputs: HashMap<PhysicalInput, f32>,
+    capture: Option<Capture>,
+}
+
+impl ActionMap {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let config = fs::read_to_string(path)?;
+        Self::parse(&config)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        fs::write(path, self.to_config())?;
+        Ok(())
+    }
+
+    pub fn parse(config: &str) -> Result<Self, Box<dyn Error>> {
+        let mut map = ActionMap::new();
+        for (n, line) in config.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let err = |msg: String| format!("line {}: {}", n + 1, msg);
+
+            let mut parts = line.splitn(2, char::is_whitespace);
+            let kind = match parts.next().unwrap_or("") {
+                "digital" => ActionKind::Digital,
+                "analog" => ActionKind::Analog,
+                other => return Err(err(format!("unknown action kind '{}'", other)).into()),
+            };
+            let mut parts = parts.next().unwrap_or("").splitn(2, '=');
+            let name = parts.next().unwrap_or("").trim();
+            let bindings = parts
+                .next()
+                .ok_or_else(|| err(format!("expected '=' after '{}'", name)))?;
+            if name.is_empty() {
+                return Err(err("missing action name".to_string()).into());
+            }
+
+            map.define(name, kind);
+            for binding in bindings.split(',').map(str::trim).filter(|b| !b.is_empty()) {
+                map.bind(name, binding.parse().map_err(err)?)?;
+            }
+        }
+        Ok(map)
+    }
+
+    /// Serialize to the config format read by `parse`, actions sorted by name
+    pub fn to_config(&self) -> String {
+        let mut names = self.actions.keys().collect::<Vec<_>>();
+        names.sort();
+        names
+            .iter()
+            .map(|name| {
+                let action = &self.actions[*name];
+                let kind = match action.kind {
+                    ActionKind::Digital => "digital",
+                    ActionKind::Analog => "analog",
+                };
+                let bindings = action
+                    .bindings
+                    .iter()
+                    .map(|b| b.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("{} {} = {}\n", kind, name, bindings)
+            })
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    /// Declare an action. Redefining an action keeps its bindings but changes its kind.
+    pub fn define(&mut self, action: &str, kind: ActionKind) {
+        self.actions
+            .entry(action.to_string())
+            .and_modify(|a| a.kind = kind)
+            .or_insert(Action {
+                kind,
+                bindings: Vec::new(),
+                state: Default::default(),
+            });
+    }
+
+    pub fn bind(&mut self, action: &str, binding: Binding) -> Result<(), Box<dyn Error>> {
+        let action = self
+            .actions
+            .get_mut(action)
+            .ok_or_else(|| format!("no such action '{}'", action))?;
+        if !action.bindings.contains(&binding) {
+            action.bindings.push(binding);
+        }
+        Ok(())
+    }
+
+    pub fn unbind(&mut self, action: &str, binding: &Binding) {
+        if let Some(action) = self.actions.get_mut(action) {
+            action.bindings.retain(|b| b != binding);
+        }
+    }
+
+    pub fn clear_bindings(&mut self, action: &str) {
+        if let Some(action) = self.actions.get_mut(action) {
+            action.bindings.clear();
+        }
+    }
+
+    pub fn get_bindings(&self, action: &str) -> &[Binding] {
+        self.actions
+            .get(action)
+            .map(|a| a.bindings.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Bind the next button pressed or axis pushed (with any modifiers held) to `action`,
+    /// either alongside or in place of its existing bindings.
+    pub fn capture_next_input(&mut self, action: &str, replace: bool) {
+        self.capture = Some(Capture {
+            action: action.to_string(),
+            replace,
+        });
+    }
+
+    pub fn is_capturing(&self) -> bool {
+        self.capture.is_some()
+    }
+
+    pub fn get_state(&self, action: &str) -> ActionState {
+        self.actions
+            .get(action)
+            .map(|a| a.state)
+            .unwrap_or_default()
+    }
+
+    pub fn pressed(&self, action: &str) -> bool {
+        self.get_state(action).pressed
+    }
+
+    pub fn just_pressed(&self, action: &str) -> bool {
+        self.get_state(action).just_pressed
+    }
+
+    pub fn just_released(&self, action: &str) -> bool {
+        self.get_state(action).just_released
+    }
+
+    pub fn value(&self, action: &str) -> f32 {
+        self.get_state(action).value
+    }
+
+    /// Apply a frame's worth of input events, and recompute every action's state.
+    /// Call once per frame, even when there are no events, so `just_*` flags are cleared.
+    pub fn update(&mut self, events: &[InputEvent]) {
+        // relative inputs only hold the motion of the frame they happened in
+        self.inputs.retain(|input, _| !input.is_relative());
+
+        for event in events {
+            if let InputEvent::LostFocus(_) = event {
+                // we won't see the releases of anything held while unfocused
+                self.inputs.clear();
+            }
+            for (input, value) in PhysicalInput::from_event(event) {
+                self.try_capture(&input, value);
+                if input.is_relative() {
+                    *self.inputs.entry(input).or_insert(0.0) += value;
+                } else {
+                    self.inputs.insert(input, value);
+                }
+            }
+        }
+
+        self.evaluate();
+    }
+
+    fn held_modifiers(&self) -> Modifiers {
+        let mut modifiers = Modifiers::default();
+        for (key, modifier) in MODIFIER_KEYS.iter() {
+            let input = PhysicalInput::Key(*key as i32 as u32);
+            if self.inputs.get(&input).cloned().unwrap_or(0.0) >= PRESS_THRESHOLD {
+                match modifier {
+                    Modifier::Ctrl => modifiers.ctrl = true,
+                    Modifier::Shift => modifiers.shift = true,
+                    Modifier::Alt => modifiers.alt = true,
+                    Modifier::Gui => modifiers.gui = true,
+                }
+            }
+        }
+        modifiers
+    }
+
+    fn try_capture(&mut self, input: &PhysicalInput, value: f32) {
+        if input.is_relative() || input.is_modifier_key() || value.abs() < PRESS_THRESHOLD {
+            return;
+        }
+        if let Some(capture) = self.capture.take() {
+            let binding = Binding::new(input.clone())
+                .with_modifiers(self.held_modifiers())
+                .with_scale(if value < 0.0 { -1.0 } else { 1.0 });
+            if capture.replace {
+                self.clear_bindings(&capture.action);
+            }
+            if let Err(err) = self.bind(&capture.action, binding) {
+                println!("ActionMap: unable to rebind: {}", err);
+            }
+        }
+    }
+
+    fn evaluate(&mut self) {
+        let modifiers = self.held_modifiers();
+        // the modifiers of every binding that could apply, by input
+        let mut satisfied: HashMap<PhysicalInput, Vec<Modifiers>> = HashMap::new();
+        for binding in self.actions.values().flat_map(|a| a.bindings.iter()) {
+            if modifiers.satisfies(&binding.modifiers) {
+                satisfied
+                    .entry(binding.input.clone())
+                    .or_default()
+                    .push(binding.modifiers);
+            }
+        }
+        // a binding is shadowed by one on the same input needing more of the held modifiers
+        let applies = |b: &Binding| {
+            modifiers.satisfies(&b.modifiers)
+                && !satisfied[&b.input]
+                    .iter()
+                    .any(|other| other.is_more_specific_than(&b.modifiers))
+        };
+
+        let inputs = &self.inputs;
+        for action in self.actions.values_mut() {
+            let mut held = 0.0;
+            let mut relative = 0.0;
+            let mut any_pressed = false;
+            for binding in action.bindings.iter().filter(|b| applies(b)) {
+                let v = inputs.get(&binding.input).cloned().unwrap_or(0.0) * binding.scale;
+                if binding.input.is_relative() {
+                    relative += v;
+                } else {
+                    held += v;
+                    any_pressed |= v.abs() >= PRESS_THRESHOLD;
+                }
+            }
+
+            let (value, pressed) = match action.kind {
+                ActionKind::Digital => {
+                    let pressed = any_pressed || relative.abs() >= PRESS_THRESHOLD;
+                    (if pressed { 1.0 } else { 0.0 }, pressed)
+                }
+                ActionKind::Analog => {
+                    let value = held.clamp(-1.0, 1.0) + relative;
+                    (value, value.abs() >= PRESS_THRESHOLD)
+                }
+            };
+
+            let was_pressed = action.state.pressed;
+            action.state = ActionState {
+                value,
+                pressed,
+                just_pressed: pressed && !was_pressed,
+                just_released: !pressed && was_pressed,
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: u64 = 0;
+
+    fn map() -> ActionMap {
+        ActionMap::parse(
+            "
+            # comment
+            digital jump = joy:South, mouse:Right
+            digital save = ctrl+key:S
+            digital move_backward = key:S
+            analog move_x = joy_axis:LeftStickX, mouse_wheel:X*-1
+            analog look_x = mouse_axis:X*0.5
+            ",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn parse_and_round_trip() {
+        let map = map();
+        assert_eq!(map.get_bindings("jump").len(), 2);
+        assert_eq!(map.get_bindings("look_x")[0].scale, 0.5);
+        assert!(map.get_bindings("save")[0].modifiers.ctrl);
+
+        let reparsed = ActionMap::parse(&map.to_config()).unwrap();
+        assert_eq!(reparsed.to_config(), map.to_config());
+    }
+
+    #[test]
+    fn parse_errors_name_the_line() {
+        let err = ActionMap::parse("digital a = joy:South\nanalog b = joy_axis:Sideways")
+            .err()
+            .unwrap();
+        assert!(err.to_string().starts_with("line 2"));
+    }
+
+    #[test]
+    fn digital_edges() {
+        let mut map = map();
+        map.update(&[InputEvent::JoyButtonDown(ID, 0, JoyButton::South)]);
+        assert!(map.pressed("jump") && map.just_pressed("jump"));
+        map.update(&[]);
+        assert!(map.pressed("jump") && !map.just_pressed("jump"));
+
+        // a second binding held, then the first released: still pressed
+        map.update(&[InputEvent::MouseDown(ID, MouseButton::Right)]);
+        map.update(&[InputEvent::JoyButtonUp(ID, 0, JoyButton::South)]);
+        assert!(map.pressed("jump"));
+        map.update(&[InputEvent::MouseUp(ID, MouseButton::Right)]);
+        assert!(!map.pressed("jump") && map.just_released("jump"));
+    }
+
+    #[test]
+    fn analog_values() {
+        let mut map = map();
+        map.update(&[InputEvent::JoyAxisChanged(ID, 0, JoyAxis::LeftStickX, 0.25)]);
+        assert_eq!(map.value("move_x"), 0.25);
+        assert!(!map.pressed("move_x"));
+
+        map.update(&[InputEvent::MouseMotion(ID, 10.0, 0.0)]);
+        assert_eq!(map.value("look_x"), 5.0);
+
+        // mouse motion doesn't carry over into the next frame
+        map.update(&[]);
+        assert_eq!(map.value("look_x"), 0.0);
+        assert_eq!(map.value("move_x"), 0.25);
+    }
+
+    #[test]
+    fn modified_bindings_shadow_plain_ones() {
+        let key = |k: Keycode| k as i32 as u32;
+        let mut map = map();
+        map.update(&[
+            InputEvent::KeyDown(ID, key(Keycode::LCtrl)),
+            InputEvent::KeyDown(ID, key(Keycode::S)),
+        ]);
+        assert!(map.pressed("save"));
+        assert!(!map.pressed("move_backward"));
+
+        map.update(&[InputEvent::KeyUp(ID, key(Keycode::LCtrl))]);
+        assert!(!map.pressed("save") && map.pressed("move_backward"));
+
+        // modifiers nothing on the input is bound with don't get in the way
+        map.update(&[InputEvent::KeyDown(ID, key(Keycode::LShift))]);
+        assert!(map.pressed("move_backward"));
+    }
+
+    #[test]
+    fn capture_rebinds() {
+        let mut map = map();
+        map.capture_next_input("jump", true);
+        map.update(&[InputEvent::JoyAxisChanged(ID, 0, JoyAxis::RightZ, -0.9)]);
+        assert!(!map.is_capturing());
+        assert_eq!(
+            map.get_bindings("jump"),
+            &[Binding::new(PhysicalInput::JoyAxis(JoyAxis::RightZ)).with_scale(-1.0)]
+        );
+    }
+}
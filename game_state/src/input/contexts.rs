@@ -0,0 +1,146 @@
+use std::collections::HashSet;
+
+//
+// Input contexts: mods push a context (e.g. console > menu > gameplay) declaring the actions it
+// handles. Each action is delivered to the highest context declaring it, so a menu can take over
+// `pause` from gameplay. A blocking context also hides every action and event from the contexts
+// below it, so typing into a console doesn't move the camera.
+//
+
+#[derive(Debug, Clone)]
+pub struct InputContext {
+    name: String,
+    actions: HashSet<String>,
+    blocking: bool,
+}
+
+impl InputContext {
+    pub fn new(name: &str, actions: &[&str]) -> Self {
+        InputContext {
+            name: name.to_string(),
+            actions: actions.iter().map(|a| a.to_string()).collect(),
+            blocking: false,
+        }
+    }
+
+    /// Contexts below this one receive nothing while it is on the stack
+    pub fn blocking(mut self) -> Self {
+        self.blocking = true;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn handles(&self, action: &str) -> bool {
+        self.actions.contains(action)
+    }
+
+    pub fn is_blocking(&self) -> bool {
+        self.blocking
+    }
+}
+
+/// The last context pushed is the highest
+#[derive(Default)]
+pub struct ContextStack {
+    contexts: Vec<InputContext>,
+}
+
+impl ContextStack {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Pushes the context on top, replacing any context with the same name
+    pub fn push(&mut self, context: InputContext) {
+        self.pop(context.name());
+        self.contexts.push(context);
+    }
+
+    /// Removes the named context wherever it is in the stack
+    pub fn pop(&mut self, name: &str) -> Option<InputContext> {
+        let i = self.contexts.iter().position(|c| c.name() == name)?;
+        Some(self.contexts.remove(i))
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.contexts.iter().any(|c| c.name() == name)
+    }
+
+    pub fn top(&self) -> Option<&InputContext> {
+        self.contexts.last()
+    }
+
+    /// The context an action is delivered to, if any
+    pub fn owner(&self, action: &str) -> Option<&str> {
+        for context in self.contexts.iter().rev() {
+            if context.handles(action) {
+                return Some(context.name());
+            }
+            if context.is_blocking() {
+                break;
+            }
+        }
+        None
+    }
+
+    /// Whether the named context is on the stack and not under a blocking context
+    pub fn receives_events(&self, name: &str) -> bool {
+        for context in self.contexts.iter().rev() {
+            if context.name() == name {
+                return true;
+            }
+            if context.is_blocking() {
+                break;
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stack() -> ContextStack {
+        let mut stack = ContextStack::new();
+        stack.push(InputContext::new(
+            "gameplay",
+            &["pause", "jump", "fullscreen"],
+        ));
+        stack.push(InputContext::new("menu", &["pause", "select"]));
+        stack
+    }
+
+    #[test]
+    fn higher_contexts_consume_first() {
+        let stack = stack();
+        assert_eq!(stack.owner("pause"), Some("menu"));
+        assert_eq!(stack.owner("jump"), Some("gameplay"));
+        assert_eq!(stack.owner("unbound"), None);
+        assert!(stack.receives_events("gameplay"));
+    }
+
+    #[test]
+    fn blocking_contexts_hide_lower_ones() {
+        let mut stack = stack();
+        stack.push(InputContext::new("console", &["toggle_console"]).blocking());
+        assert_eq!(stack.owner("jump"), None);
+        assert!(!stack.receives_events("menu"));
+        assert!(stack.receives_events("console"));
+
+        assert!(stack.pop("console").is_some());
+        assert_eq!(stack.owner("jump"), Some("gameplay"));
+    }
+
+    #[test]
+    fn pushing_again_moves_to_top() {
+        let mut stack = stack();
+        stack.push(InputContext::new("gameplay", &["pause"]));
+        assert_eq!(stack.top().map(|c| c.name()), Some("gameplay"));
+        assert_eq!(stack.owner("pause"), Some("gameplay"));
+        assert_eq!(stack.owner("select"), Some("menu"));
+    }
+}
@@ -1,7 +1,7 @@
 use crate::input::screen::{DeltaVector, ScreenPoint};
 use crate::Identity;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum MouseButton {
     Left,
     Middle,
@@ -11,7 +11,7 @@ pub enum MouseButton {
 
 pub type DeviceId = usize;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum JoyAxis {
     LeftStickX,
     LeftStickY,
@@ -24,7 +24,7 @@ pub enum JoyAxis {
     Unknown,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum JoyButton {
     South,
     East,
@@ -48,6 +48,9 @@ pub enum JoyButton {
     Unknown,
 }
 
+/// The first Identity of an event is always the window it is for. Window, keyboard and mouse
+/// events carry the window they happened in, controller events the window with keyboard focus,
+/// whichever source sent them.
 #[derive(Debug, Clone)]
 pub enum InputEvent {
     KeyDown(Identity, u32),
@@ -56,20 +59,32 @@ pub enum InputEvent {
     MouseDown(Identity, MouseButton),
     MouseUp(Identity, MouseButton),
     MouseMove(Identity, ScreenPoint),
+    /// Relative motion, still reported while the cursor is grabbed. Scaled by the mouse
+    /// input profile once sent.
+    MouseMotion(Identity, f32, f32),
     MouseWheel(Identity, DeltaVector),
 
     MouseEntered(Identity),
     MouseLeft(Identity),
 
+    /// Committed UTF-8 text, sent while text input is started for the window (see InputAccess)
+    TextInput(Identity, String),
+    /// IME composition in progress: the composed text, then the cursor start and selection
+    /// length in characters
+    TextEditing(Identity, String, i32, i32),
+
     JoyAxisChanged(Identity, DeviceId, JoyAxis, f32),
     JoyButtonDown(Identity, DeviceId, JoyButton),
     JoyButtonRepeated(Identity, DeviceId, JoyButton),
     JoyButtonChanged(Identity, DeviceId, JoyButton, f32),
     JoyButtonUp(Identity, DeviceId, JoyButton),
-    JoyConnected(Identity, DeviceId),
+    /// Carries the controller GUID, which selects its input profile
+    JoyConnected(Identity, DeviceId, String),
     JoyDisconnected(Identity, DeviceId),
     JoyEventDropped(Identity, DeviceId),
 
+    /// The application as a whole was asked to quit (e.g. SDL_QUIT)
+    QuitRequested,
     CloseRequested(Identity),
     Destroyed(Identity),
     Resized(Identity, f32, f32),
@@ -78,11 +93,31 @@ pub enum InputEvent {
     Moved(Identity, ScreenPoint),
 }
 
+impl InputEvent {
+    /// Input starting or changing something, rather than releases, pointer position, device
+    /// and window events, which readers need to keep their state right
+    pub fn is_press(&self) -> bool {
+        matches!(
+            self,
+            InputEvent::KeyDown(..)
+                | InputEvent::MouseDown(..)
+                | InputEvent::MouseMotion(..)
+                | InputEvent::MouseWheel(..)
+                | InputEvent::TextInput(..)
+                | InputEvent::TextEditing(..)
+                | InputEvent::JoyAxisChanged(..)
+                | InputEvent::JoyButtonDown(..)
+                | InputEvent::JoyButtonRepeated(..)
+                | InputEvent::JoyButtonChanged(..)
+        )
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    use crate::event::{CopyingEventProducer, EventProducer};
+    use crate::event::EventBus;
 
     use std::sync::{Arc, Mutex};
 
@@ -107,47 +142,48 @@ mod tests {
         let state = Arc::new(Mutex::new(s1));
         let closed_state = state.clone();
 
-        let handler1 = CopyingEventProducer::<InputEvent>::create_handler(
-            move |event: InputEvent| match event {
-                InputEvent::KeyDown(_id, code) => {
-                    closed_state.lock().unwrap().keys_down[code as usize] = true;
-                }
-                InputEvent::KeyUp(_id, code) => {
-                    closed_state.lock().unwrap().keys_down[code as usize] = false;
-                }
-                _ => {
-                    panic!("Nope!");
-                }
-            },
-        );
+        let handler1 = move |event: &InputEvent| match *event {
+            InputEvent::KeyDown(_id, code) => {
+                closed_state.lock().unwrap().keys_down[code as usize] = true;
+            }
+            InputEvent::KeyUp(_id, code) => {
+                closed_state.lock().unwrap().keys_down[code as usize] = false;
+            }
+            _ => {
+                panic!("Nope!");
+            }
+        };
 
         let down_event = InputEvent::KeyDown(id, 42);
         let up_event = InputEvent::KeyUp(id, 42);
 
-        let mut producer = CopyingEventProducer::<InputEvent>::new();
+        let producer = EventBus::<InputEvent>::new();
 
-        let handler_id = "input_handler_id".to_string();
-        producer.add_handler(handler_id.clone(), &handler1);
+        let subscription = producer.subscribe("input_handler_id", 0, handler1).unwrap();
 
         // Initial state is unpressed
         let not_pressed = !state.lock().unwrap().get_keys()[42];
         assert!(not_pressed);
 
         producer.publish(down_event.clone());
+        producer.dispatch();
         let pressed = state.lock().unwrap().get_keys()[42];
         assert!(pressed);
 
         producer.publish(up_event);
+        producer.dispatch();
         let not_pressed = !state.lock().unwrap().get_keys()[42];
         assert!(not_pressed);
 
-        producer.remove_handler(&handler_id);
+        drop(subscription);
         producer.publish(down_event);
+        producer.dispatch();
         let pressed = state.lock().unwrap().get_keys()[42];
         assert!(!pressed);
 
         let evt = InputEvent::KeyDown(id, 69);
         producer.publish(evt);
+        producer.dispatch();
     }
 
     #[test]
@@ -1,2 +1,33 @@
+use std::collections::VecDeque;
+
+use crate::input::events::InputEvent;
+use crate::{Identifyable, Identity};
+
+pub mod actions;
+pub mod contexts;
 pub mod events;
+pub mod pressed;
+pub mod processing;
 pub mod screen;
+
+/// A device owned by a mod, producing input events when polled.
+///
+/// Sources are kept in State so the owning mod can poll them each frame. They must be removed
+/// when that mod unloads, since their code goes with it.
+/// Their devices aren't bound to a window, so events are tagged with the `window` given, the one
+/// with keyboard focus (see `events::InputEvent`).
+pub trait InputSource: Identifyable {
+    fn get_input_events(&mut self, window: Identity) -> VecDeque<InputEvent>;
+
+    /// Final events to send when the source is removed, e.g. disconnecting its devices
+    fn unload(&mut self, _window: Identity) -> VecDeque<InputEvent> {
+        VecDeque::new()
+    }
+}
+
+/// Sees every event before it is published, e.g. the UI, which takes the clicks that land on it.
+/// Consumed events are still published, flagged, and left out for readers that only want what
+/// nothing else handled, like gameplay.
+pub trait InputConsumer {
+    fn consume(&mut self, event: &InputEvent) -> bool;
+}
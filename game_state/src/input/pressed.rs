@@ -0,0 +1,163 @@
+use std::collections::HashSet;
+
+use crate::input::events::{DeviceId, InputEvent, JoyButton, MouseButton};
+
+/// Anything that can be held down
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum InputButton {
+    Key(u32),
+    Mouse(MouseButton),
+    Joy(DeviceId, JoyButton),
+}
+
+impl InputButton {
+    /// The button an event presses (true) or releases (false), if any
+    pub fn from_event(event: &InputEvent) -> Option<(InputButton, bool)> {
+        match event {
+            InputEvent::KeyDown(_, code) => Some((InputButton::Key(*code), true)),
+            InputEvent::KeyUp(_, code) => Some((InputButton::Key(*code), false)),
+            InputEvent::MouseDown(_, b) => Some((InputButton::Mouse(b.clone()), true)),
+            InputEvent::MouseUp(_, b) => Some((InputButton::Mouse(b.clone()), false)),
+            InputEvent::JoyButtonDown(_, device, b) => {
+                Some((InputButton::Joy(*device, b.clone()), true))
+            }
+            InputEvent::JoyButtonUp(_, device, b) => {
+                Some((InputButton::Joy(*device, b.clone()), false))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Tracks which keys, mouse buttons and gamepad buttons are held, and which were pressed or
+/// released during the current frame. Several may be held at once.
+///
+/// Edges are kept until the end of the frame they happened in, so a button pressed and released
+/// within one frame reports both `just_pressed` and `just_released` but is not held.
+#[derive(Default)]
+pub struct PressedState {
+    held: HashSet<InputButton>,
+    pressed: HashSet<InputButton>,
+    released: HashSet<InputButton>,
+}
+
+impl PressedState {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn apply(&mut self, event: &InputEvent) {
+        match event {
+            // we won't see the releases of anything held while unfocused
+            InputEvent::LostFocus(_) => self.release_all(),
+            InputEvent::JoyDisconnected(_, device) => {
+                let released = self
+                    .held
+                    .iter()
+                    .filter(|b| matches!(b, InputButton::Joy(d, _) if d == device))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                for button in released {
+                    self.release(button);
+                }
+            }
+            _ => match InputButton::from_event(event) {
+                Some((button, true)) => self.press(button),
+                Some((button, false)) => self.release(button),
+                None => {}
+            },
+        }
+    }
+
+    /// Forget this frame's edges
+    pub fn end_frame(&mut self) {
+        self.pressed.clear();
+        self.released.clear();
+    }
+
+    pub fn is_held(&self, button: &InputButton) -> bool {
+        self.held.contains(button)
+    }
+
+    pub fn just_pressed(&self, button: &InputButton) -> bool {
+        self.pressed.contains(button)
+    }
+
+    pub fn just_released(&self, button: &InputButton) -> bool {
+        self.released.contains(button)
+    }
+
+    pub fn is_key_held(&self, code: u32) -> bool {
+        self.is_held(&InputButton::Key(code))
+    }
+
+    pub fn held(&self) -> impl Iterator<Item = &InputButton> {
+        self.held.iter()
+    }
+
+    fn press(&mut self, button: InputButton) {
+        // key repeats and duplicate downs from several sources are not new presses
+        if self.held.insert(button.clone()) {
+            self.pressed.insert(button);
+        }
+    }
+
+    fn release(&mut self, button: InputButton) {
+        if self.held.remove(&button) {
+            self.released.insert(button);
+        }
+    }
+
+    fn release_all(&mut self) {
+        for button in self.held.drain() {
+            self.released.insert(button);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const W: u32 = 119;
+    const D: u32 = 100;
+
+    #[test]
+    fn tracks_simultaneous_keys() {
+        let mut state = PressedState::new();
+        state.apply(&InputEvent::KeyDown(0, W));
+        state.apply(&InputEvent::KeyDown(0, D));
+        assert!(state.is_key_held(W) && state.is_key_held(D));
+
+        state.end_frame();
+        state.apply(&InputEvent::KeyUp(0, W));
+        assert!(!state.is_key_held(W));
+        assert!(state.just_released(&InputButton::Key(W)));
+        assert!(state.is_key_held(D));
+        assert!(!state.just_pressed(&InputButton::Key(D)));
+    }
+
+    #[test]
+    fn tap_within_a_frame() {
+        let mut state = PressedState::new();
+        let south = InputButton::Joy(1, JoyButton::South);
+        state.apply(&InputEvent::JoyButtonDown(0, 1, JoyButton::South));
+        state.apply(&InputEvent::JoyButtonUp(0, 1, JoyButton::South));
+        assert!(state.just_pressed(&south) && state.just_released(&south));
+        assert!(!state.is_held(&south));
+        state.end_frame();
+        assert!(!state.just_pressed(&south));
+    }
+
+    #[test]
+    fn losing_focus_releases_everything() {
+        let mut state = PressedState::new();
+        state.apply(&InputEvent::MouseDown(0, MouseButton::Left));
+        state.apply(&InputEvent::JoyButtonDown(0, 2, JoyButton::East));
+        state.apply(&InputEvent::JoyDisconnected(0, 2));
+        assert!(!state.is_held(&InputButton::Joy(2, JoyButton::East)));
+        state.apply(&InputEvent::LostFocus(0));
+        assert_eq!(state.held().count(), 0);
+        assert!(state.just_released(&InputButton::Mouse(MouseButton::Left)));
+    }
+}
@@ -0,0 +1,672 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::input::actions::JOY_AXES;
+use crate::input::events::{DeviceId, InputEvent, JoyAxis};
+use crate::Identity;
+
+//
+// Input processing: raw axis values are shaped before they are published, so every consumer
+// sees the same deadzones, curves and sensitivity. Joysticks have a profile per controller GUID,
+// falling back to a default profile, and the mouse has its own.
+//
+// Profiles are declared in a config file, in sections:
+//
+//   [mouse]
+//   X = sensitivity 0.01
+//   Y = sensitivity 0.01, invert
+//
+//   [joystick]                     # any controller without its own profile
+//   radial_deadzone = 0.15
+//   RightStickY = invert, curve exponential 2
+//
+//   [joystick <guid>]
+//   LeftStickX = deadzone 0.1, curve custom 0.5:0.2 0.8:0.6, smoothing 0.5
+//
+// Axis settings are `deadzone <f>`, `curve linear|exponential <f>|custom <x>:<y> ...`,
+// `invert`, `sensitivity <f>` and `smoothing <f>`. The mouse only uses inversion, sensitivity and
+// smoothing, which spreads its motion over the following frames.
+//
+
+/// Differences smaller than this end smoothing
+const SMOOTHING_SNAP: f32 = 0.001;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResponseCurve {
+    Linear,
+    /// magnitude ^ exponent, finer control near the centre for exponents above 1
+    Exponential(f32),
+    /// Piecewise linear through (input, output) points in 0..=1, (0, 0) and (1, 1) are implied
+    Custom(Vec<(f32, f32)>),
+}
+
+impl ResponseCurve {
+    pub fn apply(&self, v: f32) -> f32 {
+        let magnitude = v.abs().min(1.0);
+        let shaped = match self {
+            ResponseCurve::Linear => magnitude,
+            ResponseCurve::Exponential(exponent) => magnitude.powf(*exponent),
+            ResponseCurve::Custom(points) => piecewise_linear(points, magnitude),
+        };
+        shaped.copysign(v)
+    }
+}
+
+fn piecewise_linear(points: &[(f32, f32)], x: f32) -> f32 {
+    let mut prev = (0.0, 0.0);
+    for &(px, py) in points.iter().chain(std::iter::once(&(1.0, 1.0))) {
+        if x <= px {
+            let span = px - prev.0;
+            if span <= f32::EPSILON {
+                return py;
+            }
+            return prev.1 + (py - prev.1) * (x - prev.0) / span;
+        }
+        prev = (px, py);
+    }
+    prev.1
+}
+
+impl fmt::Display for ResponseCurve {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ResponseCurve::Linear => write!(f, "linear"),
+            ResponseCurve::Exponential(exponent) => write!(f, "exponential {}", exponent),
+            ResponseCurve::Custom(points) => {
+                write!(f, "custom")?;
+                for (x, y) in points {
+                    write!(f, " {}:{}", x, y)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl FromStr for ResponseCurve {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+        match parts.next() {
+            Some("linear") => Ok(ResponseCurve::Linear),
+            Some("exponential") => parts
+                .next()
+                .and_then(|e| e.parse::<f32>().ok())
+                .map(ResponseCurve::Exponential)
+                .ok_or_else(|| format!("expected an exponent, got '{}'", s)),
+            Some("custom") => {
+                let mut points = parts
+                    .map(|point| {
+                        let mut xy = point.splitn(2, ':').map(|v| v.parse::<f32>());
+                        match (xy.next(), xy.next()) {
+                            (Some(Ok(x)), Some(Ok(y))) => Ok((x, y)),
+                            _ => Err(format!("expected <x>:<y>, got '{}'", point)),
+                        }
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                points.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+                Ok(ResponseCurve::Custom(points))
+            }
+            _ => Err(format!("unknown curve '{}'", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AxisSettings {
+    /// Axial deadzone, the remaining range is rescaled to 0..=1
+    pub deadzone: f32,
+    pub curve: ResponseCurve,
+    pub invert: bool,
+    pub sensitivity: f32,
+    /// Fraction of the previous value kept each frame, 0.0 for none
+    pub smoothing: f32,
+}
+
+impl Default for AxisSettings {
+    fn default() -> Self {
+        AxisSettings {
+            deadzone: 0.0,
+            curve: ResponseCurve::Linear,
+            invert: false,
+            sensitivity: 1.0,
+            smoothing: 0.0,
+        }
+    }
+}
+
+impl AxisSettings {
+    /// Deadzone, curve, inversion and sensitivity, for a value in -1.0..=1.0
+    pub fn shape(&self, v: f32) -> f32 {
+        self.scale(self.curve.apply(rescale_deadzone(v, self.deadzone)))
+    }
+
+    /// Inversion and sensitivity only, for unbounded values such as mouse motion
+    pub fn scale(&self, v: f32) -> f32 {
+        let v = if self.invert { -v } else { v };
+        v * self.sensitivity
+    }
+
+    pub fn smooth(&self, previous: f32, target: f32) -> f32 {
+        let keep = self.smoothing.clamp(0.0, 0.99);
+        let v = previous + (target - previous) * (1.0 - keep);
+        if (target - v).abs() < SMOOTHING_SNAP {
+            target
+        } else {
+            v
+        }
+    }
+}
+
+fn rescale_deadzone(v: f32, deadzone: f32) -> f32 {
+    let magnitude = v.abs();
+    if magnitude <= deadzone {
+        0.0
+    } else {
+        ((magnitude - deadzone) / (1.0 - deadzone))
+            .min(1.0)
+            .copysign(v)
+    }
+}
+
+/// Deadzone over the stick's distance from centre, rather than each axis on its own
+fn rescale_radial(x: f32, y: f32, deadzone: f32) -> (f32, f32) {
+    let magnitude = x.hypot(y);
+    if magnitude <= deadzone {
+        (0.0, 0.0)
+    } else {
+        let scale = ((magnitude - deadzone) / (1.0 - deadzone)).min(1.0) / magnitude;
+        (x * scale, y * scale)
+    }
+}
+
+impl fmt::Display for AxisSettings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let default = AxisSettings::default();
+        let mut settings = Vec::new();
+        if self.deadzone != default.deadzone {
+            settings.push(format!("deadzone {}", self.deadzone));
+        }
+        if self.curve != default.curve {
+            settings.push(format!("curve {}", self.curve));
+        }
+        if self.invert {
+            settings.push("invert".to_string());
+        }
+        if self.sensitivity != default.sensitivity {
+            settings.push(format!("sensitivity {}", self.sensitivity));
+        }
+        if self.smoothing != default.smoothing {
+            settings.push(format!("smoothing {}", self.smoothing));
+        }
+        write!(f, "{}", settings.join(", "))
+    }
+}
+
+impl FromStr for AxisSettings {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut settings = AxisSettings::default();
+        for setting in s.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let mut parts = setting.splitn(2, char::is_whitespace);
+            let name = parts.next().unwrap_or("");
+            let value = parts.next().unwrap_or("").trim();
+            let number = || {
+                value
+                    .parse::<f32>()
+                    .map_err(|_| format!("expected a number for {}, got '{}'", name, value))
+            };
+            match name {
+                "deadzone" => settings.deadzone = number()?.clamp(0.0, 0.99),
+                "curve" => settings.curve = value.parse()?,
+                "invert" => settings.invert = true,
+                "sensitivity" => settings.sensitivity = number()?,
+                "smoothing" => settings.smoothing = number()?.clamp(0.0, 0.99),
+                other => return Err(format!("unknown axis setting '{}'", other)),
+            }
+        }
+        Ok(settings)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct JoystickProfile {
+    pub radial_deadzone: f32,
+    pub axes: HashMap<JoyAxis, AxisSettings>,
+}
+
+impl JoystickProfile {
+    pub fn axis(&self, axis: &JoyAxis) -> AxisSettings {
+        self.axes.get(axis).cloned().unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MouseProfile {
+    pub x: AxisSettings,
+    pub y: AxisSettings,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct InputProfiles {
+    pub mouse: MouseProfile,
+    /// Used by controllers without a profile of their own
+    pub joystick: JoystickProfile,
+    /// Keyed by controller GUID
+    pub controllers: HashMap<String, JoystickProfile>,
+}
+
+enum Section {
+    Mouse,
+    Joystick(Option<String>),
+}
+
+impl InputProfiles {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let config = fs::read_to_string(path)?;
+        Self::parse(&config)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        fs::write(path, self.to_config())?;
+        Ok(())
+    }
+
+    pub fn parse(config: &str) -> Result<Self, Box<dyn Error>> {
+        let mut profiles = InputProfiles::new();
+        let mut section = None;
+        for (n, line) in config.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let err = |msg: String| format!("line {}: {}", n + 1, msg);
+
+            if line.starts_with('[') && line.ends_with(']') {
+                let mut parts = line[1..line.len() - 1].split_whitespace();
+                section = match (parts.next(), parts.next()) {
+                    (Some("mouse"), None) => Some(Section::Mouse),
+                    (Some("joystick"), guid) => {
+                        Some(Section::Joystick(guid.map(|g| g.to_string())))
+                    }
+                    _ => return Err(err(format!("unknown section '{}'", line)).into()),
+                };
+                continue;
+            }
+
+            let mut parts = line.splitn(2, '=');
+            let name = parts.next().unwrap_or("").trim();
+            let value = parts
+                .next()
+                .ok_or_else(|| err(format!("expected '=' after '{}'", name)))?
+                .trim();
+            match &section {
+                None => return Err(err("setting outside of a section".to_string()).into()),
+                Some(Section::Mouse) => {
+                    let settings = value.parse().map_err(err)?;
+                    match name {
+                        "X" | "x" => profiles.mouse.x = settings,
+                        "Y" | "y" => profiles.mouse.y = settings,
+                        _ => return Err(err(format!("unknown mouse axis '{}'", name)).into()),
+                    }
+                }
+                Some(Section::Joystick(guid)) => {
+                    let profile = match guid {
+                        Some(guid) => profiles.controllers.entry(guid.clone()).or_default(),
+                        None => &mut profiles.joystick,
+                    };
+                    if name == "radial_deadzone" {
+                        profile.radial_deadzone = value
+                            .parse::<f32>()
+                            .map_err(|_| err(format!("expected a number, got '{}'", value)))?
+                            .clamp(0.0, 0.99);
+                    } else {
+                        let axis = JOY_AXES
+                            .iter()
+                            .find(|a| format!("{:?}", a) == name)
+                            .ok_or_else(|| err(format!("unknown joystick axis '{}'", name)))?;
+                        profile
+                            .axes
+                            .insert(axis.clone(), value.parse().map_err(err)?);
+                    }
+                }
+            }
+        }
+        Ok(profiles)
+    }
+
+    pub fn to_config(&self) -> String {
+        let mut config = format!("[mouse]\nX = {}\nY = {}\n", self.mouse.x, self.mouse.y);
+        config += &format!("\n[joystick]\n{}", joystick_config(&self.joystick));
+
+        let mut guids = self.controllers.keys().collect::<Vec<_>>();
+        guids.sort();
+        for guid in guids {
+            config += &format!(
+                "\n[joystick {}]\n{}",
+                guid,
+                joystick_config(&self.controllers[guid])
+            );
+        }
+        config
+    }
+
+    pub fn is_default(&self) -> bool {
+        *self == InputProfiles::default()
+    }
+
+    pub fn joystick(&self, guid: Option<&str>) -> &JoystickProfile {
+        guid.and_then(|guid| self.controllers.get(guid))
+            .unwrap_or(&self.joystick)
+    }
+}
+
+fn joystick_config(profile: &JoystickProfile) -> String {
+    let mut config = format!("radial_deadzone = {}\n", profile.radial_deadzone);
+    for axis in JOY_AXES.iter() {
+        if let Some(settings) = profile.axes.get(axis) {
+            config += &format!("{:?} = {}\n", axis, settings);
+        }
+    }
+    config
+}
+
+/// This frame's motion of a smoothed mouse axis, 0.0 for an axis sent as it moves
+fn smooth_motion(settings: &AxisSettings, previous: f32, motion: f32) -> f32 {
+    if settings.smoothing > 0.0 {
+        settings.smooth(previous, motion)
+    } else {
+        0.0
+    }
+}
+
+fn stick_partner(axis: &JoyAxis) -> Option<(JoyAxis, JoyAxis)> {
+    match axis {
+        JoyAxis::LeftStickX | JoyAxis::LeftStickY => {
+            Some((JoyAxis::LeftStickX, JoyAxis::LeftStickY))
+        }
+        JoyAxis::RightStickX | JoyAxis::RightStickY => {
+            Some((JoyAxis::RightStickX, JoyAxis::RightStickY))
+        }
+        _ => None,
+    }
+}
+
+#[derive(Default)]
+struct AxisValue {
+    source: Identity,
+    raw: f32,
+    target: f32,
+    output: f32,
+}
+
+/// Motion of the smoothed mouse axes, summed over the frame
+#[derive(Default)]
+struct MouseValue {
+    source: Identity,
+    pending: (f32, f32),
+    output: (f32, f32),
+}
+
+/// Applies InputProfiles to events as they are sent
+#[derive(Default)]
+pub struct InputProcessor {
+    profiles: InputProfiles,
+    guids: HashMap<DeviceId, String>,
+    axes: HashMap<(DeviceId, JoyAxis), AxisValue>,
+    mouse: MouseValue,
+}
+
+impl InputProcessor {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn get_profiles(&mut self) -> &mut InputProfiles {
+        &mut self.profiles
+    }
+
+    /// The events to publish in place of `event`. Moving one stick axis may also change the
+    /// other, through the radial deadzone.
+    pub fn process(&mut self, event: InputEvent) -> Vec<InputEvent> {
+        match event {
+            InputEvent::JoyConnected(_, device, ref guid) => {
+                self.guids.insert(device, guid.clone());
+                vec![event]
+            }
+            InputEvent::JoyDisconnected(_, device) => {
+                self.guids.remove(&device);
+                self.axes.retain(|(d, _), _| *d != device);
+                vec![event]
+            }
+            InputEvent::JoyAxisChanged(source, device, axis, value) => {
+                self.process_axis(source, device, axis, value)
+            }
+            InputEvent::MouseMotion(id, x, y) => self.process_mouse(id, x, y),
+            event => vec![event],
+        }
+    }
+
+    /// Moves smoothed axes towards their targets, once per frame
+    pub fn end_frame(&mut self) -> Vec<InputEvent> {
+        let mut events = Vec::new();
+
+        let mouse = &self.profiles.mouse;
+        let value = &mut self.mouse;
+        value.output = (
+            smooth_motion(&mouse.x, value.output.0, value.pending.0),
+            smooth_motion(&mouse.y, value.output.1, value.pending.1),
+        );
+        value.pending = (0.0, 0.0);
+        if value.output != (0.0, 0.0) {
+            events.push(InputEvent::MouseMotion(
+                value.source,
+                value.output.0,
+                value.output.1,
+            ));
+        }
+
+        for ((device, axis), value) in self.axes.iter_mut() {
+            if value.output == value.target {
+                continue;
+            }
+            let guid = self.guids.get(device).map(|g| g.as_str());
+            let settings = self.profiles.joystick(guid).axis(axis);
+            value.output = settings.smooth(value.output, value.target);
+            events.push(InputEvent::JoyAxisChanged(
+                value.source,
+                *device,
+                axis.clone(),
+                value.output,
+            ));
+        }
+        events
+    }
+
+    /// Smoothed mouse axes are sent at the end of the frame, the others right away
+    fn process_mouse(&mut self, source: Identity, x: f32, y: f32) -> Vec<InputEvent> {
+        let mouse = &self.profiles.mouse;
+        if mouse.x.smoothing <= 0.0 && mouse.y.smoothing <= 0.0 {
+            return vec![InputEvent::MouseMotion(
+                source,
+                mouse.x.scale(x),
+                mouse.y.scale(y),
+            )];
+        }
+
+        let value = &mut self.mouse;
+        value.source = source;
+        let mut now = (mouse.x.scale(x), mouse.y.scale(y));
+        if mouse.x.smoothing > 0.0 {
+            value.pending.0 += now.0;
+            now.0 = 0.0;
+        }
+        if mouse.y.smoothing > 0.0 {
+            value.pending.1 += now.1;
+            now.1 = 0.0;
+        }
+        if now == (0.0, 0.0) {
+            Vec::new()
+        } else {
+            vec![InputEvent::MouseMotion(source, now.0, now.1)]
+        }
+    }
+
+    fn process_axis(
+        &mut self,
+        source: Identity,
+        device: DeviceId,
+        axis: JoyAxis,
+        raw: f32,
+    ) -> Vec<InputEvent> {
+        let guid = self.guids.get(&device).map(|g| g.as_str());
+        let profile = self.profiles.joystick(guid);
+
+        let entry = self.axes.entry((device, axis.clone())).or_default();
+        entry.source = source;
+        entry.raw = raw;
+
+        // both axes of a stick are shaped together, since the radial deadzone depends on each
+        let targets = match stick_partner(&axis) {
+            Some((x_axis, y_axis)) => {
+                let raw_of = |a: &JoyAxis| {
+                    self.axes
+                        .get(&(device, a.clone()))
+                        .map(|v| v.raw)
+                        .unwrap_or(0.0)
+                };
+                let (x, y) =
+                    rescale_radial(raw_of(&x_axis), raw_of(&y_axis), profile.radial_deadzone);
+                vec![(x_axis, x), (y_axis, y)]
+            }
+            None => vec![(axis.clone(), raw)],
+        };
+
+        let mut events = Vec::new();
+        for (a, v) in targets {
+            let settings = profile.axis(&a);
+            let target = settings.shape(v);
+            let value = self.axes.entry((device, a.clone())).or_default();
+            value.source = source;
+            value.target = target;
+            // smoothed axes are sent at the end of the frame
+            if settings.smoothing > 0.0 || (a != axis && value.output == target) {
+                continue;
+            }
+            value.output = target;
+            events.push(InputEvent::JoyAxisChanged(source, device, a, target));
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn axis_values(events: &[InputEvent]) -> Vec<(JoyAxis, f32)> {
+        events
+            .iter()
+            .filter_map(|e| match e {
+                InputEvent::JoyAxisChanged(_, _, a, v) => Some((a.clone(), *v)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn curves_and_deadzones() {
+        assert_eq!(ResponseCurve::Exponential(2.0).apply(-0.5), -0.25);
+        let custom: ResponseCurve = "custom 0.5:0.25".parse().unwrap();
+        assert_eq!(custom.apply(0.75), 0.625);
+
+        let settings: AxisSettings = "deadzone 0.2, invert, sensitivity 2".parse().unwrap();
+        assert_eq!(settings.shape(0.1), 0.0);
+        assert!((settings.shape(0.6) + 1.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn radial_deadzone_shapes_both_stick_axes() {
+        let mut processor = InputProcessor::new();
+        processor.get_profiles().joystick.radial_deadzone = 0.5;
+
+        let events = processor.process(InputEvent::JoyAxisChanged(0, 1, JoyAxis::LeftStickX, 0.3));
+        assert_eq!(axis_values(&events), vec![(JoyAxis::LeftStickX, 0.0)]);
+
+        let events = processor.process(InputEvent::JoyAxisChanged(0, 1, JoyAxis::LeftStickX, 0.6));
+        let values = axis_values(&events);
+        assert_eq!(values.len(), 1);
+        assert!((values[0].1 - 0.2).abs() < 0.001);
+
+        // 0.6, 0.8 is a full deflection, the partner axis is resent as it has changed
+        let events = processor.process(InputEvent::JoyAxisChanged(0, 1, JoyAxis::LeftStickY, 0.8));
+        let values = axis_values(&events);
+        assert_eq!(values.len(), 2);
+        assert!((values[0].1 - 0.6).abs() < 0.001 && (values[1].1 - 0.8).abs() < 0.001);
+    }
+
+    #[test]
+    fn profiles_by_guid_and_smoothing() {
+        let config = "[mouse]\nY = invert, sensitivity 0.5\n\n\
+                      [joystick abc]\nRightZ = smoothing 0.5\n";
+        let profiles = InputProfiles::parse(config).unwrap();
+        assert_eq!(
+            InputProfiles::parse(&profiles.to_config()).unwrap(),
+            profiles
+        );
+
+        let mut processor = InputProcessor::new();
+        *processor.get_profiles() = profiles;
+        let events = processor.process(InputEvent::MouseMotion(0, 4.0, 4.0));
+        assert!(matches!(events[0], InputEvent::MouseMotion(_, x, y) if x == 4.0 && y == -2.0));
+
+        processor.process(InputEvent::JoyConnected(0, 1, "abc".to_string()));
+        let events = processor.process(InputEvent::JoyAxisChanged(0, 1, JoyAxis::RightZ, 1.0));
+        assert!(events.is_empty());
+        assert_eq!(
+            axis_values(&processor.end_frame()),
+            vec![(JoyAxis::RightZ, 0.5)]
+        );
+        assert_eq!(
+            axis_values(&processor.end_frame()),
+            vec![(JoyAxis::RightZ, 0.75)]
+        );
+    }
+
+    #[test]
+    fn mouse_smoothing_spreads_motion_over_frames() {
+        let mut processor = InputProcessor::new();
+        processor.get_profiles().mouse.x = "smoothing 0.5".parse().unwrap();
+
+        // the unsmoothed axis is sent right away
+        let events = processor.process(InputEvent::MouseMotion(0, 4.0, 2.0));
+        assert!(matches!(events[..], [InputEvent::MouseMotion(_, x, y)] if x == 0.0 && y == 2.0));
+        assert!(processor
+            .process(InputEvent::MouseMotion(0, 4.0, 0.0))
+            .is_empty());
+
+        let motion = |events: Vec<InputEvent>| match events[..] {
+            [InputEvent::MouseMotion(_, x, y)] => (x, y),
+            _ => panic!("expected one motion, got {:?}", events),
+        };
+        assert_eq!(motion(processor.end_frame()), (4.0, 0.0));
+        assert_eq!(motion(processor.end_frame()), (2.0, 0.0));
+        assert_eq!(motion(processor.end_frame()), (1.0, 0.0));
+
+        // and stops once it has settled
+        for _ in 0..20 {
+            processor.end_frame();
+        }
+        assert!(processor.end_frame().is_empty());
+    }
+}
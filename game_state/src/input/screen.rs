@@ -1,4 +1,4 @@
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ScreenPoint {
     pub x: i32,
     pub y: i32,
@@ -10,7 +10,7 @@ impl ScreenPoint {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ScreenRect {
     pub x: i32,
     pub y: i32,
@@ -28,6 +28,28 @@ impl ScreenRect {
             && point.x < self.x + self.w
             && point.y < self.y + self.h
     }
+
+    /// The area both rects cover, empty (w or h of 0) if they don't overlap
+    pub fn intersection(&self, other: &ScreenRect) -> ScreenRect {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = (self.x + self.w).min(other.x + other.w);
+        let bottom = (self.y + self.h).min(other.y + other.h);
+        ScreenRect::new(x, y, (right - x).max(0), (bottom - y).max(0))
+    }
+
+    /// The smallest rect covering both
+    pub fn union(&self, other: &ScreenRect) -> ScreenRect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = (self.x + self.w).max(other.x + other.w);
+        let bottom = (self.y + self.h).max(other.y + other.h);
+        ScreenRect::new(x, y, right - x, bottom - y)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.w <= 0 || self.h <= 0
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -1,4 +1,5 @@
 // opinion here? reexport winit or import in other libs...?
+pub use gilrs;
 pub use nalgebra;
 pub use sdl2;
 pub use sdl2::sys as sdl2_sys;
@@ -9,6 +10,7 @@ pub mod tree;
 
 pub mod event;
 pub mod input;
+pub mod message;
 pub mod thing;
 pub mod ui;
 pub mod utils;
@@ -16,9 +18,9 @@ pub mod utils;
 use std::sync::Arc;
 use std::time::Duration;
 
-use state::SceneGraph;
+use state::{CameraView, DrawMode, ModelView, SceneGraph, WindowId};
 use std::sync::atomic::{AtomicUsize, Ordering};
-use thing::CameraFacet;
+use ui::draw::DrawList;
 
 static GLOBAL_IDENITY_CURSOR: AtomicUsize = AtomicUsize::new(0);
 
@@ -44,9 +46,24 @@ pub trait Renderer: Identifyable {
     /// Set the renderer up with a queue of SceneGraphs
     fn queue_render_layer(&mut self, layer: Arc<SceneGraph>);
 
+    /// queue_ui_layer()
+    /// Set the renderer up with the UI of its window, drawn over every view after the render
+    /// layers: quads, textured quads and clip rects, text is already typeset into quads.
+    fn queue_ui_layer(&mut self, layer: Arc<DrawList>);
+
+    /// set_draw_mode()
+    /// Draw the window's scene in another mode from the next frame, e.g. as a wireframe
+    fn set_draw_mode(&mut self, _mode: DrawMode) {}
+
+    /// window_id()
+    /// The window this renderer presents to, used to look up the window's viewports
+    fn window_id(&self) -> WindowId;
+
     /// present()
-    /// Actually render the image, compositing render layers in the order they were queued
-    fn present(&mut self, camera: &CameraFacet);
+    /// Actually render the image, compositing render layers in the order they were queued,
+    /// once for each view. Scene graph nodes are drawn with their thing's model and world
+    /// matrix, nodes whose thing isn't among the models are skipped.
+    fn present(&mut self, views: &[CameraView], models: &[ModelView]);
 }
 
 pub trait Behavior {
@@ -0,0 +1,376 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+//
+// Messages between mods. A mod sends a named message to another mod, or to every mod, and the
+// receiver takes it from its inbox on the next tick, after the main loop delivered the tick's
+// messages, see `MessageAccess`.
+//
+// Messages are kept as plain data, a `Value`, and inboxes live in State by mod name, so neither
+// depends on code in a mod: unread messages wait while the receiver is reloaded, and those sent
+// by a mod arrive even if it's reloaded straight after.
+//
+
+/// Messages waiting for a mod, the oldest are dropped past this, e.g. when a mod isn't loaded
+const MAX_INBOX: usize = 1024;
+
+/// A message's payload, serializable so mods can save or forward it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Value {
+    None,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Text(String),
+    List(Vec<Value>),
+    Map(BTreeMap<String, Value>),
+}
+
+impl Value {
+    pub fn map() -> Value {
+        Value::Map(BTreeMap::new())
+    }
+
+    /// A map with the entry added, e.g. `Value::map().with("paused", true)`
+    pub fn with<V: Into<Value>>(mut self, key: &str, value: V) -> Value {
+        if let Value::Map(entries) = &mut self {
+            entries.insert(key.to_string(), value.into());
+        }
+        self
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Map(entries) => entries.get(key),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            Value::Int(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    /// Ints are read as floats too
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            Value::Float(v) => Some(*v),
+            Value::Int(v) => Some(*v as f64),
+            _ => None,
+        }
+    }
+
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Value::Text(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Value]> {
+        match self {
+            Value::List(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// The entry, or an error naming it, for `Message::from_value`
+    pub fn field(&self, key: &str) -> Result<&Value, String> {
+        self.get(key)
+            .ok_or_else(|| format!("missing field '{}'", key))
+    }
+}
+
+impl From<bool> for Value {
+    fn from(v: bool) -> Self {
+        Value::Bool(v)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(v: i64) -> Self {
+        Value::Int(v)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(v: f64) -> Self {
+        Value::Float(v)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(v: &str) -> Self {
+        Value::Text(v.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(v: String) -> Self {
+        Value::Text(v)
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(v: Vec<T>) -> Self {
+        Value::List(v.into_iter().map(Into::into).collect())
+    }
+}
+
+/// A typed message, sent as its name and a `Value`
+pub trait Message: Sized {
+    const NAME: &'static str;
+
+    fn to_value(&self) -> Value;
+    fn from_value(value: &Value) -> Result<Self, String>;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Recipient {
+    Mod(String),
+    /// Every mod with an inbox, but the sender
+    Broadcast,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Envelope {
+    pub id: u64,
+    pub name: String,
+    pub from: String,
+    pub to: Recipient,
+    /// The message this replies to
+    pub reply_to: Option<u64>,
+    pub payload: Value,
+}
+
+impl Envelope {
+    pub fn is<M: Message>(&self) -> bool {
+        self.name == M::NAME
+    }
+
+    /// The message, if it is one of these
+    pub fn read<M: Message>(&self) -> Option<Result<M, String>> {
+        if self.is::<M>() {
+            Some(M::from_value(&self.payload))
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct PostOffice {
+    next_id: u64,
+    /// Sent this tick, delivered by `deliver`
+    outgoing: Vec<Envelope>,
+    inboxes: HashMap<String, VecDeque<Envelope>>,
+}
+
+impl PostOffice {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Only mods with an inbox receive messages, the main loop opens one for each mod
+    pub fn open_inbox(&mut self, owner: &str) {
+        self.inboxes.entry(owner.to_string()).or_default();
+    }
+
+    pub fn has_inbox(&self, owner: &str) -> bool {
+        self.inboxes.contains_key(owner)
+    }
+
+    /// Returns the message's id, which replies refer to
+    pub fn send<M: Message>(&mut self, from: &str, to: Recipient, message: &M) -> u64 {
+        self.post(from, to, None, M::NAME, message.to_value())
+    }
+
+    pub fn reply<M: Message>(&mut self, from: &str, original: &Envelope, message: &M) -> u64 {
+        let to = Recipient::Mod(original.from.clone());
+        self.post(from, to, Some(original.id), M::NAME, message.to_value())
+    }
+
+    /// A message by name and payload, for senders without a `Message` type
+    pub fn post(
+        &mut self,
+        from: &str,
+        to: Recipient,
+        reply_to: Option<u64>,
+        name: &str,
+        payload: Value,
+    ) -> u64 {
+        self.next_id += 1;
+        self.outgoing.push(Envelope {
+            id: self.next_id,
+            name: name.to_string(),
+            from: from.to_string(),
+            to,
+            reply_to,
+            payload,
+        });
+        self.next_id
+    }
+
+    /// Moves the messages sent since the last call into their receivers' inboxes, messages to
+    /// a mod without one are dropped
+    pub fn deliver(&mut self) {
+        for envelope in std::mem::take(&mut self.outgoing) {
+            match &envelope.to {
+                Recipient::Mod(to) => match self.inboxes.get_mut(to) {
+                    Some(inbox) => push_bounded(inbox, envelope),
+                    None => println!(
+                        "message: dropped '{}' from {}, there is no mod named {}",
+                        envelope.name, envelope.from, to
+                    ),
+                },
+                Recipient::Broadcast => {
+                    for (owner, inbox) in self.inboxes.iter_mut() {
+                        if *owner != envelope.from {
+                            push_bounded(inbox, envelope.clone());
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Messages delivered to the owner since the last call, oldest first
+    pub fn take(&mut self, owner: &str) -> Vec<Envelope> {
+        match self.inboxes.get_mut(owner) {
+            Some(inbox) => inbox.drain(..).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn unread(&self, owner: &str) -> usize {
+        self.inboxes
+            .get(owner)
+            .map(|inbox| inbox.len())
+            .unwrap_or(0)
+    }
+}
+
+fn push_bounded(inbox: &mut VecDeque<Envelope>, envelope: Envelope) {
+    if inbox.len() >= MAX_INBOX {
+        inbox.pop_front();
+    }
+    inbox.push_back(envelope);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Ping {
+        count: i64,
+    }
+
+    impl Message for Ping {
+        const NAME: &'static str = "ping";
+
+        fn to_value(&self) -> Value {
+            Value::map().with("count", self.count)
+        }
+
+        fn from_value(value: &Value) -> Result<Self, String> {
+            let count = value.field("count")?.as_int().ok_or("count isn't an int")?;
+            Ok(Ping { count })
+        }
+    }
+
+    #[test]
+    fn messages_arrive_after_delivery() {
+        let mut post = PostOffice::new();
+        post.open_inbox("input");
+        let id = post.send(
+            "gui",
+            Recipient::Mod("input".to_string()),
+            &Ping { count: 1 },
+        );
+        assert_eq!(post.unread("input"), 0);
+
+        post.deliver();
+        let received = post.take("input");
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].id, id);
+        assert_eq!(received[0].from, "gui");
+        assert_eq!(received[0].read::<Ping>(), Some(Ok(Ping { count: 1 })));
+        assert!(post.take("input").is_empty());
+    }
+
+    #[test]
+    fn broadcasts_skip_the_sender_and_replies_go_back() {
+        let mut post = PostOffice::new();
+        for owner in ["gui", "input", "simulation"].iter() {
+            post.open_inbox(owner);
+        }
+        post.send("gui", Recipient::Broadcast, &Ping { count: 2 });
+        post.deliver();
+        assert_eq!(post.unread("gui"), 0);
+        assert_eq!(post.unread("simulation"), 1);
+
+        let ping = post.take("input").remove(0);
+        post.reply("input", &ping, &Ping { count: 3 });
+        post.deliver();
+        let replies = post.take("gui");
+        assert_eq!(replies[0].reply_to, Some(ping.id));
+        assert_eq!(replies[0].to, Recipient::Mod("gui".to_string()));
+    }
+
+    #[test]
+    fn messages_to_unknown_mods_are_dropped() {
+        let mut post = PostOffice::new();
+        post.open_inbox("main");
+        post.post(
+            "main",
+            Recipient::Mod("audio".to_string()),
+            None,
+            "volume",
+            0.5.into(),
+        );
+        post.deliver();
+        assert!(!post.has_inbox("audio"));
+        assert!(post.take("audio").is_empty());
+
+        // an inbox keeps messages while its mod is reloaded
+        post.open_inbox("audio");
+        post.post(
+            "main",
+            Recipient::Mod("audio".to_string()),
+            None,
+            "volume",
+            0.5.into(),
+        );
+        post.deliver();
+        let envelope = post.take("audio").remove(0);
+        assert!(!envelope.is::<Ping>());
+        assert_eq!(envelope.read::<Ping>(), None);
+        assert_eq!(envelope.payload.as_float(), Some(0.5));
+        assert!(Ping::from_value(&Value::map()).is_err());
+    }
+
+    #[test]
+    fn values_survive_a_round_trip() {
+        let value = Value::map()
+            .with("paused", true)
+            .with("count", 3i64)
+            .with("volume", 0.5)
+            .with("name", "level one")
+            .with("path", vec![1i64, 2, 3])
+            .with("target", Value::None);
+        let json = serde_json::to_string(&value).unwrap();
+        let read: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(read, value);
+    }
+}
@@ -1,8 +1,6 @@
 use std::error::Error;
 use std::path::Path;
 
-use nalgebra::Matrix4;
-
 // TODO: still need to refactor nom-obj to take BufReader, among other things
 use nom_obj::model::{Interleaved, Obj};
 
@@ -19,14 +17,13 @@ pub struct Material {
 pub struct Model {
     pub filename: String,
     pub id: Identity,
-    pub model_mat: Matrix4<f32>,
-    pub world_mat: Matrix4<f32>,
     pub material: Material,
     pub mesh: Mesh,
 }
 
 impl Model {
-    pub fn load(filename: &str, model_mat: Matrix4<f32>) -> Result<Vec<Self>, Box<dyn Error>> {
+    /// The file's objects, drawn where the things using them are, see `ModelInstanceFacet`
+    pub fn load(filename: &str) -> Result<Vec<Self>, Box<dyn Error>> {
         let obj = Obj::read_file(filename)?;
 
         let mut models = Vec::new();
@@ -56,8 +53,6 @@ impl Model {
             models.push(Model {
                 filename: filename.to_string(),
                 id: create_next_identity(),
-                model_mat,
-                world_mat: Matrix4::<f32>::identity(),
                 mesh: Mesh::create(verts, indices),
                 material: Material { diffuse_map },
             })
@@ -84,7 +79,7 @@ fn slice_windows_learning() {
         assert_eq!(c.len(), 2);
     }
 
-    //let model = Model::create("assets/models/teapot.obj", Matrix4::<f32>::identity());
+    //let model = Model::load("assets/models/teapot.obj");
 }
 
 #[derive(Debug, Copy, Clone)]
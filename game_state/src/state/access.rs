@@ -1,23 +1,47 @@
 use std::collections::VecDeque;
 use std::error::Error;
+use std::fs;
+use std::mem;
 use std::rc::Rc;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use sdl2::rect::Rect;
+use sdl2::sys::SDL_WindowFlags;
 use sdl2::video::WindowContext;
 
 use super::DrawMode;
 use super::Model;
 use super::Renderer;
 
-use crate::input::events::InputEvent;
-use crate::input::screen::ScreenPoint;
-use crate::state::render_state::WindowWithAttrs;
+use crate::input::actions::{ActionMap, ActionState};
+use crate::input::contexts::{ContextStack, InputContext};
+use crate::input::events::{DeviceId, InputEvent};
+use crate::input::pressed::PressedState;
+use crate::input::processing::InputProfiles;
+use crate::input::screen::{ScreenPoint, ScreenRect};
+use crate::input::InputSource;
+use crate::message::{Envelope, Message, Recipient};
+use crate::state::render_state::{CameraView, ModelView, Viewport, WindowId, WindowWithAttrs};
+use crate::state::ui_state::{show_variable, BoundWidget, LoadedDocument};
 use crate::state::{SceneGraph, State, World};
+use crate::thing::{CameraFacet, ModelInstanceFacet, ThingId, TransformFacet};
+use crate::ui::console::{Command, Console, Invocation, BUILTIN};
+use crate::ui::document::{CallbackValue, UICallback, UIDocument};
+use crate::ui::draw::DrawList;
 use crate::ui::events::UIEvent;
+use crate::ui::font::Font;
+use crate::ui::scene::UIScene;
+use crate::utils::fps::FPS;
 use crate::Identity;
 
 use crate::state::Variable;
 
+/// Line width when the console switches a window to wireframe
+const WIREFRAME_WIDTH: f32 = 3.0;
+/// How often UI documents are checked for changes on disk
+const DOCUMENT_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
 pub trait WorldAccess {
     fn get_world(&mut self) -> &mut World;
 }
@@ -33,11 +57,30 @@ pub trait VariableAccess {
     fn bool_exists(&self, key: &'static str) -> bool {
         self.get_bool(key).is_some()
     }
+    fn get_number(&self, key: &'static str) -> Option<f32>;
+    fn set_number(&mut self, key: &'static str, value: f32);
+    fn get_text(&self, key: &'static str) -> Option<&str>;
+    fn set_text(&mut self, key: &'static str, value: &str);
 }
 
 pub trait WindowAccess {
     fn add_window(&mut self, w: u32, h: u32, title: &str, x: i32, y: i32, draw_mode: DrawMode);
-    fn get_windows(&mut self) -> Vec<(Rc<WindowContext>, DrawMode)>;
+    fn get_windows(&mut self) -> Vec<(WindowId, Rc<WindowContext>, DrawMode)>;
+    /// Destroys the window and the renderers drawing into it
+    fn close_window(&mut self, window: WindowId);
+
+    /// Drawable pixels per window coordinate, above 1.0 on HiDPI displays
+    fn get_window_scale(&self, window: WindowId) -> f32;
+
+    /// Render the whole window from the camera of the given thing
+    fn set_window_camera(&mut self, window: WindowId, camera: ThingId);
+
+    /// Replace the window's viewports, e.g. with a split-screen layout
+    fn set_window_viewports(&mut self, window: WindowId, viewports: Vec<Viewport>);
+    fn get_window_viewports(&self, window: WindowId) -> Vec<Viewport>;
+
+    /// Draw the window in another mode, its renderers switch from the next frame
+    fn set_window_draw_mode(&mut self, window: WindowId, draw_mode: DrawMode);
 }
 
 // Accessor trait for State by topic
@@ -57,34 +100,185 @@ pub trait RenderLayerAccess {
     fn get_render_layers(&mut self) -> &Vec<Arc<SceneGraph>>;
     fn add_render_layer(&mut self, layer: Arc<SceneGraph>);
     fn clear_render_layers(&mut self);
+
+    /// The UI overlay of each window, see `ui::draw`
+    fn get_ui_layers(&self) -> &Vec<Arc<DrawList>>;
+    fn set_ui_layers(&mut self, layers: Vec<Arc<DrawList>>);
 }
 
+/// Input events are broadcast: every reader (usually named after the consuming mod) has its own
+/// cursor, and sees every event published after it registered.
 pub trait InputAccess {
-    fn has_pending_input_events(&self) -> bool;
-    fn clear_input_events(&mut self);
-    fn get_input_events(&mut self) -> &VecDeque<InputEvent>;
+    fn register_input_reader(&mut self, reader: &str);
+    fn unregister_input_reader(&mut self, reader: &str);
+    /// While the context doesn't receive events (see `input::contexts`), the reader is only
+    /// sent releases, pointer position, device and window events. None receives everything.
+    fn set_input_reader_context(&mut self, reader: &str, context: Option<&str>);
+    fn has_pending_input_events(&self, reader: &str) -> bool;
+    /// Events nothing consumed, e.g. clicks on the UI are left out
+    fn read_input_events(&mut self, reader: &str) -> Vec<InputEvent>;
+    /// Every event, and whether it was consumed
+    fn read_all_input_events(&mut self, reader: &str) -> Vec<(InputEvent, bool)>;
     fn send_input_event(&mut self, event: InputEvent) -> Result<(), Box<dyn Error>>;
+
+    /// Called once per frame, after every mod has been updated
+    fn end_input_frame(&mut self);
     fn on_input_load(&mut self);
     fn on_input_unload(&mut self);
     fn get_mouse_pos(&self) -> &ScreenPoint;
     fn set_mouse_pos(&mut self, sp: ScreenPoint);
+
+    /// Named actions bound to physical inputs, see `input::actions`
+    fn get_action_map(&mut self) -> &mut ActionMap;
+    /// The action map, if its bindings changed since the last call
+    fn take_changed_bindings(&mut self) -> Option<&ActionMap>;
+    fn get_action(&self, action: &str) -> ActionState;
+
+    /// The action's state if it is delivered to the given context, see `input::contexts`.
+    /// Inactive if a higher context handles the action or blocks the context.
+    fn get_context_action(&self, context: &str, action: &str) -> ActionState;
+    /// Pushes the context on top of the stack, replacing any context with the same name
+    fn push_input_context(&mut self, context: InputContext);
+    fn pop_input_context(&mut self, name: &str) -> Option<InputContext>;
+    fn get_input_contexts(&self) -> &ContextStack;
+
+    /// Starts sending TextInput and TextEditing events, raising the window so it has keyboard
+    /// focus, as SDL delivers text to the focused window
+    fn start_text_input(&mut self, window: WindowId);
+    fn stop_text_input(&mut self);
+    /// The window text input was started for, if it is active
+    fn get_text_input_window(&self) -> Option<WindowId>;
+    /// Where the IME shows its candidates, in window coordinates, e.g. around the text cursor
+    fn set_text_input_rect(&mut self, rect: ScreenRect);
+
+    /// Keys, mouse buttons and gamepad buttons currently held, tracked from every event sent
+    fn get_pressed(&self) -> &PressedState;
+
+    fn add_input_source(
+        &mut self,
+        name: &str,
+        source: Box<dyn InputSource>,
+    ) -> Result<(), Box<dyn Error>>;
+    /// Unloads the source and sends its final events
+    fn remove_input_source(&mut self, name: &str) -> Result<(), Box<dyn Error>>;
+    /// Sends every event the source has produced since it was last polled, if it was added
+    fn poll_input_source(&mut self, name: &str) -> Result<(), Box<dyn Error>>;
+
+    /// The window with keyboard focus, 0 for none. Controller events are tagged with it.
+    fn get_focused_window(&self) -> Identity;
+
+    /// Joysticks connected, tracked from JoyConnected/JoyDisconnected events
+    fn get_joysticks(&self) -> Vec<DeviceId>;
+
+    /// Deadzones, curves and sensitivity applied to mouse and joystick axes as they are sent
+    fn get_input_profiles(&mut self) -> &mut InputProfiles;
+    /// The profiles, if they changed since the last call
+    fn take_changed_input_profiles(&mut self) -> Option<InputProfiles>;
 }
 
+/// UI windows live in the UI scene, kept in State so they stay open across reloads of the mods
+/// that opened them. Input is routed to them as it is sent, see `ui::scene`.
 pub trait UIAccess {
+    /// Events from the UI this frame, queued by mod_gui each update
     fn pending_ui_events(&mut self) -> &VecDeque<UIEvent>;
     fn queue_ui_event(&mut self, event: UIEvent);
+    fn get_ui_scene(&mut self) -> &mut UIScene;
+
+    /// Delivers the windows' queued clicks to their handlers, then replaces the pending events
+    /// with everything dispatched to the UI since the last call,
+    /// and the pending callbacks with those of document widgets. Bound variables are updated
+    /// from the widgets and then the widgets from the variables.
+    fn take_ui_events(&mut self);
+    /// Callbacks from UI document widgets this frame, e.g. `on_click=resume`
+    fn pending_ui_callbacks(&mut self) -> &VecDeque<UICallback>;
+    /// Shows the document's windows in the first SDL window, replacing those from an earlier
+    /// load of the file. Nothing changes if the file has errors.
+    fn load_ui_document(&mut self, path: &str) -> Result<(), Box<dyn Error>>;
+    /// Loads documents changed on disk since they were loaded, checked twice a second.
+    /// Returns the errors of those that failed, they keep their windows.
+    fn reload_ui_documents(&mut self) -> Vec<String>;
+    fn has_ui_documents(&self) -> bool;
+    /// Lays out windows in their host window when it changed size or scale, and windows
+    /// whose widgets changed
+    fn layout_ui(&mut self);
+    /// Replaces the UI render layers with the current state of every window, with text
+    /// typeset in the default font once one is loaded
+    fn draw_ui(&mut self);
+    /// A .ttf or .otf font, the first loaded is the default
+    fn load_font(&mut self, name: &str, path: &str) -> Result<(), Box<dyn Error>>;
+    fn has_fonts(&self) -> bool;
+    /// A line of text on the debug overlay, for this frame only
+    fn add_debug_text(&mut self, line: &str);
+    fn take_debug_text(&mut self) -> Vec<String>;
+    /// Frames drawn, counted by mod_gui
+    fn get_ui_fps(&mut self) -> &mut FPS;
     fn on_ui_load(&mut self);
     fn on_ui_unload(&mut self);
 }
 
+/// Commands for the developer console are data, see `ui::console`. Each mod takes the commands
+/// entered for it as it updates, State runs the built-in ones.
+pub trait ConsoleAccess {
+    fn get_console(&mut self) -> &mut Console;
+    /// Registering again, e.g. after a reload, replaces the command. Fails if another owner
+    /// registered the name.
+    fn register_console_command(&mut self, command: Command) -> Result<(), String>;
+    fn print_to_console(&mut self, text: &str);
+    /// Commands entered for the owner since the last call
+    fn take_console_commands(&mut self, owner: &str) -> Vec<Invocation>;
+    /// Runs the lines entered since the last call, or queues them for the mod owning the command
+    fn run_console(&mut self);
+}
+
+/// Messages between mods are plain data, see `message`. Those sent during a tick reach their
+/// receivers when the main loop calls `deliver_messages`, after every mod updated.
+pub trait MessageAccess {
+    /// Messages only reach mods with an inbox, the main loop opens one for each mod
+    fn open_inbox(&mut self, owner: &str);
+    /// Returns the message's id, which replies refer to
+    fn send_message<M: Message>(&mut self, from: &str, to: &str, message: &M) -> u64;
+    fn broadcast_message<M: Message>(&mut self, from: &str, message: &M) -> u64;
+    fn reply_to_message<M: Message>(&mut self, from: &str, original: &Envelope, message: &M)
+        -> u64;
+    /// Messages delivered to the owner since the last call
+    fn take_messages(&mut self, owner: &str) -> Vec<Envelope>;
+    fn deliver_messages(&mut self);
+}
+
 impl VariableAccess for State {
     fn get_bool(&self, key: &'static str) -> Option<bool> {
-        self.variables.get(key).map(|Variable::Bool(v)| *v)
+        match self.variables.get(key) {
+            Some(Variable::Bool(v)) => Some(*v),
+            _ => None,
+        }
     }
 
     fn set_bool(&mut self, key: &'static str, value: bool) {
         self.variables.insert(key, Variable::Bool(value));
     }
+
+    fn get_number(&self, key: &'static str) -> Option<f32> {
+        match self.variables.get(key) {
+            Some(Variable::Number(v)) => Some(*v),
+            _ => None,
+        }
+    }
+
+    fn set_number(&mut self, key: &'static str, value: f32) {
+        self.variables.insert(key, Variable::Number(value));
+    }
+
+    fn get_text(&self, key: &'static str) -> Option<&str> {
+        match self.variables.get(key) {
+            Some(Variable::Text(v)) => Some(v),
+            _ => None,
+        }
+    }
+
+    fn set_text(&mut self, key: &'static str, value: &str) {
+        self.variables
+            .insert(key, Variable::Text(value.to_string()));
+    }
 }
 
 impl ModelAccess for State {
@@ -118,17 +312,95 @@ impl WindowAccess for State {
                 .unwrap()
         };
 
+        self.render_state.windows.push(WindowWithAttrs {
+            window,
+            draw_mode,
+            viewports: Vec::new(),
+        });
+    }
+
+    fn get_windows(&mut self) -> Vec<(WindowId, Rc<WindowContext>, DrawMode)> {
         self.render_state
             .windows
-            .push(WindowWithAttrs { window, draw_mode });
+            .iter()
+            .map(|w| (w.window.id(), w.window.context(), w.draw_mode))
+            .collect::<Vec<_>>()
     }
 
-    fn get_windows(&mut self) -> Vec<(Rc<WindowContext>, DrawMode)> {
+    fn close_window(&mut self, window: WindowId) {
         self.render_state
+            .renderers
+            .retain(|r| r.window_id() != window);
+        self.render_state
+            .windows
+            .retain(|w| w.window.id() != window);
+    }
+
+    fn get_window_scale(&self, window: WindowId) -> f32 {
+        match self
+            .render_state
             .windows
             .iter()
-            .map(|w| (w.window.context(), w.draw_mode))
-            .collect::<Vec<_>>()
+            .find(|w| w.window.id() == window)
+        {
+            Some(w) => {
+                let (width, _) = w.window.size();
+                let opengl = w.window.window_flags() & SDL_WindowFlags::SDL_WINDOW_OPENGL as u32;
+                let (drawable, _) = if opengl != 0 {
+                    w.window.drawable_size()
+                } else {
+                    w.window.vulkan_drawable_size()
+                };
+                if width > 0 {
+                    drawable as f32 / width as f32
+                } else {
+                    1.0
+                }
+            }
+            None => 1.0,
+        }
+    }
+
+    fn set_window_camera(&mut self, window: WindowId, camera: ThingId) {
+        self.set_window_viewports(window, vec![Viewport::full(camera)]);
+    }
+
+    fn set_window_viewports(&mut self, window: WindowId, viewports: Vec<Viewport>) {
+        match self
+            .render_state
+            .windows
+            .iter_mut()
+            .find(|w| w.window.id() == window)
+        {
+            Some(w) => w.viewports = viewports,
+            None => println!("set_window_viewports: no window with id {}", window),
+        }
+    }
+
+    fn get_window_viewports(&self, window: WindowId) -> Vec<Viewport> {
+        self.render_state
+            .windows
+            .iter()
+            .find(|w| w.window.id() == window)
+            .map(|w| w.viewports.clone())
+            .unwrap_or_default()
+    }
+
+    fn set_window_draw_mode(&mut self, window: WindowId, draw_mode: DrawMode) {
+        match self
+            .render_state
+            .windows
+            .iter_mut()
+            .find(|w| w.window.id() == window)
+        {
+            Some(w) => w.draw_mode = draw_mode,
+            None => println!("set_window_draw_mode: no window with id {}", window),
+        }
+        for r in self.render_state.renderers.iter_mut() {
+            if r.window_id() == window {
+                r.set_draw_mode(draw_mode);
+            }
+        }
     }
 }
 
@@ -144,6 +416,14 @@ impl RenderLayerAccess for State {
     fn clear_render_layers(&mut self) {
         self.render_state.render_layers.clear();
     }
+
+    fn get_ui_layers(&self) -> &Vec<Arc<DrawList>> {
+        &self.render_state.ui_layers
+    }
+
+    fn set_ui_layers(&mut self, layers: Vec<Arc<DrawList>>) {
+        self.render_state.ui_layers = layers;
+    }
 }
 
 impl RenderAccess for State {
@@ -160,9 +440,50 @@ impl RenderAccess for State {
     }
 
     fn present_all(&mut self) {
-        let camera = &self.world.get_facets().cameras[0];
+        self.world.update_transforms();
+        let world = &self.world;
+        let windows = &self.render_state.windows;
+        let models = world
+            .query::<(&ModelInstanceFacet, &TransformFacet)>()
+            .map(|(thing, (instance, transform))| ModelView {
+                thing,
+                model: instance.model.clone(),
+                world: *transform.world_matrix(),
+            })
+            .collect::<Vec<_>>();
         for r in self.render_state.renderers.iter_mut() {
-            r.present(camera);
+            let viewports = windows
+                .iter()
+                .find(|w| w.window.id() == r.window_id())
+                .map(|w| w.viewports.as_slice())
+                .unwrap_or(&[]);
+
+            let views = if viewports.is_empty() {
+                // no layout configured for this window, fall back to the first camera
+                world
+                    .query::<(&CameraFacet, &TransformFacet)>()
+                    .next()
+                    .map(|(thing, (camera, transform))| CameraView {
+                        viewport: Viewport::full(thing),
+                        camera,
+                        view: transform.view_matrix(),
+                    })
+                    .into_iter()
+                    .collect::<Vec<_>>()
+            } else {
+                viewports
+                    .iter()
+                    .filter_map(|viewport| {
+                        let transform = world.get::<TransformFacet>(viewport.camera)?;
+                        world.camera_for(viewport.camera).map(|camera| CameraView {
+                            viewport: *viewport,
+                            camera,
+                            view: transform.view_matrix(),
+                        })
+                    })
+                    .collect::<Vec<_>>()
+            };
+            r.present(&views, &models);
         }
     }
 
@@ -184,6 +505,16 @@ impl RenderAccess for State {
             for r in &self.render_state.render_layers {
                 self.render_state.renderers[i].queue_render_layer(r.clone());
             }
+            // then the UI over them
+            let window = self.render_state.renderers[i].window_id() as Identity;
+            for ui in self
+                .render_state
+                .ui_layers
+                .iter()
+                .filter(|l| l.window == window)
+            {
+                self.render_state.renderers[i].queue_ui_layer(ui.clone());
+            }
         }
     }
 
@@ -203,31 +534,51 @@ impl RenderAccess for State {
 }
 
 impl InputAccess for State {
-    fn has_pending_input_events(&self) -> bool {
-        !self.input_state.get_input_events().is_empty()
+    fn register_input_reader(&mut self, reader: &str) {
+        self.input_state.register_reader(reader);
     }
 
-    fn clear_input_events(&mut self) {
-        self.input_state.clear();
+    fn unregister_input_reader(&mut self, reader: &str) {
+        self.input_state.unregister_reader(reader);
     }
 
-    fn get_input_events(&mut self) -> &VecDeque<InputEvent> {
-        &self.input_state.get_input_events()
+    fn set_input_reader_context(&mut self, reader: &str, context: Option<&str>) {
+        self.input_state.set_reader_context(reader, context);
     }
 
-    // Input events might also come from other subsystems, so we allow them to be queued as well
-    fn send_input_event(&mut self, event: InputEvent) -> Result<(), Box<dyn Error>> {
-        self.input_state.send(event)
+    fn has_pending_input_events(&self, reader: &str) -> bool {
+        self.input_state.has_unread(reader)
+    }
+
+    fn read_input_events(&mut self, reader: &str) -> Vec<InputEvent> {
+        self.input_state.read(reader)
     }
 
-    fn on_input_load(&mut self) {
-        self.input_state.clear();
+    fn read_all_input_events(&mut self, reader: &str) -> Vec<(InputEvent, bool)> {
+        self.input_state.read_all(reader)
     }
 
-    fn on_input_unload(&mut self) {
-        self.input_state.clear();
+    // Input events might also come from other subsystems (gamepad, network), so any mod may
+    // publish into the same stream. The UI sees every event first.
+    fn send_input_event(&mut self, event: InputEvent) -> Result<(), Box<dyn Error>> {
+        // the scale may change with the size, e.g. the window was moved to another display
+        if let InputEvent::Resized(window, ..) = event {
+            let scale = self.get_window_scale(window as WindowId);
+            self.ui_state.scene.set_host_scale(window, scale);
+        }
+        self.input_state.send(event, &mut self.ui_state)
+    }
+
+    fn end_input_frame(&mut self) {
+        self.input_state.end_frame(&mut self.ui_state);
     }
 
+    // Pending events are deliberately kept across an input mod reload, other readers may not
+    // have seen them yet.
+    fn on_input_load(&mut self) {}
+
+    fn on_input_unload(&mut self) {}
+
     fn get_mouse_pos(&self) -> &ScreenPoint {
         self.input_state.get_mouse_pos()
     }
@@ -235,6 +586,104 @@ impl InputAccess for State {
     fn set_mouse_pos(&mut self, sp: ScreenPoint) {
         self.input_state.set_mouse_pos(sp)
     }
+
+    fn get_action_map(&mut self) -> &mut ActionMap {
+        self.input_state.get_action_map()
+    }
+
+    fn take_changed_bindings(&mut self) -> Option<&ActionMap> {
+        self.input_state.take_changed_bindings()
+    }
+
+    fn get_action(&self, action: &str) -> ActionState {
+        self.input_state.get_action(action)
+    }
+
+    fn get_context_action(&self, context: &str, action: &str) -> ActionState {
+        self.input_state.get_context_action(context, action)
+    }
+
+    fn push_input_context(&mut self, context: InputContext) {
+        self.input_state.push_context(context);
+    }
+
+    fn pop_input_context(&mut self, name: &str) -> Option<InputContext> {
+        self.input_state.pop_context(name)
+    }
+
+    fn get_input_contexts(&self) -> &ContextStack {
+        self.input_state.get_contexts()
+    }
+
+    fn start_text_input(&mut self, window: WindowId) {
+        match self
+            .render_state
+            .windows
+            .iter_mut()
+            .find(|w| w.window.id() == window)
+        {
+            Some(w) => w.window.raise(),
+            None => println!("start_text_input: no window with id {}", window),
+        }
+        self.sdl_subsystems.video.text_input().start();
+        self.input_state.set_text_input_window(Some(window));
+    }
+
+    fn stop_text_input(&mut self) {
+        self.sdl_subsystems.video.text_input().stop();
+        self.input_state.set_text_input_window(None);
+    }
+
+    fn get_text_input_window(&self) -> Option<WindowId> {
+        self.input_state.get_text_input_window()
+    }
+
+    fn set_text_input_rect(&mut self, rect: ScreenRect) {
+        let rect = Rect::new(rect.x, rect.y, rect.w.max(0) as u32, rect.h.max(0) as u32);
+        self.sdl_subsystems.video.text_input().set_rect(rect);
+    }
+
+    fn get_pressed(&self) -> &PressedState {
+        self.input_state.get_pressed()
+    }
+
+    fn add_input_source(
+        &mut self,
+        name: &str,
+        source: Box<dyn InputSource>,
+    ) -> Result<(), Box<dyn Error>> {
+        let window = self.get_focused_window();
+        self.input_state
+            .add_source(name, source, window, &mut self.ui_state)
+    }
+
+    fn remove_input_source(&mut self, name: &str) -> Result<(), Box<dyn Error>> {
+        let window = self.get_focused_window();
+        self.input_state
+            .remove_source(name, window, &mut self.ui_state)
+    }
+
+    fn poll_input_source(&mut self, name: &str) -> Result<(), Box<dyn Error>> {
+        let window = self.get_focused_window();
+        self.input_state
+            .poll_source(name, window, &mut self.ui_state)
+    }
+
+    fn get_focused_window(&self) -> Identity {
+        self.sdl_context.keyboard().focused_window_id().unwrap_or(0) as Identity
+    }
+
+    fn get_joysticks(&self) -> Vec<DeviceId> {
+        self.input_state.get_joysticks()
+    }
+
+    fn get_input_profiles(&mut self) -> &mut InputProfiles {
+        self.input_state.get_input_profiles()
+    }
+
+    fn take_changed_input_profiles(&mut self) -> Option<InputProfiles> {
+        self.input_state.take_changed_profiles()
+    }
 }
 
 impl UIAccess for State {
@@ -246,11 +695,440 @@ impl UIAccess for State {
         self.ui_state.pending_ui_events.push_back(event);
     }
 
+    fn get_ui_scene(&mut self) -> &mut UIScene {
+        &mut self.ui_state.scene
+    }
+
+    fn take_ui_events(&mut self) {
+        for window in self.ui_state.scene.windows_mut() {
+            window.deliver_clicks();
+        }
+        let events = self.ui_state.scene.take_events();
+        self.ui_state.pending_ui_callbacks.clear();
+        for event in events.iter() {
+            self.bind_ui_event(event);
+        }
+        self.ui_state.pending_ui_events = events.into_iter().collect();
+        self.sync_ui_bindings();
+    }
+
+    fn has_ui_documents(&self) -> bool {
+        !self.ui_state.documents.is_empty()
+    }
+
+    fn pending_ui_callbacks(&mut self) -> &VecDeque<UICallback> {
+        &self.ui_state.pending_ui_callbacks
+    }
+
+    fn load_ui_document(&mut self, path: &str) -> Result<(), Box<dyn Error>> {
+        let document = UIDocument::load(path).map_err(|e| format!("{}: {}", path, e))?;
+        let built = document.build().map_err(|e| format!("{}: {}", path, e))?;
+        if let Some(font) = &document.font {
+            if self.ui_state.fonts.get(font).is_none() {
+                self.load_font(font, font)?;
+            }
+            self.ui_state.fonts.set_default(font);
+        }
+
+        let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+        let i = self.ui_state.documents.iter().position(|d| d.path == path);
+        if let Some(old) = i.map(|i| self.ui_state.documents.remove(i)) {
+            for window in old.windows {
+                self.ui_state.scene.remove_window(window);
+                self.ui_state.bindings.retain(|_, b| b.window != window);
+            }
+        }
+
+        let host = self
+            .render_state
+            .windows
+            .first()
+            .map(|w| w.window.id() as Identity);
+        let mut windows = Vec::new();
+        for mut built in built {
+            built.window.host = host;
+            let window_tag = built.window.tag.clone();
+            let tags = built
+                .bindings
+                .iter()
+                .map(|(id, _)| built.window.widgets.get(*id).map(|w| w.tag().to_string()))
+                .collect::<Vec<_>>();
+            let window = self.ui_state.scene.add_window(built.window);
+            for ((widget, binding), tag) in built.bindings.into_iter().zip(tags) {
+                let bound = BoundWidget {
+                    window,
+                    window_tag: window_tag.clone(),
+                    widget_tag: tag.unwrap_or_default(),
+                    binding,
+                };
+                self.ui_state.bindings.insert(widget, bound);
+            }
+            windows.push(window);
+        }
+        self.ui_state.documents.push(LoadedDocument {
+            path: path.to_string(),
+            modified,
+            windows,
+        });
+        // the variables shown decide what's visible from the first frame
+        self.sync_ui_bindings();
+        Ok(())
+    }
+
+    fn reload_ui_documents(&mut self) -> Vec<String> {
+        let now = Instant::now();
+        match self.ui_state.documents_checked {
+            Some(checked) if now.duration_since(checked) < DOCUMENT_CHECK_INTERVAL => {
+                return Vec::new()
+            }
+            _ => self.ui_state.documents_checked = Some(now),
+        }
+        let mut changed = Vec::new();
+        for document in self.ui_state.documents.iter_mut() {
+            let modified = fs::metadata(&document.path).and_then(|m| m.modified()).ok();
+            if modified.is_some() && modified != document.modified {
+                // a file with errors is tried again once it's saved again
+                document.modified = modified;
+                changed.push(document.path.clone());
+            }
+        }
+        changed
+            .iter()
+            .filter_map(|path| self.load_ui_document(path).err())
+            .map(|e| e.to_string())
+            .collect()
+    }
+
+    fn layout_ui(&mut self) {
+        let hosts = self
+            .render_state
+            .windows
+            .iter()
+            .map(|w| {
+                let id = w.window.id();
+                let (width, height) = w.window.size();
+                (
+                    id as Identity,
+                    width as f32,
+                    height as f32,
+                    self.get_window_scale(id),
+                )
+            })
+            .collect::<Vec<_>>();
+        for window in self.ui_state.scene.windows_mut() {
+            let host = hosts.iter().find(|(id, ..)| Some(*id) == window.host);
+            match host {
+                Some((_, width, height, scale))
+                    if window.needs_host_layout(*width, *height, *scale) =>
+                {
+                    window.layout_in_host(*width, *height, *scale)
+                }
+                _ => window.update_layout(),
+            }
+        }
+    }
+
+    fn draw_ui(&mut self) {
+        let mut lists = self.ui_state.scene.draw_lists();
+        for list in lists.iter_mut() {
+            self.ui_state.fonts.typeset(list);
+        }
+        self.render_state.ui_layers = lists.into_iter().map(Arc::new).collect();
+    }
+
+    fn load_font(&mut self, name: &str, path: &str) -> Result<(), Box<dyn Error>> {
+        let font = Font::load(path)?;
+        self.ui_state.fonts.add(name, font);
+        Ok(())
+    }
+
+    fn has_fonts(&self) -> bool {
+        !self.ui_state.fonts.is_empty()
+    }
+
+    fn add_debug_text(&mut self, line: &str) {
+        self.ui_state.debug_lines.push(line.to_string());
+    }
+
+    fn take_debug_text(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.ui_state.debug_lines)
+    }
+
+    fn get_ui_fps(&mut self) -> &mut FPS {
+        &mut self.ui_state.fps
+    }
+
+    // windows and their widgets are plain data and stay open, only a new layout is needed in
+    // case the reloaded code lays them out differently
     fn on_ui_load(&mut self) {
-        unimplemented!()
+        for window in self.ui_state.scene.windows_mut() {
+            window.widgets.invalidate_layout();
+        }
     }
 
-    fn on_ui_unload(&mut self) {
-        unimplemented!()
+    // handlers are dropped by whichever mod unloads, see `State::on_mod_unload`
+    fn on_ui_unload(&mut self) {}
+}
+
+impl MessageAccess for State {
+    fn open_inbox(&mut self, owner: &str) {
+        self.post_office.open_inbox(owner);
+    }
+
+    fn send_message<M: Message>(&mut self, from: &str, to: &str, message: &M) -> u64 {
+        let to = Recipient::Mod(to.to_string());
+        self.post_office.send(from, to, message)
+    }
+
+    fn broadcast_message<M: Message>(&mut self, from: &str, message: &M) -> u64 {
+        self.post_office.send(from, Recipient::Broadcast, message)
+    }
+
+    fn reply_to_message<M: Message>(
+        &mut self,
+        from: &str,
+        original: &Envelope,
+        message: &M,
+    ) -> u64 {
+        self.post_office.reply(from, original, message)
+    }
+
+    fn take_messages(&mut self, owner: &str) -> Vec<Envelope> {
+        self.post_office.take(owner)
+    }
+
+    fn deliver_messages(&mut self) {
+        self.post_office.deliver();
+    }
+}
+
+impl ConsoleAccess for State {
+    fn get_console(&mut self) -> &mut Console {
+        &mut self.ui_state.console
+    }
+
+    fn register_console_command(&mut self, command: Command) -> Result<(), String> {
+        self.ui_state.console.register(command)
+    }
+
+    fn print_to_console(&mut self, text: &str) {
+        self.ui_state.console.print(text);
+    }
+
+    fn take_console_commands(&mut self, owner: &str) -> Vec<Invocation> {
+        self.ui_state.console.take_queued(owner)
+    }
+
+    fn run_console(&mut self) {
+        // variables come and go, keep their names completing
+        let mut names = self
+            .variables
+            .keys()
+            .map(|k| k.to_string())
+            .collect::<Vec<_>>();
+        names.sort();
+        self.ui_state.console.set_choices("get", 0, names.clone());
+        self.ui_state.console.set_choices("set", 0, names);
+
+        for line in self.ui_state.console.take_entered() {
+            let result = match self.ui_state.console.parse_line(&line) {
+                Ok((owner, invocation)) if owner == BUILTIN => self.run_builtin(&invocation),
+                Ok((owner, invocation)) => {
+                    self.ui_state.console.queue(&owner, invocation);
+                    Ok(())
+                }
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                self.ui_state.console.print(&e);
+            }
+        }
+    }
+}
+
+impl State {
+    /// Variables are only set when State has them, with a value of the same kind, their keys
+    /// are static
+    fn set_existing(&mut self, name: &str, value: Variable) -> bool {
+        let same_kind = |v: &Variable| mem::discriminant(v) == mem::discriminant(&value);
+        let key = self
+            .variables
+            .iter()
+            .find(|(k, v)| **k == name && same_kind(v))
+            .map(|(k, _)| *k);
+        match key {
+            Some(key) => {
+                self.variables.insert(key, value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Queues the callback bound to the event's widget, and sets a variable bound to it
+    fn bind_ui_event(&mut self, event: &UIEvent) {
+        let bound = match self.ui_state.bindings.get(&event.target()) {
+            Some(bound) => bound,
+            None => return,
+        };
+        let binding = &bound.binding;
+        let (name, value) = match event {
+            UIEvent::Clicked(_) => (&binding.on_click, CallbackValue::None),
+            UIEvent::Toggled(_, checked) => (&binding.on_change, CallbackValue::Bool(*checked)),
+            UIEvent::ValueChanged(_, value) => (&binding.on_change, CallbackValue::Number(*value)),
+            UIEvent::TextChanged(_, text) => {
+                (&binding.on_change, CallbackValue::Text(text.clone()))
+            }
+            UIEvent::Submitted(_, text) => (&binding.on_submit, CallbackValue::Text(text.clone())),
+            _ => return,
+        };
+        let variable = binding.variable.clone();
+        if let Some(name) = name {
+            let callback = UICallback {
+                name: name.clone(),
+                window: bound.window_tag.clone(),
+                widget: bound.widget_tag.clone(),
+                value: value.clone(),
+            };
+            self.ui_state.pending_ui_callbacks.push_back(callback);
+        }
+        let value = match value {
+            CallbackValue::None => return,
+            CallbackValue::Bool(v) => Variable::Bool(v),
+            CallbackValue::Number(v) => Variable::Number(v),
+            CallbackValue::Text(v) => Variable::Text(v),
+        };
+        if let Some(variable) = variable {
+            self.set_existing(&variable, value);
+        }
+    }
+
+    /// Shows the bound variables in their widgets, and the widgets they show
+    fn sync_ui_bindings(&mut self) {
+        let variables = &self.variables;
+        let value = |name: &Option<String>| name.as_deref().and_then(|name| variables.get(name));
+        let scene = &mut self.ui_state.scene;
+        for (id, bound) in self.ui_state.bindings.iter() {
+            let widgets = match scene.window_mut(bound.window) {
+                Some(window) => &mut window.widgets,
+                None => continue,
+            };
+            let widget = match widgets.get_mut(*id) {
+                Some(widget) => widget,
+                None => continue,
+            };
+            let mut changed = false;
+            if let Some(Variable::Bool(visible)) = value(&bound.binding.show_if) {
+                changed |= widget.visible != *visible;
+                widget.visible = *visible;
+            }
+            if let Some(variable) = value(&bound.binding.variable) {
+                changed |= show_variable(variable, &mut widget.kind);
+            }
+            if changed {
+                widgets.invalidate_layout();
+            }
+        }
+    }
+
+    fn run_builtin(&mut self, invocation: &Invocation) -> Result<(), String> {
+        let mut output = Vec::new();
+        match invocation.command.as_str() {
+            "help" => match invocation.text(0) {
+                Some(name) => {
+                    let command = self
+                        .ui_state
+                        .console
+                        .command(name)
+                        .ok_or_else(|| format!("unknown command: {}", name))?;
+                    output.push(command.usage());
+                    output.push(format!("  {}", command.help));
+                }
+                None => {
+                    for command in self.ui_state.console.commands() {
+                        output.push(format!("{:<12}{}", command.name, command.help));
+                    }
+                }
+            },
+            "clear" => self.ui_state.console.clear_output(),
+            "vars" => {
+                let mut vars = self
+                    .variables
+                    .iter()
+                    .map(|(k, v)| format!("{} = {}", k, v))
+                    .collect::<Vec<_>>();
+                vars.sort();
+                output.extend(vars);
+            }
+            "get" => {
+                let name = invocation.text(0).unwrap_or_default();
+                let value = self
+                    .variables
+                    .get(name)
+                    .ok_or_else(|| format!("no variable {}", name))?;
+                output.push(format!("{} = {}", name, value));
+            }
+            "set" => {
+                // keys are static, only variables State already has can be set
+                let name = invocation.text(0).unwrap_or_default();
+                let typed = invocation.text(1).unwrap_or_default();
+                let current = self
+                    .variables
+                    .get(name)
+                    .ok_or_else(|| format!("no variable {}", name))?;
+                let value = current
+                    .parse_like(typed)
+                    .ok_or_else(|| format!("{} is a {}, not {}", name, current.kind(), typed))?;
+                output.push(format!("{} = {}", name, value));
+                self.set_existing(name, value);
+            }
+            "things" => {
+                for thing in self.world.get_things() {
+                    let thing = thing.lock().map_err(|e| e.to_string())?;
+                    output.push(format!("{}: {:?}", thing.id, thing.facets()));
+                }
+                output.push(format!("{} things", self.world.get_things().len()));
+            }
+            "scene" => {
+                for (i, layer) in self.render_state.render_layers.iter().enumerate() {
+                    output.push(format!("render layer {}", i));
+                    output.extend(layer.root.borrow().debug_lines(0));
+                }
+            }
+            "drawmode" => {
+                let windows = self
+                    .render_state
+                    .windows
+                    .iter()
+                    .map(|w| (w.window.id(), w.draw_mode))
+                    .filter(|(id, _)| match invocation.int(1) {
+                        Some(window) => *id as i64 == window,
+                        None => true,
+                    })
+                    .collect::<Vec<_>>();
+                if windows.is_empty() {
+                    return Err("no such window".to_string());
+                }
+                for (window, current) in windows {
+                    let draw_mode = match invocation.text(0) {
+                        Some("wireframe") => DrawMode::Wireframe(WIREFRAME_WIDTH),
+                        Some("points") => DrawMode::Points,
+                        Some("textured") => DrawMode::Textured,
+                        _ => match current {
+                            DrawMode::Wireframe(_) => DrawMode::Points,
+                            DrawMode::Points => DrawMode::Textured,
+                            DrawMode::Textured => DrawMode::Wireframe(WIREFRAME_WIDTH),
+                        },
+                    };
+                    self.set_window_draw_mode(window, draw_mode);
+                    output.push(format!("window {}: {:?}", window, draw_mode));
+                }
+            }
+            other => return Err(format!("{} isn't a built-in command", other)),
+        }
+        for line in output {
+            self.ui_state.console.print(&line);
+        }
+        Ok(())
     }
 }
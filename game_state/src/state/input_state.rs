@@ -1,32 +1,124 @@
-use crate::input::events::InputEvent;
+use crate::event::EventChannel;
+use crate::input::actions::{ActionMap, ActionState};
+use crate::input::contexts::{ContextStack, InputContext};
+use crate::input::events::{DeviceId, InputEvent};
+use crate::input::pressed::PressedState;
+use crate::input::processing::{InputProcessor, InputProfiles};
 use crate::input::screen::ScreenPoint;
-use std::collections::VecDeque;
+use crate::input::{InputConsumer, InputSource};
+use crate::state::WindowId;
+use crate::Identity;
+use std::collections::{BTreeSet, HashMap};
 use std::error::Error;
 
 pub struct InputState {
-    events: VecDeque<InputEvent>,
+    /// Events, and whether they were consumed
+    events: EventChannel<(InputEvent, bool)>,
     mouse_pos: ScreenPoint,
+    actions: ActionMap,
+    /// The bindings last taken, in the config format, to tell when they change
+    taken_bindings: String,
+    pressed: PressedState,
+    sources: Vec<(String, Box<dyn InputSource>)>,
+    joysticks: BTreeSet<DeviceId>,
+    processor: InputProcessor,
+    /// The profiles last taken, to tell when they change
+    taken_profiles: InputProfiles,
+    contexts: ContextStack,
+    /// Readers only receiving input while their context does
+    reader_contexts: HashMap<String, String>,
+    text_input: Option<WindowId>,
 }
 
 impl Default for InputState {
     fn default() -> Self {
         InputState {
-            events: Default::default(),
+            events: EventChannel::new(),
             mouse_pos: ScreenPoint::new(0, 0),
+            actions: ActionMap::new(),
+            taken_bindings: String::new(),
+            pressed: PressedState::new(),
+            sources: Vec::new(),
+            joysticks: BTreeSet::new(),
+            processor: InputProcessor::new(),
+            taken_profiles: InputProfiles::new(),
+            contexts: ContextStack::new(),
+            reader_contexts: HashMap::new(),
+            text_input: None,
         }
     }
 }
 
 impl InputState {
-    pub fn clear(&mut self) {
-        self.events.clear();
+    pub fn register_reader(&mut self, reader: &str) {
+        self.events.register_reader(reader);
     }
-    pub fn send(&mut self, event: InputEvent) -> Result<(), Box<dyn Error>> {
-        self.events.push_back(event);
+    pub fn unregister_reader(&mut self, reader: &str) {
+        self.events.unregister_reader(reader);
+        self.reader_contexts.remove(reader);
+    }
+    pub fn set_reader_context(&mut self, reader: &str, context: Option<&str>) {
+        match context {
+            Some(context) => self
+                .reader_contexts
+                .insert(reader.to_string(), context.to_string()),
+            None => self.reader_contexts.remove(reader),
+        };
+    }
+    pub fn send(
+        &mut self,
+        event: InputEvent,
+        consumer: &mut dyn InputConsumer,
+    ) -> Result<(), Box<dyn Error>> {
+        for event in self.processor.process(event) {
+            self.publish(event, consumer);
+        }
         Ok(())
     }
-    pub fn get_input_events(&self) -> &VecDeque<InputEvent> {
-        &self.events
+    fn publish(&mut self, event: InputEvent, consumer: &mut dyn InputConsumer) {
+        let consumed = consumer.consume(&event);
+        // a consumed press isn't held, its release is never consumed so it's a no-op
+        if !consumed {
+            self.pressed.apply(&event);
+        }
+        match event {
+            InputEvent::JoyConnected(_, device, _) => {
+                self.joysticks.insert(device);
+            }
+            InputEvent::JoyDisconnected(_, device) => {
+                self.joysticks.remove(&device);
+            }
+            _ => {}
+        }
+        self.events.publish((event, consumed));
+    }
+    /// Events nothing consumed, less the input the reader's context doesn't receive
+    pub fn read(&mut self, reader: &str) -> Vec<InputEvent> {
+        let blocked = match self.reader_contexts.get(reader) {
+            Some(context) => !self.contexts.receives_events(context),
+            None => false,
+        };
+        self.events
+            .read(reader)
+            .into_iter()
+            .filter(|(event, consumed)| !consumed && (!blocked || !event.is_press()))
+            .map(|(event, _)| event)
+            .collect()
+    }
+    pub fn read_all(&mut self, reader: &str) -> Vec<(InputEvent, bool)> {
+        self.events.read(reader)
+    }
+    pub fn has_unread(&self, reader: &str) -> bool {
+        self.events.has_unread(reader)
+    }
+    pub fn end_frame(&mut self, consumer: &mut dyn InputConsumer) {
+        self.events.end_frame();
+        self.pressed.end_frame();
+
+        // smoothed axes move once per frame, these are read next frame
+        for event in self.processor.end_frame() {
+            self.publish(event, consumer);
+        }
     }
 
     pub fn get_mouse_pos(&self) -> &ScreenPoint {
@@ -36,4 +128,184 @@ impl InputState {
     pub fn set_mouse_pos(&mut self, sp: ScreenPoint) {
         self.mouse_pos = sp;
     }
+
+    pub fn get_action_map(&mut self) -> &mut ActionMap {
+        &mut self.actions
+    }
+
+    pub fn take_changed_bindings(&mut self) -> Option<&ActionMap> {
+        let bindings = self.actions.to_config();
+        if bindings == self.taken_bindings {
+            return None;
+        }
+        self.taken_bindings = bindings;
+        Some(&self.actions)
+    }
+
+    pub fn get_action(&self, action: &str) -> ActionState {
+        self.actions.get_state(action)
+    }
+
+    pub fn get_context_action(&self, context: &str, action: &str) -> ActionState {
+        if self.contexts.owner(action) == Some(context) {
+            self.actions.get_state(action)
+        } else {
+            ActionState::default()
+        }
+    }
+
+    pub fn push_context(&mut self, context: InputContext) {
+        self.contexts.push(context);
+    }
+
+    pub fn pop_context(&mut self, name: &str) -> Option<InputContext> {
+        self.contexts.pop(name)
+    }
+
+    pub fn get_contexts(&self) -> &ContextStack {
+        &self.contexts
+    }
+
+    pub fn get_text_input_window(&self) -> Option<WindowId> {
+        self.text_input
+    }
+
+    pub fn set_text_input_window(&mut self, window: Option<WindowId>) {
+        self.text_input = window;
+    }
+
+    pub fn get_pressed(&self) -> &PressedState {
+        &self.pressed
+    }
+
+    /// Replaces, after removing, any source previously added under the same name
+    pub fn add_source(
+        &mut self,
+        name: &str,
+        source: Box<dyn InputSource>,
+        window: Identity,
+        consumer: &mut dyn InputConsumer,
+    ) -> Result<(), Box<dyn Error>> {
+        self.remove_source(name, window, consumer)?;
+        self.sources.push((name.to_string(), source));
+        Ok(())
+    }
+
+    /// `window` is the window with keyboard focus, which the source's events are tagged with
+    pub fn remove_source(
+        &mut self,
+        name: &str,
+        window: Identity,
+        consumer: &mut dyn InputConsumer,
+    ) -> Result<(), Box<dyn Error>> {
+        if let Some(i) = self.sources.iter().position(|(n, _)| n == name) {
+            let (_, mut source) = self.sources.remove(i);
+            for event in source.unload(window) {
+                self.send(event, consumer)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn poll_source(
+        &mut self,
+        name: &str,
+        window: Identity,
+        consumer: &mut dyn InputConsumer,
+    ) -> Result<(), Box<dyn Error>> {
+        let events = match self.sources.iter_mut().find(|(n, _)| n == name) {
+            Some((_, source)) => source.get_input_events(window),
+            None => return Ok(()),
+        };
+        for event in events {
+            self.send(event, consumer)?;
+        }
+        Ok(())
+    }
+
+    pub fn get_input_profiles(&mut self) -> &mut InputProfiles {
+        self.processor.get_profiles()
+    }
+
+    pub fn take_changed_profiles(&mut self) -> Option<InputProfiles> {
+        let profiles = self.processor.get_profiles();
+        if *profiles == self.taken_profiles {
+            return None;
+        }
+        self.taken_profiles = profiles.clone();
+        Some(profiles.clone())
+    }
+
+    pub fn get_joysticks(&self) -> Vec<DeviceId> {
+        self.joysticks.iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct ConsumesNothing;
+    impl InputConsumer for ConsumesNothing {
+        fn consume(&mut self, _event: &InputEvent) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn readers_miss_presses_while_their_context_is_blocked() {
+        let mut state = InputState::default();
+        state.register_reader("gameplay");
+        state.register_reader("any");
+        state.set_reader_context("gameplay", Some("gameplay"));
+        state.push_context(InputContext::new("gameplay", &["jump"]));
+        state.push_context(InputContext::new("console", &[]).blocking());
+
+        state
+            .send(InputEvent::KeyDown(0, 32), &mut ConsumesNothing)
+            .unwrap();
+        state
+            .send(InputEvent::KeyUp(0, 32), &mut ConsumesNothing)
+            .unwrap();
+        state
+            .send(InputEvent::QuitRequested, &mut ConsumesNothing)
+            .unwrap();
+
+        let gameplay = state.read("gameplay");
+        assert_eq!(gameplay.len(), 2);
+        assert!(matches!(gameplay[0], InputEvent::KeyUp(0, 32)));
+        assert!(matches!(gameplay[1], InputEvent::QuitRequested));
+        assert_eq!(state.read("any").len(), 3);
+
+        state.pop_context("console");
+        state
+            .send(InputEvent::KeyDown(0, 32), &mut ConsumesNothing)
+            .unwrap();
+        assert_eq!(state.read("gameplay").len(), 1);
+    }
+
+    #[test]
+    fn changed_profiles_are_taken_once() {
+        let mut state = InputState::default();
+        assert!(state.take_changed_profiles().is_none());
+
+        state.get_input_profiles().mouse.x.invert = true;
+        let taken = state.take_changed_profiles().expect("profiles changed");
+        assert!(taken.mouse.x.invert);
+        assert!(state.take_changed_profiles().is_none());
+    }
+
+    #[test]
+    fn changed_bindings_are_taken_once() {
+        let mut state = InputState::default();
+        *state.get_action_map() = ActionMap::parse("digital jump = key:Space").unwrap();
+        assert!(state.take_changed_bindings().is_some());
+        assert!(state.take_changed_bindings().is_none());
+
+        let binding = "key:J".parse().unwrap();
+        state.get_action_map().bind("jump", binding).unwrap();
+        let taken = state.take_changed_bindings().expect("bindings changed");
+        assert_eq!(taken.get_bindings("jump").len(), 2);
+        assert!(state.take_changed_bindings().is_none());
+    }
 }
@@ -1,15 +1,20 @@
 use std::collections::HashMap;
+use std::fmt;
 
 use super::model::Model;
 use super::Renderer;
+use crate::message::PostOffice;
 use crate::thing::World;
+use crate::ui::console::parse_bool;
 
 pub use self::access::{
-    InputAccess, ModelAccess, RenderAccess, RenderLayerAccess, VariableAccess, WindowAccess,
-    WorldAccess,
+    ConsoleAccess, InputAccess, MessageAccess, ModelAccess, RenderAccess, RenderLayerAccess,
+    UIAccess, VariableAccess, WindowAccess, WorldAccess,
 };
 pub use self::input_state::InputState;
-pub use self::render_state::{DrawMode, RenderState, SceneGraph};
+pub use self::render_state::{
+    CameraView, DrawMode, ModelView, RenderState, SceneGraph, Viewport, WindowId,
+};
 pub use self::simulation_state::SimulationState;
 use self::ui_state::UIState;
 
@@ -26,6 +31,10 @@ pub struct State {
     pub sdl_context: sdl2::Sdl,
     pub sdl_subsystems: SdlSubsystems,
 
+    /// Made along with State so gamepads stay connected while mod_gamepad is reloaded, gilrs
+    /// runs a thread of its own code. None when gamepads are unavailable.
+    pub gilrs: Option<gilrs::Gilrs>,
+
     /// Root container of the Thing/Facet system (game world state)
     world: World,
 
@@ -38,15 +47,51 @@ pub struct State {
     /// Container for all UI related state
     ui_state: UIState,
 
+    /// Inboxes of messages between mods
+    post_office: PostOffice,
+
     // TEMPORARY container for general purpose state variables
     pub variables: HashMap<&'static str, Variable>,
 
     pub simulation_state: SimulationState,
 }
 
-#[derive(Copy, Clone)]
+/// A state variable, UI document widgets bound to one show it and change it
+#[derive(Debug, Clone, PartialEq)]
 pub enum Variable {
     Bool(bool),
+    Number(f32),
+    Text(String),
+}
+
+impl Variable {
+    /// bool, number or text
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Variable::Bool(_) => "bool",
+            Variable::Number(_) => "number",
+            Variable::Text(_) => "text",
+        }
+    }
+
+    /// Reads a value of the same kind, e.g. typed in the console
+    pub fn parse_like(&self, value: &str) -> Option<Variable> {
+        match self {
+            Variable::Bool(_) => parse_bool(value).map(Variable::Bool),
+            Variable::Number(_) => value.parse().ok().map(Variable::Number),
+            Variable::Text(_) => Some(Variable::Text(value.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Variable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Variable::Bool(v) => write!(f, "{}", v),
+            Variable::Number(v) => write!(f, "{}", v),
+            Variable::Text(v) => write!(f, "{}", v),
+        }
+    }
 }
 
 pub struct SdlSubsystems {
@@ -54,20 +99,61 @@ pub struct SdlSubsystems {
     pub event_pump: sdl2::EventPump,
 }
 
+impl State {
+    /// Called by the main loop after a mod's unload, before its code is gone, to drop anything
+    /// State keeps that was made by the mod's code
+    pub fn on_mod_unload(&mut self, name: &str) {
+        self.world.forget_decoded();
+        self.ui_state.scene.remove_handlers(name);
+    }
+}
+
 impl Default for State {
     fn default() -> Self {
         let ctx = sdl2::init().expect("unable to create sdl2 context");
         let video = ctx.video().expect("unable to create video subsystem");
         let event_pump = ctx.event_pump().expect("unable to create event pump");
+        let gilrs = match gilrs::Gilrs::new() {
+            Ok(gilrs) => Some(gilrs),
+            Err(err) => {
+                println!("unable to create gilrs context {}", err);
+                None
+            }
+        };
         Self {
             sdl_context: ctx,
             sdl_subsystems: SdlSubsystems { video, event_pump },
+            gilrs,
             world: Default::default(),
             render_state: Default::default(),
             input_state: Default::default(),
             simulation_state: Default::default(),
             ui_state: Default::default(),
+            post_office: Default::default(),
             variables: HashMap::new(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_are_read_as_the_variables_kind() {
+        let (flag, number, text) = (
+            Variable::Bool(false),
+            Variable::Number(0.0),
+            Variable::Text(String::new()),
+        );
+        assert_eq!(flag.parse_like("on"), Some(Variable::Bool(true)));
+        assert_eq!(flag.parse_like("2.5"), None);
+        assert_eq!(number.parse_like("2.5"), Some(Variable::Number(2.5)));
+        assert_eq!(number.parse_like("on"), None);
+        assert_eq!(number.kind(), "number");
+        assert_eq!(
+            text.parse_like("on"),
+            Some(Variable::Text("on".to_string()))
+        );
+    }
+}
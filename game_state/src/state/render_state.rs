@@ -1,25 +1,113 @@
 use std::sync::Arc;
 
+use nalgebra::Matrix4;
 use sdl2::video::Window;
 
 use super::{Model, Renderer};
+use crate::thing::{CameraFacet, ThingId};
 use crate::tree::RcNode;
+use crate::ui::draw::DrawList;
 
+/// SDL's id for a window, as reported by `Window::id()` and carried by SDL window events
+pub type WindowId = u32;
+
+/// Things drawn together, e.g. a level or an overlay. Nodes refer to things with a model, which
+/// are placed by their TransformFacet.
 #[derive(Default)]
-pub struct SceneGraph<T = Option<Arc<Model>>> {
+pub struct SceneGraph<T = Option<ThingId>> {
     pub root: RcNode<T>,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum DrawMode {
     Wireframe(f32),
     Points,
     Textured,
 }
 
+/// A region of a window rendered from the point of view of a single camera thing.
+/// Coordinates are normalized (0.0 - 1.0) so a layout survives the window being resized.
+#[derive(Debug, Copy, Clone)]
+pub struct Viewport {
+    /// The thing whose CameraFacet this viewport renders from
+    pub camera: ThingId,
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+}
+
+impl Viewport {
+    pub fn new(camera: ThingId, x: f32, y: f32, w: f32, h: f32) -> Self {
+        Viewport { camera, x, y, w, h }
+    }
+
+    /// A viewport covering the whole window
+    pub fn full(camera: ThingId) -> Self {
+        Viewport::new(camera, 0.0, 0.0, 1.0, 1.0)
+    }
+
+    /// Split a window into side-by-side columns, one per camera, left to right. Without
+    /// cameras there are no viewports.
+    pub fn split_horizontal(cameras: &[ThingId]) -> Vec<Self> {
+        if cameras.is_empty() {
+            return Vec::new();
+        }
+        let w = 1.0 / cameras.len() as f32;
+        cameras
+            .iter()
+            .enumerate()
+            .map(|(i, camera)| Viewport::new(*camera, i as f32 * w, 0.0, w, 1.0))
+            .collect()
+    }
+
+    /// Split a window into stacked rows, one per camera, top to bottom. Without cameras
+    /// there are no viewports.
+    pub fn split_vertical(cameras: &[ThingId]) -> Vec<Self> {
+        if cameras.is_empty() {
+            return Vec::new();
+        }
+        let h = 1.0 / cameras.len() as f32;
+        cameras
+            .iter()
+            .enumerate()
+            .map(|(i, camera)| Viewport::new(*camera, 0.0, i as f32 * h, 1.0, h))
+            .collect()
+    }
+
+    /// Pixel origin and dimensions of this viewport within a window of the given size
+    pub fn to_pixels(&self, width: u32, height: u32) -> ([f32; 2], [f32; 2]) {
+        let (width, height) = (width as f32, height as f32);
+        (
+            [self.x * width, self.y * height],
+            [self.w * width, self.h * height],
+        )
+    }
+}
+
+/// A viewport resolved against the world, handed to a Renderer when presenting
+pub struct CameraView<'a> {
+    pub viewport: Viewport,
+    pub camera: &'a CameraFacet,
+    /// The inverse of the camera thing's world matrix, see `TransformFacet::view_matrix`
+    pub view: Matrix4<f32>,
+}
+
+/// A thing with a model, handed to a Renderer to draw the scene graph nodes referring to it
+pub struct ModelView {
+    pub thing: ThingId,
+    pub model: Arc<Model>,
+    /// From the thing's TransformFacet
+    pub world: Matrix4<f32>,
+}
+
 pub struct WindowWithAttrs {
     pub window: Window,
     pub draw_mode: DrawMode,
+
+    /// Viewports rendered into this window, in order. When empty, the window is
+    /// rendered entirely from the first camera in the world.
+    pub viewports: Vec<Viewport>,
 }
 
 pub struct RenderState {
@@ -27,6 +115,8 @@ pub struct RenderState {
     pub windows: Vec<WindowWithAttrs>,
     pub renderers: Vec<Box<dyn Renderer>>,
     pub render_layers: Vec<Arc<SceneGraph>>,
+    /// Drawn over the render layers, one per window at most
+    pub ui_layers: Vec<Arc<DrawList>>,
 }
 
 impl Default for RenderState {
@@ -36,6 +126,37 @@ impl Default for RenderState {
             windows: Vec::new(),
             renderers: Vec::new(),
             render_layers: Vec::new(),
+            ui_layers: Vec::new(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_horizontal_covers_window() {
+        let cameras = (1..4).map(|i| ThingId::new(i, 1)).collect::<Vec<_>>();
+        let viewports = Viewport::split_horizontal(&cameras);
+        assert_eq!(viewports.len(), 3);
+        assert_eq!(viewports[1].camera, cameras[1]);
+        assert!((viewports[2].x - 2.0 / 3.0).abs() < 0.0001);
+        let total: f32 = viewports.iter().map(|v| v.w).sum();
+        assert!((total - 1.0).abs() < 0.0001);
+    }
+
+    #[test]
+    fn splitting_without_cameras_has_no_viewports() {
+        assert!(Viewport::split_horizontal(&[]).is_empty());
+        assert!(Viewport::split_vertical(&[]).is_empty());
+    }
+
+    #[test]
+    fn viewport_to_pixels() {
+        let (origin, dims) =
+            Viewport::new(ThingId::default(), 0.5, 0.0, 0.5, 1.0).to_pixels(800, 600);
+        assert_eq!(origin, [400.0, 0.0]);
+        assert_eq!(dims, [400.0, 600.0]);
+    }
+}
@@ -1,9 +1,138 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Instant, SystemTime};
 
+use crate::input::events::InputEvent;
+use crate::input::InputConsumer;
+use crate::state::Variable;
+use crate::ui::console::Console;
+use crate::ui::document::{Binding, UICallback};
 use crate::ui::events::UIEvent;
+use crate::ui::font::Fonts;
+use crate::ui::scene::UIScene;
+use crate::ui::widget::WidgetKind;
+use crate::utils::fps::FPS;
+use crate::Identity;
+
+/// A UI document's file, and the windows built from it
+pub struct LoadedDocument {
+    pub path: String,
+    /// When the file was changed, it's loaded again when that changes
+    pub modified: Option<SystemTime>,
+    pub windows: Vec<Identity>,
+}
+
+/// A widget from a UI document with callbacks or variables bound to it
+pub struct BoundWidget {
+    pub window: Identity,
+    pub window_tag: String,
+    pub widget_tag: String,
+    pub binding: Binding,
+}
 
 #[derive(Default)]
 pub struct UIState {
     pub pending_ui_events: VecDeque<UIEvent>,
-    //pub scene: SceneGraph
+    /// Every UI window, routing input to their widgets
+    pub scene: UIScene,
+    /// Text in the UI is drawn with these
+    pub fonts: Fonts,
+    pub fps: FPS,
+    /// Lines of debug text shown this frame, cleared as mod_gui draws them
+    pub debug_lines: Vec<String>,
+    pub console: Console,
+    pub documents: Vec<LoadedDocument>,
+    /// Widgets from documents with bindings, by widget id
+    pub bindings: HashMap<Identity, BoundWidget>,
+    /// Callbacks from document widgets this frame, like `pending_ui_events`
+    pub pending_ui_callbacks: VecDeque<UICallback>,
+    /// When documents were last checked for changes on disk
+    pub documents_checked: Option<Instant>,
+}
+
+// the console takes keys before the widgets, so typing in it doesn't also press them, but while
+// it's closed a focused text field gets every key, including the console's toggle key
+impl InputConsumer for UIState {
+    fn consume(&mut self, event: &InputEvent) -> bool {
+        let typing = !self.console.is_open() && self.scene.focused_text_field().is_some();
+        (!typing && self.console.consume(event)) || self.scene.consume(event)
+    }
+}
+
+/// Shows a bound variable in a widget, checkboxes, sliders and text fields show variables of
+/// their own kind, labels show any. Returns true when the widget's size may have changed.
+pub fn show_variable(variable: &Variable, kind: &mut WidgetKind) -> bool {
+    match (variable, kind) {
+        (Variable::Bool(v), WidgetKind::Checkbox { checked, .. }) => *checked = *v,
+        (Variable::Number(v), WidgetKind::Slider { min, max, value }) => {
+            *value = v.max(*min).min(*max)
+        }
+        (Variable::Text(v), WidgetKind::TextField { text, cursor, .. }) if text != v => {
+            *text = v.clone();
+            *cursor = (*cursor).min(text.chars().count());
+        }
+        (v, WidgetKind::Label { text }) if *text != v.to_string() => {
+            *text = v.to_string();
+            return true;
+        }
+        _ => {}
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use sdl2::keyboard::Keycode;
+
+    use crate::input::screen::ScreenRect;
+    use crate::ui::view::UIWindow;
+    use crate::ui::widget::Widget;
+
+    #[test]
+    fn text_fields_take_the_console_toggle_key() {
+        let mut ui = UIState::default();
+        let mut window = UIWindow::new("menu".to_string(), ScreenRect::new(0, 0, 200, 200));
+        let root = window.widgets.root();
+        let field = Widget::text_field("name", "").with_bounds(ScreenRect::new(0, 0, 100, 20));
+        let field = window.widgets.add(root, field).unwrap();
+        let window = ui.scene.add_window(window);
+        let toggle = InputEvent::KeyDown(0, Keycode::Backquote as i32 as u32);
+        let text = InputEvent::TextInput(0, "`".to_string());
+
+        ui.scene.set_focus(Some((window, field)));
+        assert!(ui.consume(&toggle));
+        assert!(ui.consume(&text));
+        assert!(!ui.console.is_open());
+        let typed = UIEvent::TextChanged(field, "`".to_string());
+        assert_eq!(ui.scene.take_events().last(), Some(&typed));
+
+        ui.scene.set_focus(None);
+        assert!(ui.consume(&toggle));
+        assert!(ui.consume(&text));
+        assert!(ui.console.is_open());
+    }
+
+    #[test]
+    fn variables_are_shown_in_widgets_of_their_kind() {
+        let mut slider = Widget::slider("volume", 0.0, 1.0, 0.5).kind;
+        assert!(!show_variable(&Variable::Number(2.0), &mut slider));
+        assert!(matches!(slider, WidgetKind::Slider { value, .. } if value == 1.0));
+        show_variable(&Variable::Bool(true), &mut slider);
+        assert!(matches!(slider, WidgetKind::Slider { value, .. } if value == 1.0));
+
+        let mut field = Widget::text_field("name", "").kind;
+        if let WidgetKind::TextField { cursor, .. } = &mut field {
+            *cursor = 10;
+        }
+        show_variable(&Variable::Text("abc".to_string()), &mut field);
+        assert!(
+            matches!(&field, WidgetKind::TextField { text, cursor, .. } if text == "abc" && *cursor == 3)
+        );
+
+        let mut label = Widget::label("count", "").kind;
+        assert!(show_variable(&Variable::Number(3.0), &mut label));
+        assert!(!show_variable(&Variable::Number(3.0), &mut label));
+        assert!(matches!(&label, WidgetKind::Label { text } if text == "3"));
+    }
 }
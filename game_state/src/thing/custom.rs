@@ -0,0 +1,366 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use super::{FacetId, FacetIndex, Slots};
+use crate::message::Value;
+
+//
+// Facet types registered at runtime, e.g. an InventoryFacet added by a gameplay mod, without
+// changing WorldFacets. Each type has its own store, found by the type's name.
+//
+// Like messages, custom facets are kept as plain data, a `Value`, so State holds no code from
+// the mod which registered them: they outlive reloads of the mod, which may add fields to its
+// type as long as `from_value` still reads the facets made before.
+//
+// Reading a facet decodes it once, the copy is kept until the facet changes. Copies are made by
+// the mod's code, so they're dropped whenever a mod unloads, see `forget_decoded`.
+//
+
+/// A registered facet type. Types are never unregistered, so a kind stays valid for the World.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct FacetKind(u32);
+
+/// A facet type defined outside of game_state, stored as its name and a `Value`
+pub trait CustomFacet: Clone + Send + 'static {
+    const NAME: &'static str;
+
+    fn to_value(&self) -> Value;
+    fn from_value(value: &Value) -> Result<Self, String>;
+}
+
+struct CustomStore {
+    name: String,
+    facets: Slots<Value>,
+    /// The facets read since they last changed, a `Decoded<F>` of the type read last
+    decoded: Mutex<Option<Box<dyn DecodedFacets>>>,
+}
+
+type Decoded<F> = HashMap<FacetId, F>;
+
+trait DecodedFacets: Send {
+    fn forget(&mut self, id: FacetId);
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<F: CustomFacet> DecodedFacets for Decoded<F> {
+    fn forget(&mut self, id: FacetId) {
+        self.remove(&id);
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl CustomStore {
+    fn forget(&mut self, id: FacetId) {
+        let decoded = self.decoded.get_mut().unwrap_or_else(|e| e.into_inner());
+        if let Some(decoded) = decoded.as_mut() {
+            decoded.forget(id);
+        }
+    }
+
+    fn forget_all(&mut self) {
+        *self.decoded.get_mut().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+}
+
+#[derive(Default)]
+pub struct CustomFacets {
+    kinds: HashMap<String, FacetKind>,
+    stores: Vec<CustomStore>,
+}
+
+impl CustomFacets {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// The type's kind, registering it the first time, e.g. a mod loaded again gets its kind back
+    pub fn register(&mut self, name: &str) -> FacetKind {
+        if let Some(kind) = self.kinds.get(name) {
+            return *kind;
+        }
+        let kind = FacetKind(self.stores.len() as u32);
+        self.stores.push(CustomStore {
+            name: name.to_string(),
+            facets: Slots::new(),
+            decoded: Mutex::new(None),
+        });
+        self.kinds.insert(name.to_string(), kind);
+        kind
+    }
+
+    pub fn kind(&self, name: &str) -> Option<FacetKind> {
+        self.kinds.get(name).copied()
+    }
+
+    pub fn name(&self, kind: FacetKind) -> Option<&str> {
+        self.stores.get(kind.0 as usize).map(|s| s.name.as_str())
+    }
+
+    /// Every registered type, in the order they were registered
+    pub fn kinds(&self) -> impl Iterator<Item = (FacetKind, &str)> {
+        self.stores
+            .iter()
+            .enumerate()
+            .map(|(i, store)| (FacetKind(i as u32), store.name.as_str()))
+    }
+
+    pub fn store(&self, kind: FacetKind) -> Option<&Slots<Value>> {
+        self.stores.get(kind.0 as usize).map(|s| &s.facets)
+    }
+
+    /// Any facet of the type may change, so none stay decoded
+    pub fn store_mut(&mut self, kind: FacetKind) -> Option<&mut Slots<Value>> {
+        let store = self.stores.get_mut(kind.0 as usize)?;
+        store.forget_all();
+        Some(&mut store.facets)
+    }
+
+    pub fn insert(&mut self, kind: FacetKind, value: Value) -> Option<FacetId> {
+        let store = self.stores.get_mut(kind.0 as usize)?;
+        Some(store.facets.insert(value))
+    }
+
+    pub fn value_mut(&mut self, kind: FacetKind, id: FacetId) -> Option<&mut Value> {
+        let store = self.stores.get_mut(kind.0 as usize)?;
+        store.forget(id);
+        store.facets.get_mut(id)
+    }
+
+    pub fn remove(&mut self, kind: FacetKind, id: FacetId) -> Option<Value> {
+        let store = self.stores.get_mut(kind.0 as usize)?;
+        store.forget(id);
+        store.facets.remove(id)
+    }
+
+    /// The facet read by `from_value`, decoded only the first time it's read after it changed
+    pub fn decode<F: CustomFacet>(
+        &self,
+        kind: FacetKind,
+        id: FacetId,
+    ) -> Option<Result<F, String>> {
+        let store = self.stores.get(kind.0 as usize)?;
+        let value = store.facets.get(id)?;
+        let mut decoded = store.decoded.lock().unwrap_or_else(|e| e.into_inner());
+        // another type registered the same name, or nothing was read yet
+        let reuse = match decoded.as_mut() {
+            Some(decoded) => decoded.as_any_mut().is::<Decoded<F>>(),
+            None => false,
+        };
+        if !reuse {
+            *decoded = Some(Box::new(Decoded::<F>::new()));
+        }
+        let decoded = decoded
+            .as_mut()
+            .and_then(|d| d.as_any_mut().downcast_mut::<Decoded<F>>())?;
+        if let Some(facet) = decoded.get(&id) {
+            return Some(Ok(facet.clone()));
+        }
+        let facet = F::from_value(value);
+        if let Ok(facet) = &facet {
+            decoded.insert(id, facet.clone());
+        }
+        Some(facet)
+    }
+
+    /// Drops every decoded facet, e.g. before the mod whose code made them unloads
+    pub fn forget_decoded(&mut self) {
+        for store in self.stores.iter_mut() {
+            store.forget_all();
+        }
+    }
+
+    /// The facet of this type among a thing's facets
+    pub fn find(kind: FacetKind, facets: &[FacetIndex]) -> Option<FacetId> {
+        facets.iter().find_map(|facet| match facet {
+            FacetIndex::Custom(k, id) if *k == kind => Some(*id),
+            _ => None,
+        })
+    }
+
+    /// The value of the facet with this name among a thing's facets
+    pub fn value(&self, name: &str, facets: &[FacetIndex]) -> Option<&Value> {
+        let kind = self.kind(name)?;
+        self.store(kind)?.get(Self::find(kind, facets)?)
+    }
+
+    /// Removes every facet, the types stay registered
+    pub fn clear(&mut self) {
+        for store in self.stores.iter_mut() {
+            store.facets.clear();
+            store.forget_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::thing::{Custom, World};
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct InventoryFacet {
+        items: Vec<String>,
+    }
+
+    impl CustomFacet for InventoryFacet {
+        const NAME: &'static str = "inventory";
+
+        fn to_value(&self) -> Value {
+            Value::map().with("items", self.items.clone())
+        }
+
+        fn from_value(value: &Value) -> Result<Self, String> {
+            let items = value
+                .field("items")?
+                .as_list()
+                .ok_or("items isn't a list")?;
+            let items = items
+                .iter()
+                .map(|item| item.as_text().map(str::to_string))
+                .collect::<Option<Vec<_>>>()
+                .ok_or("items aren't text")?;
+            Ok(InventoryFacet { items })
+        }
+    }
+
+    fn inventory(items: &[&str]) -> InventoryFacet {
+        InventoryFacet {
+            items: items.iter().map(|item| item.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn registering_again_keeps_the_kind_and_its_facets() {
+        let mut custom = CustomFacets::new();
+        let kind = custom.register("inventory");
+        let other = custom.register("dialogue");
+        assert_ne!(kind, other);
+        if let Some(store) = custom.store_mut(kind) {
+            store.insert(Value::Int(1));
+        }
+
+        // e.g. the mod was reloaded
+        assert_eq!(custom.register("inventory"), kind);
+        assert_eq!(custom.store(kind).map(|s| s.len()), Some(1));
+        assert_eq!(custom.name(other), Some("dialogue"));
+
+        custom.clear();
+        assert_eq!(custom.kind("inventory"), Some(kind));
+        assert_eq!(custom.store(kind).map(|s| s.len()), Some(0));
+    }
+
+    static DECODED: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct CountedFacet(i64);
+
+    impl CustomFacet for CountedFacet {
+        const NAME: &'static str = "counted";
+
+        fn to_value(&self) -> Value {
+            Value::Int(self.0)
+        }
+
+        fn from_value(value: &Value) -> Result<Self, String> {
+            DECODED.fetch_add(1, Ordering::SeqCst);
+            value
+                .as_int()
+                .map(CountedFacet)
+                .ok_or_else(|| "not an int".to_string())
+        }
+    }
+
+    #[test]
+    fn facets_are_decoded_once_until_they_change() {
+        let mut world = World::new();
+        let thing = world.start_thing().with_custom(&CountedFacet(1)).build();
+        let thing = thing.lock().unwrap().id;
+        let decoded = || DECODED.load(Ordering::SeqCst);
+
+        assert_eq!(world.get_custom(thing), Some(Ok(CountedFacet(1))));
+        let after_first = decoded();
+        assert_eq!(world.get_custom(thing), Some(Ok(CountedFacet(1))));
+        assert_eq!(world.query::<Custom<CountedFacet>>().count(), 1);
+        assert_eq!(decoded(), after_first);
+
+        world.set_custom(thing, &CountedFacet(2)).unwrap();
+        assert_eq!(world.get_custom(thing), Some(Ok(CountedFacet(2))));
+        assert_eq!(decoded(), after_first + 1);
+
+        // e.g. a mod unloaded
+        world.forget_decoded();
+        assert_eq!(world.get_custom(thing), Some(Ok(CountedFacet(2))));
+        assert_eq!(decoded(), after_first + 2);
+    }
+
+    #[test]
+    fn custom_facets_are_spawned_read_and_queried() {
+        let mut world = World::new();
+        let carrier = world
+            .start_thing()
+            .with_custom(&inventory(&["sword"]))
+            .build();
+        let carrier = carrier.lock().unwrap().id;
+        let empty = world.start_thing().build();
+        let empty = empty.lock().unwrap().id;
+
+        assert_eq!(
+            world.get_custom::<InventoryFacet>(carrier),
+            Some(Ok(inventory(&["sword"])))
+        );
+        assert_eq!(world.get_custom::<InventoryFacet>(empty), None);
+        world
+            .set_custom(carrier, &inventory(&["sword", "shield"]))
+            .unwrap();
+        assert!(world.set_custom(empty, &inventory(&[])).is_err());
+
+        let found = world
+            .query::<Custom<InventoryFacet>>()
+            .map(|(id, inventory)| (id, inventory.items.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(found, vec![(carrier, 2)]);
+
+        world.despawn(carrier).unwrap();
+        let kind = world.register_facet::<InventoryFacet>();
+        let facets = world.get_facets_ref();
+        assert_eq!(facets.custom.store(kind).map(|s| s.len()), Some(0));
+    }
+
+    #[test]
+    fn custom_facets_are_removed_and_added() {
+        let mut world = World::new();
+        let carrier = world
+            .start_thing()
+            .with_custom(&inventory(&["sword"]))
+            .build();
+        let carrier = carrier.lock().unwrap().id;
+        let empty = world.start_thing().build();
+        let empty = empty.lock().unwrap().id;
+
+        let removed = world.remove_custom::<InventoryFacet>(carrier).unwrap();
+        assert_eq!(
+            InventoryFacet::from_value(&removed),
+            Ok(inventory(&["sword"]))
+        );
+        assert!(world.get_custom::<InventoryFacet>(carrier).is_none());
+        assert!(world.remove_custom::<InventoryFacet>(carrier).is_err());
+
+        world.add_custom(empty, &inventory(&["key"])).unwrap();
+        assert!(world.add_custom(empty, &inventory(&[])).is_err());
+        assert_eq!(
+            world.get_custom::<InventoryFacet>(empty),
+            Some(Ok(inventory(&["key"])))
+        );
+
+        world.despawn(empty).unwrap();
+        let kind = world.register_facet::<InventoryFacet>();
+        let facets = world.get_facets_ref();
+        assert_eq!(facets.custom.store(kind).map(|s| s.len()), Some(0));
+    }
+}
@@ -1,28 +1,67 @@
+use std::any::type_name;
+use std::collections::{BTreeSet, HashMap};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
-use nalgebra::{Matrix4, Perspective3, Scalar, Vector3};
+use nalgebra::{Perspective3, UnitQuaternion, Vector3};
 
-use crate::{create_next_identity, model, Identifyable, Identity};
+use crate::message::Value;
+use crate::model;
 
-#[derive(Copy, Clone)]
+pub mod custom;
+pub mod query;
+pub mod slots;
+pub mod transform;
+
+pub use self::custom::{CustomFacet, CustomFacets, FacetKind};
+pub use self::query::{Custom, FacetsMut, Query, QueryGuard, QueryMut, QueryStores, With, Without};
+pub use self::slots::{Handle, Slots};
+pub use self::transform::TransformFacet;
+
+use self::transform::{Hierarchy, Link};
+
+/// A thing in the World, stale once the thing is despawned
+pub type ThingId = Handle;
+/// A facet in one of WorldFacets' stores
+pub type FacetId = Handle;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum FacetIndex {
-    Physical(usize), // does it have mass?
-    Health(usize),   // can it be hurt? die?
-    Camera(usize),
-    Model(usize),
-    // Input(usize),
-    // Network(usize),
-    // Pathing(usize), // finding it's way around
-    // Dialogue(usize), // can this entity be talked with?
-    // AI(usize),
+    Physical(FacetId), // does it have mass?
+    Health(FacetId),   // can it be hurt? die?
+    Camera(FacetId),
+    Model(FacetId),
+    Transform(FacetId), // where is it?
+    /// A facet type registered at runtime, e.g. pathing or dialogue, see `CustomFacet`
+    Custom(FacetKind, FacetId),
+}
+
+/// The type of a facet, whichever facet it is
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum FacetType {
+    Physical,
+    Health,
+    Camera,
+    Model,
+    Transform,
+    Custom(FacetKind),
 }
 
-pub struct ModelInstanceFacet<U = f32>
-where
-    U: Scalar,
-{
-    pub transform: Matrix4<U>,
+impl FacetIndex {
+    pub fn facet_type(&self) -> FacetType {
+        match self {
+            FacetIndex::Physical(_) => FacetType::Physical,
+            FacetIndex::Health(_) => FacetType::Health,
+            FacetIndex::Camera(_) => FacetType::Camera,
+            FacetIndex::Model(_) => FacetType::Model,
+            FacetIndex::Transform(_) => FacetType::Transform,
+            FacetIndex::Custom(kind, _) => FacetType::Custom(*kind),
+        }
+    }
+}
+
+/// A model drawn where its thing's TransformFacet puts it
+pub struct ModelInstanceFacet {
     pub model: Arc<model::Model>,
 }
 
@@ -58,25 +97,31 @@ pub struct PhysicalFacet {
     pub mass: f32,
     pub linear_velocity: Vector3<f32>,
     pub angular_velocity: Vector3<f32>, // is this sufficient for angular velocity? durrrrr
-    pub position: Vector3<f32>,
 }
 
-pub struct CameraFacet {
-    // TODO: pos and rotation should be part of PhysicalFacet
-    pub pos: Vector3<f32>,
-    pub pitch: f32,
-    pub yaw: f32,
+impl PhysicalFacet {
+    /// At rest
+    pub fn new(body: Shape, mass: f32) -> Self {
+        PhysicalFacet {
+            body,
+            mass,
+            linear_velocity: Vector3::zeros(),
+            angular_velocity: Vector3::zeros(),
+        }
+    }
+}
 
-    _dirty: bool,
+/// A point of view, placed and turned by its thing's TransformFacet
+pub struct CameraFacet {
     pub rotation_speed: f32,
     pub movement_speed: f32,
 
-    pub view: Matrix4<f32>,
     pub perspective: Perspective3<f32>,
-    pub movement_dir: Option<Direction>,
+    /// every direction currently held, combined into one velocity on update
+    pub movement_dirs: Vec<Direction>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Direction {
     Up,
     Down,
@@ -86,17 +131,18 @@ pub enum Direction {
     Backward,
 }
 
+impl Default for CameraFacet {
+    fn default() -> Self {
+        CameraFacet::new()
+    }
+}
+
 impl CameraFacet {
-    pub fn new(pos: Vector3<f32>, pitch: f32, yaw: f32) -> Self {
-        let mut c = CameraFacet {
-            pos,
-            pitch,
-            yaw,
+    pub fn new() -> Self {
+        CameraFacet {
             rotation_speed: 1.0,
             movement_speed: 1.0,
-            movement_dir: None,
-            _dirty: false,
-            view: Matrix4::<f32>::identity(),
+            movement_dirs: Vec::new(),
 
             // TODO fix default perspective values
             perspective: Perspective3::<f32>::new(
@@ -105,9 +151,18 @@ impl CameraFacet {
                 0.0,    // near
                 1000.0, //far
             ),
-        };
-        c.update_view_matrix();
-        c
+        }
+    }
+
+    /// The rotation of a camera looking up by pitch and around by yaw
+    pub fn orientation(pitch: f32, yaw: f32) -> UnitQuaternion<f32> {
+        UnitQuaternion::from_euler_angles(pitch, yaw, 0.0)
+    }
+
+    /// How far up the camera with this rotation looks
+    pub fn pitch(rotation: &UnitQuaternion<f32>) -> f32 {
+        let z = rotation.transform_vector(&Vector3::z());
+        (-z.y).clamp(-1.0, 1.0).asin()
     }
 
     pub fn set_perspective(&mut self, fov: f32, aspect: f32, near: f32, far: f32) {
@@ -118,46 +173,52 @@ impl CameraFacet {
         self.perspective.set_aspect(aspect);
     }
 
-    pub fn forward(&self) -> Vector3<f32> {
-        let rx = self.pitch;
-        let ry = self.yaw;
-        Vector3::new(-(rx.cos()) * ry.sin(), rx.sin(), rx.cos() * ry.cos()).normalize()
+    /// Cameras look down their -z axis
+    pub fn forward(rotation: &UnitQuaternion<f32>) -> Vector3<f32> {
+        rotation.transform_vector(&-Vector3::z())
     }
 
-    pub fn right(&self) -> Vector3<f32> {
-        let y = Vector3::new(1.0, 0.0, 0.0);
-        let forward = self.forward();
-        let cross = y.cross(&forward);
-        cross.normalize()
+    pub fn right(rotation: &UnitQuaternion<f32>) -> Vector3<f32> {
+        rotation.transform_vector(&Vector3::x())
     }
 
-    pub fn up(&self) -> Vector3<f32> {
-        let x = Vector3::new(0.0, 1.0, 0.0);
-        x.cross(&self.forward()).normalize()
+    pub fn up(rotation: &UnitQuaternion<f32>) -> Vector3<f32> {
+        rotation.transform_vector(&Vector3::y())
     }
 
-    pub fn update(&mut self, dt: &Duration) {
-        let amount = (dt.as_millis() as f64 / 100.0) as f32;
-        if let Some(move_dir) = &self.movement_dir {
-            let m = self.movement_speed * amount;
-            let d = match move_dir {
-                Direction::Forward => self.forward(),
-                Direction::Backward => -self.forward(),
-                Direction::Right => self.right(),
-                Direction::Left => -self.right(),
-                Direction::Up => self.up(),
-                Direction::Down => -self.up(),
-            };
-            self.pos += d * m;
-        }
-        self.update_view_matrix();
+    pub fn set_movement(&mut self, directions: &[Direction]) {
+        self.movement_dirs.clear();
+        self.movement_dirs.extend_from_slice(directions);
     }
 
-    pub fn update_view_matrix(&mut self) {
-        let rot = Matrix4::from_euler_angles(self.pitch, self.yaw, 0.0);
-        let trans = Matrix4::new_translation(&self.pos);
-        self.view = trans * rot;
-        self._dirty = true;
+    /// Unit velocity of all held directions, opposing directions cancel out
+    pub fn velocity(&self, rotation: &UnitQuaternion<f32>) -> Vector3<f32> {
+        let (forward, right, up) = (
+            Self::forward(rotation),
+            Self::right(rotation),
+            Self::up(rotation),
+        );
+        let v = self
+            .movement_dirs
+            .iter()
+            .fold(Vector3::zeros(), |v, dir| match dir {
+                Direction::Forward => v + forward,
+                Direction::Backward => v - forward,
+                Direction::Right => v + right,
+                Direction::Left => v - right,
+                Direction::Up => v + up,
+                Direction::Down => v - up,
+            });
+        v.try_normalize(f32::EPSILON).unwrap_or_else(Vector3::zeros)
+    }
+
+    /// Moves the camera's thing by the held directions
+    pub fn update(&mut self, transform: &mut TransformFacet, dt: &Duration) {
+        let amount = (dt.as_millis() as f64 / 100.0) as f32;
+        let velocity = self.velocity(transform.rotation());
+        if velocity != Vector3::zeros() {
+            transform.translate(velocity * self.movement_speed * amount);
+        }
     }
 }
 
@@ -168,22 +229,95 @@ impl CameraFacet {
 // safety here.
 #[derive(Default)]
 pub struct WorldFacets {
-    pub cameras: Vec<CameraFacet>,
-    pub models: Vec<ModelInstanceFacet>,
-    pub physical: Vec<PhysicalFacet>,
-    pub health: Vec<HealthFacet>,
+    pub cameras: Slots<CameraFacet>,
+    pub models: Slots<ModelInstanceFacet>,
+    pub physical: Slots<PhysicalFacet>,
+    pub health: Slots<HealthFacet>,
+    pub transforms: Slots<TransformFacet>,
+    pub custom: CustomFacets,
 }
 
 impl WorldFacets {
     pub fn new() -> Self {
         Default::default()
     }
+
+    /// Frees the facet's slot, false if it was already removed
+    pub fn remove(&mut self, facet: FacetIndex) -> bool {
+        match facet {
+            FacetIndex::Physical(id) => self.physical.remove(id).is_some(),
+            FacetIndex::Health(id) => self.health.remove(id).is_some(),
+            FacetIndex::Camera(id) => self.cameras.remove(id).is_some(),
+            FacetIndex::Model(id) => self.models.remove(id).is_some(),
+            FacetIndex::Transform(id) => self.transforms.remove(id).is_some(),
+            FacetIndex::Custom(kind, id) => self.custom.remove(kind, id).is_some(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.cameras.clear();
+        self.health.clear();
+        self.models.clear();
+        self.physical.clear();
+        self.transforms.clear();
+        self.custom.clear();
+    }
 }
 
+/// A facet type kept in WorldFacets, which things can be queried by
+pub trait Facet: Sized + 'static {
+    const TYPE: FacetType;
+    /// The thing's facet of this type, among its facets
+    fn find(facets: &[FacetIndex]) -> Option<FacetId>;
+    /// How a thing refers to its facet of this type
+    fn index(id: FacetId) -> FacetIndex;
+    fn store(world: &WorldFacets) -> Option<&Slots<Self>>;
+    fn store_mut(world: &mut WorldFacets) -> &mut Slots<Self>;
+}
+
+macro_rules! facet {
+    ($facet:ty, $index:ident, $store:ident) => {
+        impl Facet for $facet {
+            const TYPE: FacetType = FacetType::$index;
+
+            fn find(facets: &[FacetIndex]) -> Option<FacetId> {
+                facets.iter().find_map(|facet| match facet {
+                    FacetIndex::$index(id) => Some(*id),
+                    _ => None,
+                })
+            }
+
+            fn index(id: FacetId) -> FacetIndex {
+                FacetIndex::$index(id)
+            }
+
+            fn store(world: &WorldFacets) -> Option<&Slots<Self>> {
+                Some(&world.$store)
+            }
+
+            fn store_mut(world: &mut WorldFacets) -> &mut Slots<Self> {
+                &mut world.$store
+            }
+        }
+    };
+}
+
+facet!(CameraFacet, Camera, cameras);
+facet!(ModelInstanceFacet, Model, models);
+facet!(PhysicalFacet, Physical, physical);
+facet!(HealthFacet, Health, health);
+facet!(TransformFacet, Transform, transforms);
+
 #[derive(Default)]
 pub struct World {
-    things: Vec<Arc<Mutex<Thing>>>,
+    things: Slots<Arc<Mutex<Thing>>>,
     facets: WorldFacets,
+    /// Each thing's facets, so queries don't lock things
+    thing_facets: HashMap<ThingId, Vec<FacetIndex>>,
+    /// The things with a facet of each type, queries visit the fewest
+    holders: HashMap<FacetType, BTreeSet<ThingId>>,
+    /// Where each transform is in the hierarchy
+    hierarchy: Hierarchy,
 }
 
 impl World {
@@ -191,26 +325,375 @@ impl World {
         Default::default()
     }
 
-    pub fn start_thing(&mut self) -> ThingBuilder {
+    pub fn start_thing(&mut self) -> ThingBuilder<'_> {
         ThingBuilder {
             world: self,
             facets: Vec::new(),
         }
     }
 
-    pub fn get_things(&self) -> &Vec<Arc<Mutex<Thing>>> {
-        &self.things
+    /// Every thing, in the order they were spawned until one is despawned
+    pub fn get_things(&self) -> &[Arc<Mutex<Thing>>] {
+        self.things.as_slice()
+    }
+
+    /// None once the thing is despawned
+    pub fn get_thing(&self, id: ThingId) -> Option<&Arc<Mutex<Thing>>> {
+        self.things.get(id)
+    }
+
+    pub fn is_alive(&self, id: ThingId) -> bool {
+        self.things.contains(id)
+    }
+
+    /// Removes the thing and frees its facets' slots. Fails for a thing already despawned.
+    pub fn despawn(&mut self, id: ThingId) -> Result<(), String> {
+        let thing = self
+            .things
+            .remove(id)
+            .ok_or_else(|| format!("thing {} is not in the world", id))?;
+        let facets = match thing.lock() {
+            Ok(thing) => thing.facets.clone(),
+            Err(e) => e.into_inner().facets.clone(),
+        };
+        if let Some(transform) = TransformFacet::find(&facets) {
+            self.detach(transform);
+        }
+        self.thing_facets.remove(&id);
+        for facet in facets {
+            if let Some(holders) = self.holders.get_mut(&facet.facet_type()) {
+                holders.remove(&id);
+            }
+            self.facets.remove(facet);
+        }
+        Ok(())
+    }
+
+    fn index_facet(&mut self, thing_id: ThingId, facet: FacetIndex) {
+        self.thing_facets.entry(thing_id).or_default().push(facet);
+        self.holders
+            .entry(facet.facet_type())
+            .or_default()
+            .insert(thing_id);
+    }
+
+    fn unindex_facet(&mut self, thing_id: ThingId, facet: FacetIndex) {
+        if let Some(facets) = self.thing_facets.get_mut(&thing_id) {
+            facets.retain(|f| *f != facet);
+        }
+        if let Some(holders) = self.holders.get_mut(&facet.facet_type()) {
+            holders.remove(&thing_id);
+        }
+    }
+
+    /// Takes the transform out of the hierarchy, its children keep their local transforms
+    fn detach(&mut self, id: FacetId) {
+        let node = match self.hierarchy.remove(&id) {
+            Some(node) => node,
+            None => return,
+        };
+        if let Some(parent) = node
+            .parent
+            .and_then(|p| self.hierarchy.get_mut(&p.transform))
+        {
+            parent.children.retain(|child| child.transform != id);
+        }
+        for child in node.children {
+            if let Some(child) = self.hierarchy.get_mut(&child.transform) {
+                child.parent = None;
+            }
+            if let Some(child) = self.facets.transforms.get_mut(child.transform) {
+                child.mark_dirty();
+            }
+        }
+    }
+
+    /// Places the thing relative to the parent from now on, or to the world for none. Both
+    /// need a TransformFacet, and the parent can't be below the thing.
+    pub fn set_parent(&mut self, thing_id: ThingId, parent: Option<ThingId>) -> Result<(), String> {
+        let link = |world: &World, thing_id| {
+            world
+                .facet_id::<TransformFacet>(thing_id)
+                .map(|transform| Link {
+                    thing: thing_id,
+                    transform,
+                })
+                .ok_or_else(|| format!("thing {} has no transform", thing_id))
+        };
+        let child = link(self, thing_id)?;
+        let parent = match parent {
+            Some(parent) => Some(link(self, parent)?),
+            None => None,
+        };
+
+        let mut ancestor = parent;
+        while let Some(link) = ancestor {
+            if link.transform == child.transform {
+                return Err(format!("thing {} would be its own ancestor", thing_id));
+            }
+            ancestor = self.hierarchy.get(&link.transform).and_then(|n| n.parent);
+        }
+
+        let node = self.hierarchy.entry(child.transform).or_default();
+        let old = std::mem::replace(&mut node.parent, parent);
+        if let Some(old) = old.and_then(|old| self.hierarchy.get_mut(&old.transform)) {
+            old.children
+                .retain(|link| link.transform != child.transform);
+        }
+        if let Some(parent) = parent {
+            let parent = self.hierarchy.entry(parent.transform).or_default();
+            parent.children.push(child);
+        }
+        if let Some(transform) = self.facets.transforms.get_mut(child.transform) {
+            transform.mark_dirty();
+        }
+        Ok(())
+    }
+
+    /// The thing this one is placed relative to, see `set_parent`
+    pub fn parent(&self, thing_id: ThingId) -> Option<ThingId> {
+        let transform = self.facet_id::<TransformFacet>(thing_id)?;
+        let node = self.hierarchy.get(&transform)?;
+        node.parent.map(|link| link.thing)
+    }
+
+    /// The things placed relative to this one
+    pub fn children(&self, thing_id: ThingId) -> Vec<ThingId> {
+        let node = self
+            .facet_id::<TransformFacet>(thing_id)
+            .and_then(|transform| self.hierarchy.get(&transform));
+        match node {
+            Some(node) => node.children.iter().map(|link| link.thing).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Recomputes the world matrices of the transforms changed since the last call, and of
+    /// everything below them. Returns how many were recomputed.
+    pub fn update_transforms(&mut self) -> usize {
+        transform::update(&mut self.facets.transforms, &self.hierarchy)
     }
 
     pub fn get_facets(&mut self) -> &mut WorldFacets {
         &mut self.facets
     }
 
+    pub fn get_facets_ref(&self) -> &WorldFacets {
+        &self.facets
+    }
+
+    /// Find the CameraFacet attached to the thing, if any
+    pub fn camera_for(&self, thing_id: ThingId) -> Option<&CameraFacet> {
+        self.get(thing_id)
+    }
+
+    /// The thing's facet of this type, if it's alive and has one
+    pub fn get<F: Facet>(&self, thing_id: ThingId) -> Option<&F> {
+        let id = self.facet_id::<F>(thing_id)?;
+        F::store(&self.facets)?.get(id)
+    }
+
+    pub fn get_mut<F: Facet>(&mut self, thing_id: ThingId) -> Option<&mut F> {
+        let id = self.facet_id::<F>(thing_id)?;
+        F::store_mut(&mut self.facets).get_mut(id)
+    }
+
+    fn facet_id<F: Facet>(&self, thing_id: ThingId) -> Option<FacetId> {
+        let thing = self.get_thing(thing_id)?;
+        let thing = thing.lock().unwrap_or_else(|e| e.into_inner());
+        thing.facet::<F>()
+    }
+
+    /// Adds the facet to a thing already spawned. Fails if the thing is despawned or already
+    /// has a facet of this type, which `get_mut` changes instead.
+    pub fn add_facet<F: Facet>(&mut self, thing_id: ThingId, facet: F) -> Result<FacetId, String> {
+        let thing = self.lock_thing(thing_id)?;
+        let mut thing = thing.lock().unwrap_or_else(|e| e.into_inner());
+        if thing.facet::<F>().is_some() {
+            return Err(format!(
+                "thing {} already has a {}",
+                thing_id,
+                type_name::<F>()
+            ));
+        }
+        let id = F::store_mut(&mut self.facets).insert(facet);
+        thing.facets.push(F::index(id));
+        self.index_facet(thing_id, F::index(id));
+        Ok(id)
+    }
+
+    /// Takes the facet off the thing. A transform is taken out of the hierarchy first, its
+    /// children are left where their local transforms put them.
+    pub fn remove_facet<F: Facet>(&mut self, thing_id: ThingId) -> Result<F, String> {
+        let thing = self.lock_thing(thing_id)?;
+        let mut thing = thing.lock().unwrap_or_else(|e| e.into_inner());
+        let id = thing
+            .facet::<F>()
+            .ok_or_else(|| format!("thing {} has no {}", thing_id, type_name::<F>()))?;
+        if let FacetIndex::Transform(id) = F::index(id) {
+            self.detach(id);
+        }
+        thing.facets.retain(|facet| *facet != F::index(id));
+        self.unindex_facet(thing_id, F::index(id));
+        F::store_mut(&mut self.facets).remove(id).ok_or_else(|| {
+            format!(
+                "the {} of thing {} was already removed",
+                type_name::<F>(),
+                thing_id
+            )
+        })
+    }
+
+    /// Adds a facet of a type registered at runtime to a thing already spawned, registering
+    /// the type if it isn't yet. Fails like `add_facet`.
+    pub fn add_custom<F: CustomFacet>(
+        &mut self,
+        thing_id: ThingId,
+        facet: &F,
+    ) -> Result<FacetId, String> {
+        let kind = self.register_facet::<F>();
+        let thing = self.lock_thing(thing_id)?;
+        let mut thing = thing.lock().unwrap_or_else(|e| e.into_inner());
+        if CustomFacets::find(kind, &thing.facets).is_some() {
+            return Err(format!(
+                "thing {} already has a {} facet",
+                thing_id,
+                F::NAME
+            ));
+        }
+        let id = self
+            .facets
+            .custom
+            .insert(kind, facet.to_value())
+            .ok_or_else(|| format!("{} facets aren't registered", F::NAME))?;
+        thing.facets.push(FacetIndex::Custom(kind, id));
+        self.index_facet(thing_id, FacetIndex::Custom(kind, id));
+        Ok(id)
+    }
+
+    /// Takes the custom facet off the thing, returning it as it was stored
+    pub fn remove_custom<F: CustomFacet>(&mut self, thing_id: ThingId) -> Result<Value, String> {
+        let thing = self.lock_thing(thing_id)?;
+        let mut thing = thing.lock().unwrap_or_else(|e| e.into_inner());
+        let missing = || format!("thing {} has no {} facet", thing_id, F::NAME);
+        let kind = self.facets.custom.kind(F::NAME).ok_or_else(missing)?;
+        let id = CustomFacets::find(kind, &thing.facets).ok_or_else(missing)?;
+        thing
+            .facets
+            .retain(|facet| *facet != FacetIndex::Custom(kind, id));
+        self.unindex_facet(thing_id, FacetIndex::Custom(kind, id));
+        self.facets.custom.remove(kind, id).ok_or_else(missing)
+    }
+
+    /// The thing, to lock while its facets change
+    fn lock_thing(&self, thing_id: ThingId) -> Result<Arc<Mutex<Thing>>, String> {
+        self.things
+            .get(thing_id)
+            .cloned()
+            .ok_or_else(|| format!("thing {} is not in the world", thing_id))
+    }
+
+    /// The custom facet type's kind, registering it the first time
+    pub fn register_facet<F: CustomFacet>(&mut self) -> FacetKind {
+        self.facets.custom.register(F::NAME)
+    }
+
+    /// The thing's facet of this custom type, if it has one, or why it couldn't be read
+    pub fn get_custom<F: CustomFacet>(&self, thing_id: ThingId) -> Option<Result<F, String>> {
+        let kind = self.facets.custom.kind(F::NAME)?;
+        let id = self.custom_facet_id(thing_id, kind)?;
+        self.facets.custom.decode(kind, id)
+    }
+
+    /// Replaces the thing's facet of this custom type. Fails if the thing doesn't have one.
+    pub fn set_custom<F: CustomFacet>(
+        &mut self,
+        thing_id: ThingId,
+        facet: &F,
+    ) -> Result<(), String> {
+        let kind = self.register_facet::<F>();
+        let value = self.get_value_mut(thing_id, kind).ok_or_else(|| {
+            format!(
+                "thing {} has no {} facet, see add_custom",
+                thing_id,
+                F::NAME
+            )
+        })?;
+        *value = facet.to_value();
+        Ok(())
+    }
+
+    /// The thing's custom facet of this kind, as it's stored
+    pub fn get_value(&self, thing_id: ThingId, kind: FacetKind) -> Option<&Value> {
+        let id = self.custom_facet_id(thing_id, kind)?;
+        self.facets.custom.store(kind)?.get(id)
+    }
+
+    pub fn get_value_mut(&mut self, thing_id: ThingId, kind: FacetKind) -> Option<&mut Value> {
+        let id = self.custom_facet_id(thing_id, kind)?;
+        self.facets.custom.value_mut(kind, id)
+    }
+
+    /// Drops the custom facets kept decoded, which were made by a mod's code. State calls this
+    /// before a mod unloads.
+    pub fn forget_decoded(&mut self) {
+        self.facets.custom.forget_decoded();
+    }
+
+    fn custom_facet_id(&self, thing_id: ThingId, kind: FacetKind) -> Option<FacetId> {
+        let thing = self.get_thing(thing_id)?;
+        let thing = thing.lock().unwrap_or_else(|e| e.into_inner());
+        CustomFacets::find(kind, &thing.facets)
+    }
+
+    /// Every thing matching the query, with its facets, see `query`
+    pub fn query<'a, Q: Query<'a>>(&'a self) -> impl Iterator<Item = (ThingId, Q::Item)> + 'a {
+        let facets = &self.facets;
+        self.candidates(|visit| Q::required(facets, visit))
+            .filter_map(move |(id, thing_facets)| {
+                Q::fetch(facets, thing_facets).map(|item| (id, item))
+            })
+    }
+
+    /// The things a query may match, with their facets: those holding the fewest of the facet
+    /// types the query requires, or every thing when it requires none
+    fn candidates<'a>(
+        &'a self,
+        required: impl FnOnce(&mut dyn FnMut(FacetType)),
+    ) -> Box<dyn Iterator<Item = (ThingId, &'a [FacetIndex])> + 'a> {
+        let mut fewest: Option<(usize, Option<&BTreeSet<ThingId>>)> = None;
+        required(&mut |facet_type| {
+            let holders = self.holders.get(&facet_type).filter(|h| !h.is_empty());
+            let len = holders.map_or(0, |h| h.len());
+            match fewest {
+                Some((fewest_len, _)) if fewest_len <= len => {}
+                _ => fewest = Some((len, holders)),
+            }
+        });
+        let with_facets = move |id: ThingId| {
+            let facets = self.thing_facets.get(&id).map_or(&[][..], |f| f.as_slice());
+            (id, facets)
+        };
+        match fewest {
+            None => Box::new(self.things.handles().map(move |(id, _)| with_facets(id))),
+            Some((_, None)) => Box::new(std::iter::empty()),
+            Some((_, Some(holders))) => Box::new(holders.iter().map(move |id| with_facets(*id))),
+        }
+    }
+
+    /// A query which may change facets, see `query`. Panics if the query changes a facet type
+    /// it also reads, e.g. `(&mut CameraFacet, &CameraFacet)`.
+    pub fn query_mut<Q: QueryStores>(&mut self) -> QueryGuard<'_, Q> {
+        QueryGuard::new(self)
+    }
+
+    /// Despawns every thing, handles to them and their facets are stale from now on
     pub fn clear(&mut self) {
-        self.facets.cameras.clear();
-        self.facets.health.clear();
-        self.facets.models.clear();
-        self.facets.physical.clear();
+        self.things.clear();
+        self.facets.clear();
+        self.thing_facets.clear();
+        self.holders.clear();
+        self.hierarchy.clear();
     }
 }
 
@@ -220,71 +703,232 @@ pub struct ThingBuilder<'a> {
 }
 
 impl<'a> ThingBuilder<'a> {
-    pub fn with_camera(mut self, camera: CameraFacet) -> Self {
-        let idx = self.world.facets.cameras.len();
-        self.world.facets.cameras.push(camera);
-        self.facets.push(FacetIndex::Camera(idx));
+    /// Adds the facet, replacing one of the same type added before
+    pub fn with_facet<F: Facet>(mut self, facet: F) -> Self {
+        let store = F::store_mut(&mut self.world.facets);
+        if let Some(old) = F::find(&self.facets) {
+            self.facets.retain(|facet| *facet != F::index(old));
+            store.remove(old);
+        }
+        let id = store.insert(facet);
+        self.facets.push(F::index(id));
         self
     }
 
-    pub fn with_model(mut self, transform: Matrix4<f32>, model: Arc<model::Model>) -> Self {
-        let idx = self.world.facets.models.len();
-        self.world
-            .facets
-            .models
-            .push(ModelInstanceFacet { transform, model });
-        self.facets.push(FacetIndex::Model(idx));
+    pub fn with_camera(self, camera: CameraFacet) -> Self {
+        self.with_facet(camera)
+    }
+
+    pub fn with_model(self, model: Arc<model::Model>) -> Self {
+        self.with_facet(ModelInstanceFacet { model })
+    }
+
+    pub fn with_transform(self, transform: TransformFacet) -> Self {
+        self.with_facet(transform)
+    }
+
+    pub fn with_physical(self, physical: PhysicalFacet) -> Self {
+        self.with_facet(physical)
+    }
+
+    pub fn with_health(self, health: HealthFacet) -> Self {
+        self.with_facet(health)
+    }
+
+    /// Adds a facet of a type registered at runtime, registering the type if it isn't yet.
+    /// Replaces one of the same type added before.
+    pub fn with_custom<F: CustomFacet>(mut self, facet: &F) -> Self {
+        let kind = self.world.register_facet::<F>();
+        let custom = &mut self.world.facets.custom;
+        match CustomFacets::find(kind, &self.facets).and_then(|id| custom.value_mut(kind, id)) {
+            Some(value) => *value = facet.to_value(),
+            None => {
+                if let Some(id) = custom.insert(kind, facet.to_value()) {
+                    self.facets.push(FacetIndex::Custom(kind, id));
+                }
+            }
+        }
         self
     }
 
-    pub fn build(self) -> Arc<Mutex<Thing>> {
-        let thing = Thing::new(self.facets);
-        let a = Arc::new(Mutex::new(thing));
-        self.world.things.push(a.clone());
-        a
+    pub fn build(mut self) -> Arc<Mutex<Thing>> {
+        let facets = std::mem::take(&mut self.facets);
+        let id = self
+            .world
+            .things
+            .insert_with(|id| Arc::new(Mutex::new(Thing::new(id, facets.clone()))));
+        self.world.thing_facets.insert(id, Vec::new());
+        for facet in facets {
+            self.world.index_facet(id, facet);
+        }
+        self.world
+            .things
+            .get(id)
+            .cloned()
+            .expect("the thing was just added")
+    }
+}
+
+// the facets are in the stores already, a builder dropped without building frees them
+impl Drop for ThingBuilder<'_> {
+    fn drop(&mut self) {
+        for facet in self.facets.drain(..) {
+            self.world.facets.remove(facet);
+        }
     }
 }
 
 pub struct Thing {
-    pub id: Identity,
-    pub facets: Vec<FacetIndex>, // index pointers to WorldFacets' specific fields
+    pub id: ThingId,
+    /// handles to facets in WorldFacets' specific fields, only changed by the World so its
+    /// index of them stays in step
+    facets: Vec<FacetIndex>,
 }
 
 impl Thing {
-    pub fn new(facets: Vec<FacetIndex>) -> Self {
-        let id = create_next_identity();
+    pub fn new(id: ThingId, facets: Vec<FacetIndex>) -> Self {
         Thing { id, facets }
     }
 
+    /// Handles to the thing's facets, see `World::add_facet` and `World::remove_facet`
+    pub fn facets(&self) -> &[FacetIndex] {
+        &self.facets
+    }
+
+    /// The handle of the thing's facet of this type
+    pub fn facet<F: Facet>(&self) -> Option<FacetId> {
+        F::find(&self.facets)
+    }
+
     pub fn get_camera_fi(&self) -> Option<FacetIndex> {
-        self.facets
-            .iter()
-            .find(|i| {
-                if let FacetIndex::Camera(_) = i {
-                    true
-                } else {
-                    false
-                }
-            })
-            .cloned()
+        self.facet::<CameraFacet>().map(FacetIndex::Camera)
     }
 
     pub fn get_model_fi(&self) -> Option<FacetIndex> {
-        self.facets
-            .iter()
-            .find(|i| {
-                if let FacetIndex::Camera(_) = i {
-                    true
-                } else {
-                    false
-                }
-            })
-            .cloned()
+        self.facet::<ModelInstanceFacet>().map(FacetIndex::Model)
     }
 }
 
-impl Identifyable for Thing {
-    fn identify(&self) -> u64 {
-        self.id
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Point3;
+
+    fn camera() -> CameraFacet {
+        CameraFacet::new()
+    }
+
+    #[test]
+    fn despawning_frees_facets_and_stales_the_handle() {
+        let mut world = World::new();
+        let first = world.start_thing().with_camera(camera()).build();
+        let first = first.lock().unwrap().id;
+        let second = world.start_thing().with_camera(camera()).build();
+        let second = second.lock().unwrap().id;
+        assert!(world.camera_for(first).is_some());
+
+        world.despawn(first).unwrap();
+        assert!(!world.is_alive(first));
+        assert!(world.despawn(first).is_err());
+        assert!(world.camera_for(first).is_none());
+        assert_eq!(world.get_facets_ref().cameras.len(), 1);
+        assert!(world.camera_for(second).is_some());
+
+        // the freed slots are reused, the old handle stays stale
+        let third = world.start_thing().with_camera(camera()).build();
+        let third = third.lock().unwrap().id;
+        assert_eq!(third.index(), first.index());
+        assert!(world.get_thing(first).is_none());
+        assert!(world.get_thing(third).is_some());
+    }
+
+    #[test]
+    fn builders_dropped_unbuilt_free_their_facets() {
+        let mut world = World::new();
+        let builder = world
+            .start_thing()
+            .with_camera(camera())
+            .with_transform(TransformFacet::default());
+        drop(builder);
+        assert!(world.get_facets_ref().cameras.is_empty());
+        assert!(world.get_facets_ref().transforms.is_empty());
+
+        let thing = world.start_thing().with_camera(camera()).build();
+        let id = thing.lock().unwrap().id;
+        assert!(world.camera_for(id).is_some());
+    }
+
+    #[test]
+    fn clearing_despawns_every_thing() {
+        let mut world = World::new();
+        let thing = world.start_thing().with_camera(camera()).build();
+        let id = thing.lock().unwrap().id;
+        world.clear();
+        assert!(world.get_things().is_empty());
+        assert!(world.get_facets_ref().cameras.is_empty());
+        assert!(!world.is_alive(id));
+    }
+
+    #[test]
+    fn facets_are_added_and_removed_after_spawning() {
+        let mut world = World::new();
+        let thing = world
+            .start_thing()
+            .with_health(HealthFacet::new(5))
+            .with_health(HealthFacet::new(10))
+            .build();
+        let id = thing.lock().unwrap().id;
+        assert_eq!(world.get_facets_ref().health.len(), 1);
+        assert_eq!(world.get::<HealthFacet>(id).map(|h| h.hp), Some(10));
+
+        let body = Shape::Sphere { radius: 1.0 };
+        world.add_facet(id, PhysicalFacet::new(body, 2.0)).unwrap();
+        let body = Shape::Sphere { radius: 1.0 };
+        assert!(world.add_facet(id, PhysicalFacet::new(body, 3.0)).is_err());
+        assert_eq!(world.get::<PhysicalFacet>(id).map(|p| p.mass), Some(2.0));
+
+        let health = world.remove_facet::<HealthFacet>(id).unwrap();
+        assert_eq!(health.hp, 10);
+        assert!(world.remove_facet::<HealthFacet>(id).is_err());
+        assert!(world.get_facets_ref().health.is_empty());
+        assert_eq!(thing.lock().unwrap().facets.len(), 1);
+
+        world.despawn(id).unwrap();
+        assert!(world.get_facets_ref().physical.is_empty());
+        assert!(world.add_facet(id, HealthFacet::new(1)).is_err());
+    }
+
+    #[test]
+    fn cameras_look_where_their_transform_turns_them() {
+        let (pitch, yaw) = (0.3_f32, 1.2_f32);
+        let rotation = CameraFacet::orientation(pitch, yaw);
+        assert!((CameraFacet::pitch(&rotation) - pitch).abs() < 1e-5);
+
+        let forward = CameraFacet::forward(&rotation);
+        let expected = Vector3::new(
+            -(pitch.cos()) * yaw.sin(),
+            pitch.sin(),
+            -(pitch.cos()) * yaw.cos(),
+        );
+        assert!((forward - expected).norm() < 1e-5);
+        assert!(CameraFacet::right(&rotation).dot(&forward).abs() < 1e-5);
+        assert!(CameraFacet::up(&rotation).y > 0.0);
+
+        let mut camera = camera();
+        let mut transform = TransformFacet::default().with_rotation(rotation);
+        camera.set_movement(&[Direction::Forward]);
+        camera.update(&mut transform, &Duration::from_millis(100));
+        assert!((*transform.translation() - expected).norm() < 1e-5);
+        assert!(transform.is_dirty());
+
+        // what's ahead of the camera is down the view's -z, wherever the camera is
+        let mut world = World::new();
+        let thing = world.start_thing().with_transform(transform).build();
+        let id = thing.lock().unwrap().id;
+        world.update_transforms();
+        let transform = world.get::<TransformFacet>(id).unwrap();
+        let ahead = Point3::from(transform.translation() + expected * 3.0);
+        let seen = transform.view_matrix().transform_point(&ahead);
+        assert!((seen - Point3::new(0.0, 0.0, -3.0)).norm() < 1e-5);
     }
 }
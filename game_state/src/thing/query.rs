@@ -0,0 +1,466 @@
+use std::any::{type_name, TypeId};
+use std::marker::PhantomData;
+
+use super::{
+    CustomFacet, CustomFacets, Facet, FacetIndex, FacetType, Slots, ThingId, World, WorldFacets,
+};
+
+//
+// Queries over the things in a World, by the facets they have, e.g. every thing with a model
+// and a physical facet:
+//
+//   for (thing, (model, physical)) in world.query::<(&ModelInstanceFacet, &PhysicalFacet)>() {}
+//
+// A query is a facet reference, an Option of one for a facet the thing may not have, With or
+// Without a facet to only filter, Custom for a copy of a facet registered at runtime, or a tuple
+// of those.
+//
+// Queries don't lock things. The World keeps which things hold each facet type, and a query only
+// visits the holders of the rarest type it requires. A query requiring none, e.g. only Options,
+// visits every thing, in the order of `World::get_things`.
+//
+// Mutable queries take the facet stores they change out of WorldFacets until the query is
+// dropped, so the borrow checker sees each store borrowed once:
+//
+//   let mut query = world.query_mut::<(&mut ModelInstanceFacet, Option<&PhysicalFacet>)>();
+//   for (thing, (model, physical)) in query.iter() {}
+//
+
+/// Only matches things with the facet, without reading it
+pub struct With<F>(PhantomData<F>);
+
+/// Only matches things without the facet
+pub struct Without<F>(PhantomData<F>);
+
+/// Matches things with the custom facet, read into a copy. Things whose facet can't be read are
+/// skipped.
+pub struct Custom<F>(PhantomData<F>);
+
+/// A query reading facets
+pub trait Query<'a> {
+    type Item;
+
+    fn fetch(world: &'a WorldFacets, facets: &[FacetIndex]) -> Option<Self::Item>;
+
+    /// Visits each facet type a thing must have to match
+    fn required(_world: &WorldFacets, _visit: &mut dyn FnMut(FacetType)) {}
+}
+
+impl<'a, F: Facet> Query<'a> for &F {
+    type Item = &'a F;
+
+    fn fetch(world: &'a WorldFacets, facets: &[FacetIndex]) -> Option<Self::Item> {
+        F::store(world)?.get(F::find(facets)?)
+    }
+
+    fn required(_world: &WorldFacets, visit: &mut dyn FnMut(FacetType)) {
+        visit(F::TYPE);
+    }
+}
+
+impl<'a, F: Facet> Query<'a> for Option<&F> {
+    type Item = Option<&'a F>;
+
+    fn fetch(world: &'a WorldFacets, facets: &[FacetIndex]) -> Option<Self::Item> {
+        Some(<&F as Query>::fetch(world, facets))
+    }
+}
+
+impl<'a, F: Facet> Query<'a> for With<F> {
+    type Item = ();
+
+    fn fetch(_world: &'a WorldFacets, facets: &[FacetIndex]) -> Option<Self::Item> {
+        F::find(facets).map(|_| ())
+    }
+
+    fn required(_world: &WorldFacets, visit: &mut dyn FnMut(FacetType)) {
+        visit(F::TYPE);
+    }
+}
+
+impl<'a, F: Facet> Query<'a> for Without<F> {
+    type Item = ();
+
+    fn fetch(_world: &'a WorldFacets, facets: &[FacetIndex]) -> Option<Self::Item> {
+        match F::find(facets) {
+            Some(_) => None,
+            None => Some(()),
+        }
+    }
+}
+
+impl<'a, F: CustomFacet> Query<'a> for Custom<F> {
+    type Item = F;
+
+    fn fetch(world: &'a WorldFacets, facets: &[FacetIndex]) -> Option<Self::Item> {
+        let kind = world.custom.kind(F::NAME)?;
+        world
+            .custom
+            .decode(kind, CustomFacets::find(kind, facets)?)?
+            .ok()
+    }
+
+    // unregistered, nothing has one and every fetch fails
+    fn required(world: &WorldFacets, visit: &mut dyn FnMut(FacetType)) {
+        if let Some(kind) = world.custom.kind(F::NAME) {
+            visit(FacetType::Custom(kind));
+        }
+    }
+}
+
+/// A query which may change facets: the stores it takes out of WorldFacets while it runs
+pub trait QueryStores {
+    type Stores;
+
+    fn take(world: &mut WorldFacets) -> Self::Stores;
+    fn restore(world: &mut WorldFacets, stores: Self::Stores);
+    /// The facet types read, and whether they are changed
+    fn borrows(borrows: &mut Vec<(TypeId, &'static str, bool)>);
+    /// Visits each facet type a thing must have to match, see `Query::required`
+    fn required(_world: &WorldFacets, _visit: &mut dyn FnMut(FacetType)) {}
+}
+
+/// The items of a mutable query, borrowed for `'s`
+pub trait QueryMut<'s>: QueryStores {
+    type Prepared;
+    type Item;
+
+    fn prepare(world: &'s WorldFacets, stores: &'s mut Self::Stores) -> Self::Prepared;
+    fn fetch(prepared: &mut Self::Prepared, facets: &[FacetIndex]) -> Option<Self::Item>;
+}
+
+impl<F: Facet> QueryStores for &mut F {
+    type Stores = super::Slots<F>;
+
+    fn take(world: &mut WorldFacets) -> Self::Stores {
+        std::mem::take(F::store_mut(world))
+    }
+
+    fn restore(world: &mut WorldFacets, stores: Self::Stores) {
+        *F::store_mut(world) = stores;
+    }
+
+    fn borrows(borrows: &mut Vec<(TypeId, &'static str, bool)>) {
+        borrows.push((TypeId::of::<F>(), type_name::<F>(), true));
+    }
+
+    fn required(_world: &WorldFacets, visit: &mut dyn FnMut(FacetType)) {
+        visit(F::TYPE);
+    }
+}
+
+/// A store taken out by a mutable query, handing out each of its facets for `'s`
+pub struct FacetsMut<'s, F> {
+    slots: &'s Slots<F>,
+    items: *mut F,
+    marker: PhantomData<&'s mut F>,
+}
+
+impl<'s, F: Facet> QueryMut<'s> for &mut F {
+    type Prepared = FacetsMut<'s, F>;
+    type Item = &'s mut F;
+
+    fn prepare(_world: &'s WorldFacets, stores: &'s mut Self::Stores) -> Self::Prepared {
+        let items = stores.as_mut_slice().as_mut_ptr();
+        FacetsMut {
+            slots: stores,
+            items,
+            marker: PhantomData,
+        }
+    }
+
+    fn fetch(prepared: &mut Self::Prepared, facets: &[FacetIndex]) -> Option<Self::Item> {
+        let i = prepared.slots.position(F::find(facets)?)?;
+        // SAFETY: the store is out of WorldFacets until the query is dropped, so nothing else
+        // reaches its items, and `slots` is only read for positions, never for items. A facet
+        // belongs to one thing and a query visits each thing once, so no item is handed out twice.
+        Some(unsafe { &mut *prepared.items.add(i) })
+    }
+}
+
+impl<F: Facet> QueryStores for Option<&mut F> {
+    type Stores = super::Slots<F>;
+
+    fn take(world: &mut WorldFacets) -> Self::Stores {
+        <&mut F as QueryStores>::take(world)
+    }
+
+    fn restore(world: &mut WorldFacets, stores: Self::Stores) {
+        <&mut F as QueryStores>::restore(world, stores)
+    }
+
+    fn borrows(borrows: &mut Vec<(TypeId, &'static str, bool)>) {
+        <&mut F as QueryStores>::borrows(borrows)
+    }
+}
+
+impl<'s, F: Facet> QueryMut<'s> for Option<&mut F> {
+    type Prepared = FacetsMut<'s, F>;
+    type Item = Option<&'s mut F>;
+
+    fn prepare(world: &'s WorldFacets, stores: &'s mut Self::Stores) -> Self::Prepared {
+        <&mut F as QueryMut>::prepare(world, stores)
+    }
+
+    fn fetch(prepared: &mut Self::Prepared, facets: &[FacetIndex]) -> Option<Self::Item> {
+        Some(<&mut F as QueryMut>::fetch(prepared, facets))
+    }
+}
+
+// queries only reading take nothing, and read WorldFacets as usual
+macro_rules! read_only {
+    ($($query:ty => $facet:ident: $bound:ident),*) => {$(
+        impl<$facet: $bound> QueryStores for $query {
+            type Stores = ();
+
+            fn take(_world: &mut WorldFacets) -> Self::Stores {}
+
+            fn restore(_world: &mut WorldFacets, _stores: Self::Stores) {}
+
+            fn borrows(borrows: &mut Vec<(TypeId, &'static str, bool)>) {
+                borrows.push((TypeId::of::<$facet>(), type_name::<$facet>(), false));
+            }
+
+            fn required(world: &WorldFacets, visit: &mut dyn FnMut(FacetType)) {
+                <Self as Query>::required(world, visit)
+            }
+        }
+
+        impl<'s, $facet: $bound> QueryMut<'s> for $query {
+            type Prepared = &'s WorldFacets;
+            type Item = <Self as Query<'s>>::Item;
+
+            fn prepare(world: &'s WorldFacets, _stores: &'s mut Self::Stores) -> Self::Prepared {
+                world
+            }
+
+            fn fetch(prepared: &mut Self::Prepared, facets: &[FacetIndex]) -> Option<Self::Item> {
+                <Self as Query<'s>>::fetch(prepared, facets)
+            }
+        }
+    )*};
+}
+
+read_only!(
+    &F => F: Facet,
+    Option<&F> => F: Facet,
+    With<F> => F: Facet,
+    Without<F> => F: Facet,
+    Custom<F> => F: CustomFacet
+);
+
+macro_rules! tuple_query {
+    ($($q:ident $i:tt),*) => {
+        impl<'a, $($q: Query<'a>),*> Query<'a> for ($($q,)*) {
+            type Item = ($($q::Item,)*);
+
+            fn fetch(world: &'a WorldFacets, facets: &[FacetIndex]) -> Option<Self::Item> {
+                Some(($($q::fetch(world, facets)?,)*))
+            }
+
+            fn required(world: &WorldFacets, visit: &mut dyn FnMut(FacetType)) {
+                $($q::required(world, visit);)*
+            }
+        }
+
+        impl<$($q: QueryStores),*> QueryStores for ($($q,)*) {
+            type Stores = ($($q::Stores,)*);
+
+            fn take(world: &mut WorldFacets) -> Self::Stores {
+                ($($q::take(world),)*)
+            }
+
+            fn restore(world: &mut WorldFacets, stores: Self::Stores) {
+                $($q::restore(world, stores.$i);)*
+            }
+
+            fn borrows(borrows: &mut Vec<(TypeId, &'static str, bool)>) {
+                $($q::borrows(borrows);)*
+            }
+
+            fn required(world: &WorldFacets, visit: &mut dyn FnMut(FacetType)) {
+                $($q::required(world, visit);)*
+            }
+        }
+
+        impl<'s, $($q: QueryMut<'s>),*> QueryMut<'s> for ($($q,)*) {
+            type Prepared = ($($q::Prepared,)*);
+            type Item = ($($q::Item,)*);
+
+            fn prepare(world: &'s WorldFacets, stores: &'s mut Self::Stores) -> Self::Prepared {
+                ($($q::prepare(world, &mut stores.$i),)*)
+            }
+
+            fn fetch(prepared: &mut Self::Prepared, facets: &[FacetIndex]) -> Option<Self::Item> {
+                Some(($($q::fetch(&mut prepared.$i, facets)?,)*))
+            }
+        }
+    };
+}
+
+tuple_query!(A 0, B 1);
+tuple_query!(A 0, B 1, C 2);
+tuple_query!(A 0, B 1, C 2, D 3);
+
+/// A mutable query over a World, see `World::query_mut`. The facet stores it changes are put
+/// back in the World when it's dropped.
+pub struct QueryGuard<'w, Q: QueryStores> {
+    world: &'w mut World,
+    stores: Option<Q::Stores>,
+}
+
+impl<'w, Q: QueryStores> QueryGuard<'w, Q> {
+    pub(super) fn new(world: &'w mut World) -> Self {
+        let mut borrows = Vec::new();
+        Q::borrows(&mut borrows);
+        for (i, (facet, name, mutable)) in borrows.iter().enumerate() {
+            let again = borrows.iter().skip(i + 1).find(|(f, ..)| f == facet);
+            if let Some((_, _, also_mutable)) = again {
+                if *mutable || *also_mutable {
+                    panic!("{} is changed by a query which also reads it", name);
+                }
+            }
+        }
+        let stores = Q::take(&mut world.facets);
+        QueryGuard {
+            world,
+            stores: Some(stores),
+        }
+    }
+
+    /// Every matching thing and its facets
+    pub fn iter<'s>(&'s mut self) -> impl Iterator<Item = (ThingId, <Q as QueryMut<'s>>::Item)> + 's
+    where
+        Q: QueryMut<'s>,
+    {
+        let world: &'s World = self.world;
+        let mut prepared = self
+            .stores
+            .as_mut()
+            .map(|stores| Q::prepare(&world.facets, stores));
+        world
+            .candidates(|visit| Q::required(&world.facets, visit))
+            .filter_map(move |(id, facets)| {
+                let item = Q::fetch(prepared.as_mut()?, facets)?;
+                Some((id, item))
+            })
+    }
+}
+
+impl<'w, Q: QueryStores> Drop for QueryGuard<'w, Q> {
+    fn drop(&mut self) {
+        if let Some(stores) = self.stores.take() {
+            Q::restore(&mut self.world.facets, stores);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::thing::{CameraFacet, HealthFacet, TransformFacet};
+
+    use nalgebra::Vector3;
+
+    fn spawn(world: &mut World, x: Option<f32>, hp: Option<u32>) -> ThingId {
+        let mut builder = world.start_thing();
+        if let Some(x) = x {
+            builder = builder
+                .with_camera(CameraFacet::new())
+                .with_transform(TransformFacet::at(Vector3::new(x, 0.0, 0.0)));
+        }
+        if let Some(hp) = hp {
+            builder = builder.with_health(HealthFacet::new(hp));
+        }
+        builder.build().lock().unwrap().id
+    }
+
+    #[test]
+    fn queries_match_things_with_every_facet() {
+        let mut world = World::new();
+        let both = spawn(&mut world, Some(1.0), Some(10));
+        let camera = spawn(&mut world, Some(2.0), None);
+        let health = spawn(&mut world, None, Some(20));
+
+        let found = world
+            .query::<(&CameraFacet, &TransformFacet, &HealthFacet)>()
+            .map(|(id, (_, transform, health))| (id, transform.translation().x, health.hp))
+            .collect::<Vec<_>>();
+        assert_eq!(found, vec![(both, 1.0, 10)]);
+
+        let cameras = world
+            .query::<(&CameraFacet, Option<&HealthFacet>)>()
+            .map(|(id, (_, health))| (id, health.map(|h| h.hp)))
+            .collect::<Vec<_>>();
+        assert_eq!(cameras, vec![(both, Some(10)), (camera, None)]);
+
+        let unseen = world
+            .query::<(&HealthFacet, Without<CameraFacet>)>()
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        assert_eq!(unseen, vec![health]);
+        assert_eq!(world.query::<With<HealthFacet>>().count(), 2);
+        assert_eq!(world.get::<HealthFacet>(health).map(|h| h.hp), Some(20));
+    }
+
+    #[test]
+    fn mutable_queries_change_facets_in_place() {
+        let mut world = World::new();
+        let hurt = spawn(&mut world, Some(1.0), Some(10));
+        let fine = spawn(&mut world, None, Some(20));
+        {
+            let mut query = world.query_mut::<(&mut HealthFacet, Option<&CameraFacet>)>();
+            for (_, (health, camera)) in query.iter() {
+                if camera.is_some() {
+                    health.take_dmg(15);
+                }
+            }
+            // the store is back once the query is dropped
+        }
+        assert_eq!(
+            world.get::<HealthFacet>(hurt).map(|h| h.is_alive()),
+            Some(false)
+        );
+        assert_eq!(world.get::<HealthFacet>(fine).map(|h| h.hp), Some(20));
+        assert_eq!(world.get_facets_ref().health.len(), 2);
+
+        world.despawn(hurt).unwrap();
+        let mut query = world.query_mut::<&mut HealthFacet>();
+        assert_eq!(
+            query.iter().map(|(id, _)| id).collect::<Vec<_>>(),
+            vec![fine]
+        );
+    }
+
+    #[test]
+    fn queries_run_while_things_are_locked() {
+        let mut world = World::new();
+        let first = spawn(&mut world, None, Some(10));
+        let second = spawn(&mut world, Some(1.0), None);
+        // the stores are in a different order than the things
+        world.add_facet(second, HealthFacet::new(20)).unwrap();
+        world.add_facet(first, CameraFacet::new()).unwrap();
+
+        let thing = world.get_thing(first).cloned().unwrap();
+        let _locked = thing.lock().unwrap();
+        let found = world
+            .query::<(&HealthFacet, &CameraFacet)>()
+            .map(|(id, (health, _))| (id, health.hp))
+            .collect::<Vec<_>>();
+        assert_eq!(found, vec![(first, 10), (second, 20)]);
+
+        let mut query = world.query_mut::<(&mut HealthFacet, &mut CameraFacet)>();
+        for (_, (health, _)) in query.iter() {
+            health.take_dmg(5);
+        }
+        drop(query);
+        assert_eq!(world.get::<HealthFacet>(second).map(|h| h.hp), Some(15));
+    }
+
+    #[test]
+    #[should_panic(expected = "also reads it")]
+    fn a_query_cannot_change_what_it_reads() {
+        let mut world = World::new();
+        let _ = world.query_mut::<(&mut HealthFacet, &HealthFacet)>();
+    }
+}
@@ -0,0 +1,240 @@
+use std::fmt;
+
+//
+// Generational storage for things and facets. Items are kept contiguous, the last item moving
+// into the place of one removed, and found through a slot per handle. A slot is reused once its
+// item is removed, with its generation bumped, so handles to the removed item no longer find
+// anything.
+//
+
+/// A slot and the slot's generation when the handle was made. The default handle never refers
+/// to anything, generations start at 1.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Handle {
+    index: u32,
+    generation: u32,
+}
+
+impl Handle {
+    pub(crate) fn new(index: u32, generation: u32) -> Self {
+        Handle { index, generation }
+    }
+
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+}
+
+impl fmt::Display for Handle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}v{}", self.index, self.generation)
+    }
+}
+
+struct Slot {
+    generation: u32,
+    /// where the item is in `items`, none while the slot is free
+    item: Option<usize>,
+}
+
+pub struct Slots<T> {
+    items: Vec<T>,
+    /// the slot of each item
+    owners: Vec<u32>,
+    slots: Vec<Slot>,
+    free: Vec<u32>,
+}
+
+impl<T> Default for Slots<T> {
+    fn default() -> Self {
+        Slots {
+            items: Vec::new(),
+            owners: Vec::new(),
+            slots: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+}
+
+impl<T> Slots<T> {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn insert(&mut self, item: T) -> Handle {
+        self.insert_with(|_| item)
+    }
+
+    /// Adds the item made for its handle, e.g. for items that know their own handle
+    pub fn insert_with<F: FnOnce(Handle) -> T>(&mut self, make: F) -> Handle {
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    item: None,
+                });
+                (self.slots.len() - 1) as u32
+            }
+        };
+        let slot = &mut self.slots[index as usize];
+        slot.generation += 1;
+        slot.item = Some(self.items.len());
+        let handle = Handle::new(index, slot.generation);
+        self.items.push(make(handle));
+        self.owners.push(index);
+        handle
+    }
+
+    /// Where the handle's item is in `as_slice`
+    pub(crate) fn position(&self, handle: Handle) -> Option<usize> {
+        self.slots
+            .get(handle.index as usize)
+            .filter(|slot| slot.generation == handle.generation)
+            .and_then(|slot| slot.item)
+    }
+
+    /// False once the item was removed, even if its slot holds another item since
+    pub fn contains(&self, handle: Handle) -> bool {
+        self.position(handle).is_some()
+    }
+
+    pub fn get(&self, handle: Handle) -> Option<&T> {
+        self.position(handle).map(|i| &self.items[i])
+    }
+
+    pub fn get_mut(&mut self, handle: Handle) -> Option<&mut T> {
+        let i = self.position(handle)?;
+        Some(&mut self.items[i])
+    }
+
+    /// The last item moves into the removed item's place, only its slot changes
+    pub fn remove(&mut self, handle: Handle) -> Option<T> {
+        let i = self.position(handle)?;
+        self.slots[handle.index as usize].item = None;
+        self.free.push(handle.index);
+        self.owners.swap_remove(i);
+        if let Some(moved) = self.owners.get(i) {
+            self.slots[*moved as usize].item = Some(i);
+        }
+        Some(self.items.swap_remove(i))
+    }
+
+    /// Removes every item, their handles are stale from now on
+    pub fn clear(&mut self) {
+        for owner in self.owners.drain(..) {
+            self.slots[owner as usize].item = None;
+            self.free.push(owner);
+        }
+        self.items.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Every item, in the order they were added until one is removed
+    pub fn as_slice(&self) -> &[T] {
+        &self.items
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        &mut self.items
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.items.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, T> {
+        self.items.iter_mut()
+    }
+
+    pub fn handles_mut(&mut self) -> impl Iterator<Item = (Handle, &mut T)> {
+        let slots = &self.slots;
+        self.owners
+            .iter()
+            .zip(self.items.iter_mut())
+            .map(move |(owner, item)| {
+                let generation = slots[*owner as usize].generation;
+                (Handle::new(*owner, generation), item)
+            })
+    }
+
+    /// Every item with its handle
+    pub fn handles(&self) -> impl Iterator<Item = (Handle, &T)> {
+        let slots = &self.slots;
+        self.owners
+            .iter()
+            .zip(self.items.iter())
+            .map(move |(owner, item)| {
+                let generation = slots[*owner as usize].generation;
+                (Handle::new(*owner, generation), item)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removed_items_leave_stale_handles() {
+        let mut slots = Slots::new();
+        let a = slots.insert("a");
+        let b = slots.insert("b");
+        assert_eq!(slots.remove(a), Some("a"));
+        assert_eq!(slots.remove(a), None);
+        assert!(!slots.contains(a));
+
+        // the slot is reused, the old handle still finds nothing
+        let c = slots.insert("c");
+        assert_eq!(c.index(), a.index());
+        assert_ne!(c, a);
+        assert_eq!(slots.get(a), None);
+        assert_eq!(slots.get(c), Some(&"c"));
+        assert_eq!(slots.get(b), Some(&"b"));
+        assert!(!slots.contains(Handle::default()));
+    }
+
+    #[test]
+    fn items_stay_contiguous() {
+        let mut slots = Slots::new();
+        let handles = (0..5).map(|i| slots.insert(i)).collect::<Vec<_>>();
+        // the last item takes the removed one's place
+        slots.remove(handles[1]);
+        assert_eq!(slots.as_slice(), &[0, 4, 2, 3]);
+        slots.remove(handles[3]);
+        assert_eq!(slots.as_slice(), &[0, 4, 2]);
+        assert_eq!(slots.get(handles[4]), Some(&4));
+        if let Some(item) = slots.get_mut(handles[2]) {
+            *item = 20;
+        }
+        let listed = slots.handles().map(|(h, i)| (h, *i)).collect::<Vec<_>>();
+        assert_eq!(
+            listed,
+            vec![(handles[0], 0), (handles[4], 4), (handles[2], 20)]
+        );
+    }
+
+    #[test]
+    fn clearing_invalidates_every_handle() {
+        let mut slots = Slots::new();
+        let a = slots.insert_with(|handle| handle);
+        assert_eq!(slots.get(a), Some(&a));
+        slots.clear();
+        assert!(slots.is_empty());
+        assert!(!slots.contains(a));
+        let b = slots.insert_with(|handle| handle);
+        assert_eq!(b.index(), a.index());
+        assert_eq!(b.generation(), a.generation() + 1);
+    }
+}
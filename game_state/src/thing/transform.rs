@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+
+use nalgebra::{Matrix4, UnitQuaternion, Vector3};
+
+use super::{FacetId, Slots, ThingId};
+
+//
+// Where a thing is: its translation, rotation and scale, relative to its parent thing if it has
+// one. Every other facet, and the renderer, reads a thing's position from here.
+//
+// World matrices are cached. Changing a transform flags it dirty, `update` recomputes the dirty
+// transforms and everything below them once a frame, so the matrices read in between are the
+// last frame's.
+//
+// The hierarchy is kept by the World, apart from the facets, so a transform can be copied to
+// another thing or replaced through `World::get_mut` without taking its links along.
+//
+
+/// A thing in the hierarchy, with its transform so the hierarchy can be walked without locking
+/// things
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) struct Link {
+    pub thing: ThingId,
+    pub transform: FacetId,
+}
+
+/// A transform's place in the hierarchy, by its facet id
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Node {
+    pub parent: Option<Link>,
+    pub children: Vec<Link>,
+}
+
+pub(crate) type Hierarchy = HashMap<FacetId, Node>;
+
+#[derive(Debug, Clone)]
+pub struct TransformFacet {
+    translation: Vector3<f32>,
+    rotation: UnitQuaternion<f32>,
+    scale: Vector3<f32>,
+
+    world: Matrix4<f32>,
+    dirty: bool,
+}
+
+impl Default for TransformFacet {
+    fn default() -> Self {
+        TransformFacet::at(Vector3::zeros())
+    }
+}
+
+impl TransformFacet {
+    pub fn new(
+        translation: Vector3<f32>,
+        rotation: UnitQuaternion<f32>,
+        scale: Vector3<f32>,
+    ) -> Self {
+        let mut transform = TransformFacet {
+            translation,
+            rotation,
+            scale,
+            world: Matrix4::identity(),
+            dirty: true,
+        };
+        // right until the transform is given to a thing with a parent, it's dirty for that
+        transform.world = transform.local_matrix();
+        transform
+    }
+
+    /// Unrotated and unscaled
+    pub fn at(translation: Vector3<f32>) -> Self {
+        TransformFacet::new(
+            translation,
+            UnitQuaternion::identity(),
+            Vector3::new(1.0, 1.0, 1.0),
+        )
+    }
+
+    pub fn with_rotation(mut self, rotation: UnitQuaternion<f32>) -> Self {
+        self.rotation = rotation;
+        self.world = self.local_matrix();
+        self
+    }
+
+    pub fn with_scale(mut self, scale: Vector3<f32>) -> Self {
+        self.scale = scale;
+        self.world = self.local_matrix();
+        self
+    }
+
+    pub fn translation(&self) -> &Vector3<f32> {
+        &self.translation
+    }
+
+    pub fn rotation(&self) -> &UnitQuaternion<f32> {
+        &self.rotation
+    }
+
+    pub fn scale(&self) -> &Vector3<f32> {
+        &self.scale
+    }
+
+    pub fn set_translation(&mut self, translation: Vector3<f32>) {
+        self.translation = translation;
+        self.dirty = true;
+    }
+
+    pub fn translate(&mut self, offset: Vector3<f32>) {
+        self.set_translation(self.translation + offset);
+    }
+
+    pub fn set_rotation(&mut self, rotation: UnitQuaternion<f32>) {
+        self.rotation = rotation;
+        self.dirty = true;
+    }
+
+    pub fn set_scale(&mut self, scale: Vector3<f32>) {
+        self.scale = scale;
+        self.dirty = true;
+    }
+
+    /// Relative to the parent: translation * rotation * scale
+    pub fn local_matrix(&self) -> Matrix4<f32> {
+        Matrix4::new_translation(&self.translation)
+            * self.rotation.to_homogeneous()
+            * Matrix4::new_nonuniform_scaling(&self.scale)
+    }
+
+    /// The parent's world matrix times the local matrix, as of the last `World::update_transforms`
+    pub fn world_matrix(&self) -> &Matrix4<f32> {
+        &self.world
+    }
+
+    /// The inverse of the world matrix, which takes the world into the space of a camera here
+    pub fn view_matrix(&self) -> Matrix4<f32> {
+        self.world.try_inverse().unwrap_or_else(Matrix4::identity)
+    }
+
+    /// Changed since the world matrix was computed
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Recomputed on the next update, e.g. once the parent changed
+    pub(crate) fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+}
+
+/// Recomputes the world matrices of the dirty transforms and their descendants, parents
+/// first. Returns how many were recomputed.
+pub(crate) fn update(transforms: &mut Slots<TransformFacet>, hierarchy: &Hierarchy) -> usize {
+    let has_parent = |id| match hierarchy.get(&id) {
+        Some(node) => node.parent.is_some(),
+        None => false,
+    };
+    let mut pending = transforms
+        .handles()
+        .filter(|(id, _)| !has_parent(*id))
+        .map(|(id, _)| (id, None, false))
+        .collect::<Vec<_>>();
+    let mut updated = 0;
+    while let Some((id, parent, parent_changed)) = pending.pop() {
+        let transform = match transforms.get_mut(id) {
+            Some(transform) => transform,
+            None => continue,
+        };
+        let changed = parent_changed || transform.dirty;
+        if changed {
+            let local = transform.local_matrix();
+            transform.world = match parent {
+                Some(parent) => parent * local,
+                None => local,
+            };
+            transform.dirty = false;
+            updated += 1;
+        }
+        let world = transform.world;
+        for child in hierarchy
+            .get(&id)
+            .iter()
+            .flat_map(|node| node.children.iter())
+        {
+            pending.push((child.transform, Some(world), changed));
+        }
+    }
+    updated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::thing::World;
+
+    use nalgebra::Point3;
+
+    fn spawn(world: &mut World, transform: TransformFacet) -> ThingId {
+        let thing = world.start_thing().with_transform(transform).build();
+        let id = thing.lock().unwrap().id;
+        id
+    }
+
+    fn position(world: &World, thing: ThingId) -> Point3<f32> {
+        let transform = world.get::<TransformFacet>(thing).unwrap();
+        transform.world_matrix().transform_point(&Point3::origin())
+    }
+
+    #[test]
+    fn children_follow_their_parent() {
+        let mut world = World::new();
+        let parent = spawn(&mut world, TransformFacet::at(Vector3::new(1.0, 0.0, 0.0)));
+        let child = spawn(
+            &mut world,
+            TransformFacet::at(Vector3::new(0.0, 2.0, 0.0)).with_scale(Vector3::new(2.0, 2.0, 2.0)),
+        );
+        world.set_parent(child, Some(parent)).unwrap();
+        assert!(world.get::<TransformFacet>(child).unwrap().is_dirty());
+        // new transforms are dirty too, the parent is computed along with its child
+        assert_eq!(world.update_transforms(), 2);
+        assert_eq!(position(&world, child), Point3::new(1.0, 2.0, 0.0));
+
+        // only the moved transform is dirty, its child is recomputed along with it
+        if let Some(transform) = world.get_mut::<TransformFacet>(parent) {
+            transform.translate(Vector3::new(0.0, 0.0, 3.0));
+        }
+        assert_eq!(world.update_transforms(), 2);
+        assert_eq!(world.update_transforms(), 0);
+        assert_eq!(position(&world, child), Point3::new(1.0, 2.0, 3.0));
+
+        assert_eq!(world.children(parent), vec![child]);
+    }
+
+    #[test]
+    fn a_thing_cannot_be_its_own_ancestor() {
+        let mut world = World::new();
+        let a = spawn(&mut world, TransformFacet::default());
+        let b = spawn(&mut world, TransformFacet::default());
+        let unplaced = world.start_thing().build();
+        let unplaced = unplaced.lock().unwrap().id;
+        world.set_parent(b, Some(a)).unwrap();
+        assert!(world.set_parent(a, Some(b)).is_err());
+        assert!(world.set_parent(a, Some(a)).is_err());
+        assert!(world.set_parent(unplaced, Some(a)).is_err());
+
+        world.set_parent(b, None).unwrap();
+        world.set_parent(a, Some(b)).unwrap();
+        assert_eq!(world.parent(a), Some(b));
+        assert_eq!(world.children(b).len(), 1);
+    }
+
+    #[test]
+    fn copied_and_replaced_transforms_keep_the_hierarchy_apart() {
+        let mut world = World::new();
+        let parent = spawn(&mut world, TransformFacet::at(Vector3::new(1.0, 0.0, 0.0)));
+        let child = spawn(&mut world, TransformFacet::default());
+        world.set_parent(child, Some(parent)).unwrap();
+
+        // a copy of the child's transform is placed in the world, not under the parent
+        let copied = world.get::<TransformFacet>(child).unwrap().clone();
+        let copy = spawn(&mut world, copied);
+        assert_eq!(world.parent(copy), None);
+        assert_eq!(world.children(parent), vec![child]);
+
+        // a transform replaced through get_mut stays under the parent
+        if let Some(transform) = world.get_mut::<TransformFacet>(child) {
+            *transform = TransformFacet::at(Vector3::new(0.0, 2.0, 0.0));
+        }
+        assert_eq!(world.update_transforms(), 3);
+        assert_eq!(world.parent(child), Some(parent));
+        assert_eq!(position(&world, child), Point3::new(1.0, 2.0, 0.0));
+        assert_eq!(position(&world, copy), Point3::origin());
+    }
+
+    #[test]
+    fn despawning_detaches_children() {
+        let mut world = World::new();
+        let parent = spawn(&mut world, TransformFacet::at(Vector3::new(5.0, 0.0, 0.0)));
+        let child = spawn(&mut world, TransformFacet::default());
+        world.set_parent(child, Some(parent)).unwrap();
+        world.update_transforms();
+        assert_eq!(position(&world, child), Point3::new(5.0, 0.0, 0.0));
+
+        world.despawn(parent).unwrap();
+        assert_eq!(world.parent(child), None);
+        world.update_transforms();
+        assert_eq!(position(&world, child), Point3::origin());
+    }
+}
@@ -220,22 +220,31 @@ impl<T> Node<T> {
     }
 
     pub fn debug_draw(&self, lvl: usize) {
+        for line in self.debug_lines(lvl) {
+            println!("{}", line);
+        }
+    }
+
+    /// The hierarchy dump printed by `debug_draw`, a line per node
+    pub fn debug_lines(&self, lvl: usize) -> Vec<String> {
+        let mut lines = Vec::new();
         if lvl == 0 {
-            println!("-- Hierarchy Dump --");
+            lines.push("-- Hierarchy Dump --".to_string());
         }
         let c = if !self.children.is_empty() {
             "..."
         } else {
             ".leaf*"
         };
-        println!(
+        lines.push(format!(
             "{}{}{}",
             (0..lvl).map(|_| "....").collect::<String>(),
             self,
             c
-        );
+        ));
         for child in &self.children {
-            child.borrow().debug_draw(lvl + 1);
+            lines.extend(child.borrow().debug_lines(lvl + 1));
         }
+        lines
     }
 }
@@ -0,0 +1,634 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use sdl2::keyboard::Keycode;
+
+use crate::input::events::InputEvent;
+use crate::input::InputConsumer;
+
+//
+// Developer console: a drop-down line of input with its output above it. Commands are plain data
+// like UI events, a mod registers a command's name, arguments and owner, then reads what was
+// entered for it with `ConsoleAccess::take_console_commands` each update. Nothing in State calls
+// into a mod's code, so commands survive the mod being reloaded. State runs the built-in
+// commands itself.
+//
+// While open the console takes every key before the UI and gameplay see it.
+//
+
+/// Owner of the commands State runs itself
+pub const BUILTIN: &str = "state";
+
+const MAX_OUTPUT: usize = 200;
+const MAX_HISTORY: usize = 100;
+
+/// Opens and closes the console, and never types
+const TOGGLE_KEY: Keycode = Keycode::Backquote;
+const TOGGLE_TEXT: &str = "`";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArgKind {
+    Int,
+    /// Ints are accepted too
+    Float,
+    /// true/false, on/off, yes/no or 1/0
+    Bool,
+    Text,
+    /// One of the given words, which tab completes
+    OneOf(Vec<String>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Arg {
+    pub name: String,
+    pub kind: ArgKind,
+    pub optional: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArgValue {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Text(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Command {
+    pub name: String,
+    /// The mod that reads its invocations, or `BUILTIN`
+    pub owner: String,
+    pub help: String,
+    /// Optional arguments come last
+    pub args: Vec<Arg>,
+}
+
+impl Command {
+    pub fn new(name: &str, owner: &str) -> Self {
+        Command {
+            name: name.to_string(),
+            owner: owner.to_string(),
+            help: String::new(),
+            args: Vec::new(),
+        }
+    }
+
+    pub fn with_help(mut self, help: &str) -> Self {
+        self.help = help.to_string();
+        self
+    }
+
+    pub fn with_arg(mut self, name: &str, kind: ArgKind) -> Self {
+        self.args.push(Arg {
+            name: name.to_string(),
+            kind,
+            optional: false,
+        });
+        self
+    }
+
+    pub fn with_optional_arg(mut self, name: &str, kind: ArgKind) -> Self {
+        self.args.push(Arg {
+            name: name.to_string(),
+            kind,
+            optional: true,
+        });
+        self
+    }
+
+    /// e.g. `set <name> <value>`, optional arguments in brackets
+    pub fn usage(&self) -> String {
+        let mut usage = self.name.clone();
+        for arg in self.args.iter() {
+            if arg.optional {
+                usage.push_str(&format!(" [{}]", arg.name));
+            } else {
+                usage.push_str(&format!(" <{}>", arg.name));
+            }
+        }
+        usage
+    }
+
+    /// Checks and converts the words after the command's name
+    pub fn parse(&self, words: &[String]) -> Result<Invocation, String> {
+        if words.len() > self.args.len() {
+            return Err(format!("too many arguments, usage: {}", self.usage()));
+        }
+        let mut args = Vec::new();
+        for (i, arg) in self.args.iter().enumerate() {
+            let word = match words.get(i) {
+                Some(word) => word,
+                None if arg.optional => break,
+                None => return Err(format!("missing <{}>, usage: {}", arg.name, self.usage())),
+            };
+            let value = match &arg.kind {
+                ArgKind::Int => word.parse().ok().map(ArgValue::Int),
+                ArgKind::Float => word.parse().ok().map(ArgValue::Float),
+                ArgKind::Bool => parse_bool(word).map(ArgValue::Bool),
+                ArgKind::Text => Some(ArgValue::Text(word.clone())),
+                ArgKind::OneOf(choices) => choices
+                    .iter()
+                    .find(|c| *c == word)
+                    .map(|c| ArgValue::Text(c.clone())),
+            };
+            match value {
+                Some(value) => args.push(value),
+                None => return Err(format!("invalid <{}>: {}", arg.name, word)),
+            }
+        }
+        Ok(Invocation {
+            command: self.name.clone(),
+            args,
+        })
+    }
+}
+
+pub fn parse_bool(word: &str) -> Option<bool> {
+    match word.to_lowercase().as_str() {
+        "true" | "on" | "yes" | "1" => Some(true),
+        "false" | "off" | "no" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// A command as it was entered, its arguments checked against the command's
+#[derive(Debug, Clone, PartialEq)]
+pub struct Invocation {
+    pub command: String,
+    pub args: Vec<ArgValue>,
+}
+
+impl Invocation {
+    pub fn int(&self, i: usize) -> Option<i64> {
+        match self.args.get(i)? {
+            ArgValue::Int(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn float(&self, i: usize) -> Option<f64> {
+        match self.args.get(i)? {
+            ArgValue::Float(v) => Some(*v),
+            ArgValue::Int(v) => Some(*v as f64),
+            _ => None,
+        }
+    }
+
+    pub fn bool(&self, i: usize) -> Option<bool> {
+        match self.args.get(i)? {
+            ArgValue::Bool(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn text(&self, i: usize) -> Option<&str> {
+        match self.args.get(i)? {
+            ArgValue::Text(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+/// Words separated by whitespace, "double quoted" words may hold spaces
+pub fn split_words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+    let mut in_word = false;
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                in_word = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            c => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    words
+}
+
+fn quote(word: &str) -> String {
+    if word.is_empty() || word.contains(char::is_whitespace) {
+        format!("\"{}\"", word)
+    } else {
+        word.to_string()
+    }
+}
+
+pub struct Console {
+    commands: BTreeMap<String, Command>,
+    open: bool,
+    input: String,
+    output: VecDeque<String>,
+    history: Vec<String>,
+    /// Position in the history while browsing it with Up and Down
+    browsing: Option<usize>,
+    /// Lines entered, not yet run
+    entered: VecDeque<String>,
+    /// Invocations waiting for the mod owning their command, by owner
+    queued: HashMap<String, VecDeque<Invocation>>,
+}
+
+impl Default for Console {
+    fn default() -> Self {
+        let mut console = Console {
+            commands: BTreeMap::new(),
+            open: false,
+            input: String::new(),
+            output: VecDeque::new(),
+            history: Vec::new(),
+            browsing: None,
+            entered: VecDeque::new(),
+            queued: HashMap::new(),
+        };
+        let modes = ["wireframe", "points", "textured"];
+        let builtins = vec![
+            Command::new("help", BUILTIN)
+                .with_help("lists the commands, or shows how to use one")
+                .with_optional_arg("command", ArgKind::Text),
+            Command::new("clear", BUILTIN).with_help("clears the output"),
+            Command::new("vars", BUILTIN).with_help("lists the state variables"),
+            Command::new("get", BUILTIN)
+                .with_help("shows a state variable")
+                .with_arg("name", ArgKind::OneOf(Vec::new())),
+            Command::new("set", BUILTIN)
+                .with_help("changes a state variable, the value is read as the variable's kind")
+                .with_arg("name", ArgKind::OneOf(Vec::new()))
+                .with_arg("value", ArgKind::Text),
+            Command::new("things", BUILTIN).with_help("lists the things in the world"),
+            Command::new("scene", BUILTIN).with_help("dumps the scene graph of each render layer"),
+            Command::new("drawmode", BUILTIN)
+                .with_help("sets how windows are drawn, the next mode when none is given")
+                .with_optional_arg(
+                    "mode",
+                    ArgKind::OneOf(modes.iter().map(|m| m.to_string()).collect()),
+                )
+                .with_optional_arg("window", ArgKind::Int),
+        ];
+        for command in builtins {
+            console.commands.insert(command.name.clone(), command);
+        }
+        console
+    }
+}
+
+impl Console {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Replaces a command registered before by the same owner, e.g. when a mod is reloaded
+    pub fn register(&mut self, command: Command) -> Result<(), String> {
+        match self.commands.get(&command.name) {
+            Some(existing) if existing.owner != command.owner => Err(format!(
+                "command {} is already registered by {}",
+                command.name, existing.owner
+            )),
+            _ => {
+                self.commands.insert(command.name.clone(), command);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn unregister(&mut self, name: &str) -> Option<Command> {
+        self.commands.remove(name)
+    }
+
+    pub fn command(&self, name: &str) -> Option<&Command> {
+        self.commands.get(name)
+    }
+
+    /// Every command, by name
+    pub fn commands(&self) -> impl Iterator<Item = &Command> {
+        self.commands.values()
+    }
+
+    /// Updates the words an argument accepts, e.g. as the things they name come and go
+    pub fn set_choices(&mut self, command: &str, arg: usize, choices: Vec<String>) {
+        if let Some(arg) = self
+            .commands
+            .get_mut(command)
+            .and_then(|c| c.args.get_mut(arg))
+        {
+            arg.kind = ArgKind::OneOf(choices);
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+    }
+
+    pub fn close(&mut self) {
+        self.open = false;
+    }
+
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+
+    pub fn set_input(&mut self, input: &str) {
+        self.input = input.to_string();
+        self.browsing = None;
+    }
+
+    /// Oldest first
+    pub fn output(&self) -> &VecDeque<String> {
+        &self.output
+    }
+
+    pub fn print(&mut self, text: &str) {
+        for line in text.lines() {
+            self.output.push_back(line.to_string());
+        }
+        while self.output.len() > MAX_OUTPUT {
+            self.output.pop_front();
+        }
+    }
+
+    pub fn clear_output(&mut self) {
+        self.output.clear();
+    }
+
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    /// The line entered before the one shown
+    pub fn history_back(&mut self) {
+        let i = match self.browsing {
+            Some(i) => i.saturating_sub(1),
+            None if self.history.is_empty() => return,
+            None => self.history.len() - 1,
+        };
+        self.browsing = Some(i);
+        self.input = self.history[i].clone();
+    }
+
+    /// The line entered after the one shown, or an empty line past the last
+    pub fn history_forward(&mut self) {
+        match self.browsing {
+            Some(i) if i + 1 < self.history.len() => {
+                self.browsing = Some(i + 1);
+                self.input = self.history[i + 1].clone();
+            }
+            Some(_) => {
+                self.browsing = None;
+                self.input.clear();
+            }
+            None => {}
+        }
+    }
+
+    /// Completes the word being typed: a command's name, or one of the words its argument takes.
+    /// With several candidates, completes what they share and prints them.
+    pub fn complete(&mut self) {
+        let mut words = split_words(&self.input);
+        let new_word = self.input.is_empty() || self.input.ends_with(char::is_whitespace);
+        if new_word {
+            words.push(String::new());
+        }
+        let typed = words.pop().unwrap_or_default();
+
+        let candidates: Vec<String> = if words.is_empty() {
+            self.commands.keys().cloned().collect()
+        } else {
+            let arg = self
+                .commands
+                .get(&words[0])
+                .and_then(|c| c.args.get(words.len() - 1));
+            match arg.map(|a| &a.kind) {
+                Some(ArgKind::Bool) => vec!["false".to_string(), "true".to_string()],
+                Some(ArgKind::OneOf(choices)) => choices.clone(),
+                _ => Vec::new(),
+            }
+        };
+        let matching: Vec<&String> = candidates
+            .iter()
+            .filter(|c| c.starts_with(&typed))
+            .collect();
+
+        let completed = match matching.as_slice() {
+            [] => return,
+            [only] => format!("{} ", quote(only)),
+            [first, rest @ ..] => {
+                let mut shared = first.to_string();
+                for other in rest {
+                    while !other.starts_with(&shared) {
+                        shared.pop();
+                    }
+                }
+                let listed = matching
+                    .iter()
+                    .map(|c| c.as_str())
+                    .collect::<Vec<_>>()
+                    .join("  ");
+                self.print(&listed);
+                shared
+            }
+        };
+        let mut input = words.iter().map(|w| quote(w)).collect::<Vec<_>>().join(" ");
+        if !input.is_empty() {
+            input.push(' ');
+        }
+        input.push_str(&completed);
+        self.set_input(&input);
+    }
+
+    /// Enters the input line, to be run by State
+    pub fn submit(&mut self) {
+        let line = std::mem::take(&mut self.input);
+        self.browsing = None;
+        if line.trim().is_empty() {
+            return;
+        }
+        self.print(&format!("> {}", line));
+        if self.history.last() != Some(&line) {
+            self.history.push(line.clone());
+            if self.history.len() > MAX_HISTORY {
+                self.history.remove(0);
+            }
+        }
+        self.entered.push_back(line);
+    }
+
+    pub fn take_entered(&mut self) -> Vec<String> {
+        self.entered.drain(..).collect()
+    }
+
+    /// The owner of the line's command, and its invocation
+    pub fn parse_line(&self, line: &str) -> Result<(String, Invocation), String> {
+        let words = split_words(line);
+        let name = words.first().ok_or_else(|| "nothing entered".to_string())?;
+        let command = self
+            .commands
+            .get(name)
+            .ok_or_else(|| format!("unknown command: {}, try help", name))?;
+        let invocation = command.parse(&words[1..])?;
+        Ok((command.owner.clone(), invocation))
+    }
+
+    pub fn queue(&mut self, owner: &str, invocation: Invocation) {
+        self.queued
+            .entry(owner.to_string())
+            .or_default()
+            .push_back(invocation);
+    }
+
+    pub fn take_queued(&mut self, owner: &str) -> Vec<Invocation> {
+        match self.queued.get_mut(owner) {
+            Some(queued) => queued.drain(..).collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+impl InputConsumer for Console {
+    fn consume(&mut self, event: &InputEvent) -> bool {
+        match event {
+            InputEvent::KeyDown(_, code) => {
+                let key = Keycode::from_i32(*code as i32);
+                if key == Some(TOGGLE_KEY) {
+                    self.toggle();
+                    return true;
+                }
+                if !self.open {
+                    return false;
+                }
+                match key {
+                    Some(Keycode::Return) | Some(Keycode::KpEnter) => self.submit(),
+                    Some(Keycode::Backspace) => {
+                        self.input.pop();
+                    }
+                    Some(Keycode::Tab) => self.complete(),
+                    Some(Keycode::Up) => self.history_back(),
+                    Some(Keycode::Down) => self.history_forward(),
+                    Some(Keycode::Escape) => self.close(),
+                    _ => {}
+                }
+                true
+            }
+            InputEvent::TextInput(_, text) if text == TOGGLE_TEXT => true,
+            InputEvent::TextInput(_, text) if self.open => {
+                self.input.push_str(text);
+                true
+            }
+            InputEvent::TextEditing(..) => self.open,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spawn() -> Command {
+        Command::new("spawn", "mod_simulation")
+            .with_arg(
+                "model",
+                ArgKind::OneOf(vec!["cube".to_string(), "cone".to_string()]),
+            )
+            .with_optional_arg("count", ArgKind::Int)
+    }
+
+    #[test]
+    fn parses_arguments() {
+        let mut console = Console::new();
+        console.register(spawn()).unwrap();
+        assert!(console
+            .register(Command::new("spawn", "mod_input"))
+            .is_err());
+
+        let (owner, invocation) = console.parse_line("spawn cube 3").unwrap();
+        assert_eq!(owner, "mod_simulation");
+        assert_eq!(
+            (invocation.text(0), invocation.int(1)),
+            (Some("cube"), Some(3))
+        );
+        assert_eq!(console.parse_line("spawn cone").unwrap().1.args.len(), 1);
+        assert!(console.parse_line("spawn").is_err());
+        assert!(console.parse_line("spawn ball").is_err());
+        assert!(console.parse_line("spawn cube three").is_err());
+        assert!(console.parse_line("jump").is_err());
+
+        assert_eq!(
+            split_words(r#"say "hello there"  "" x"#),
+            vec!["say", "hello there", "", "x"]
+        );
+        // set only takes the names of variables State has
+        assert!(console.parse_line("set vsync on").is_err());
+        console.set_choices("set", 0, vec!["vsync".to_string()]);
+        let (owner, set) = console.parse_line("set vsync on").unwrap();
+        assert_eq!((owner.as_str(), set.text(1)), (BUILTIN, Some("on")));
+    }
+
+    #[test]
+    fn completes_names_and_choices() {
+        let mut console = Console::new();
+        console.register(spawn()).unwrap();
+        console.set_input("sp");
+        console.complete();
+        assert_eq!(console.input(), "spawn ");
+        console.complete();
+        // cube and cone share a "c", both are listed
+        assert_eq!(console.input(), "spawn c");
+        assert_eq!(
+            console.output().back().map(|s| s.as_str()),
+            Some("cube  cone")
+        );
+        console.set_input("spawn cu");
+        console.complete();
+        assert_eq!(console.input(), "spawn cube ");
+
+        let vsync = Command::new("vsync", "test").with_arg("on", ArgKind::Bool);
+        console.register(vsync).unwrap();
+        console.set_input("vsync t");
+        console.complete();
+        assert_eq!(console.input(), "vsync true ");
+    }
+
+    #[test]
+    fn takes_keys_while_open() {
+        let mut console = Console::new();
+        let key = |k: Keycode| InputEvent::KeyDown(0, k as i32 as u32);
+        let text = |t: &str| InputEvent::TextInput(0, t.to_string());
+
+        assert!(!console.consume(&key(Keycode::W)));
+        assert!(console.consume(&key(Keycode::Backquote)));
+        assert!(console.consume(&text("`")));
+        assert!(console.is_open());
+
+        for line in ["vars", "things"].iter() {
+            assert!(console.consume(&text(line)));
+            assert!(console.consume(&key(Keycode::Return)));
+        }
+        assert_eq!(console.take_entered(), vec!["vars", "things"]);
+        assert!(console.consume(&key(Keycode::Up)));
+        assert!(console.consume(&key(Keycode::Up)));
+        assert_eq!(console.input(), "vars");
+        console.consume(&key(Keycode::Down));
+        console.consume(&key(Keycode::Down));
+        assert_eq!(console.input(), "");
+        // releases are left for whoever saw the press
+        assert!(!console.consume(&InputEvent::KeyUp(0, Keycode::W as i32 as u32)));
+
+        console.consume(&key(Keycode::Escape));
+        assert!(!console.is_open());
+    }
+}
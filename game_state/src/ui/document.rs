@@ -0,0 +1,571 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use crate::input::screen::ScreenRect;
+use crate::ui::console::split_words;
+use crate::ui::draw::{Color, Theme};
+use crate::ui::layout::{Anchor, Arrangement, Insets, Layout};
+use crate::ui::text::parse_color;
+use crate::ui::view::UIWindow;
+use crate::ui::widget::{Widget, WidgetKind};
+use crate::Identity;
+
+//
+// UI documents: windows and their style declared in a file, e.g. assets/ui/pause.ui, so menus can
+// be changed without rebuilding a mod. mod_gui loads them, and again whenever they change on disk.
+//
+//   [style]
+//   <setting> = <value>              colors are #rgb, #rrggbb or #rrggbbaa
+//
+//   [layout]
+//   window <tag> <property>=<value> ...
+//     <kind> <tag> <property>=<value> ...
+//
+// Children are indented under their parent. Kinds: container, label, button, checkbox, slider,
+// text_field, scroll_panel. Values with spaces are "quoted".
+//
+// Style: the theme's colors (text, text_dim, button, button_hovered, button_pressed, field,
+// accent, focus), font_size, disabled_alpha, font (a .ttf or .otf path, for all UI text), and
+// the default spacing of stacks and padding of windows.
+//
+// Properties: size=<w>,<h> width height anchor padding margin (<all> | <v>,<h> | <t>,<r>,<b>,<l>)
+// arrange (free, hstack, vstack, grid) spacing columns grow shrink background visible enabled
+// tab_index, and per kind text placeholder checked min max value.
+//
+// Bindings: on_click, on_change and on_submit name the callback sent when the widget is clicked,
+// changed or submitted, see `UICallback`. bind=<variable> keeps a checkbox, slider or text field
+// and a State variable of its kind in step, or shows the variable in a label.
+// show_if=<variable> shows the widget while it's true.
+//
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Binding {
+    pub on_click: Option<String>,
+    pub on_change: Option<String>,
+    pub on_submit: Option<String>,
+    pub variable: Option<String>,
+    pub show_if: Option<String>,
+}
+
+impl Binding {
+    pub fn is_empty(&self) -> bool {
+        *self == Binding::default()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CallbackValue {
+    None,
+    Bool(bool),
+    Number(f32),
+    Text(String),
+}
+
+/// A named callback from a document's widget, e.g. `on_click=resume`
+#[derive(Debug, Clone, PartialEq)]
+pub struct UICallback {
+    pub name: String,
+    pub window: String,
+    pub widget: String,
+    pub value: CallbackValue,
+}
+
+#[derive(Debug, Clone)]
+pub struct WidgetSpec {
+    pub tag: String,
+    pub kind: WidgetKind,
+    pub layout: Layout,
+    pub background: Option<Color>,
+    pub visible: bool,
+    pub enabled: bool,
+    pub tab_index: i32,
+    pub binding: Binding,
+    pub children: Vec<WidgetSpec>,
+}
+
+impl WidgetSpec {
+    fn widget(&self) -> Widget {
+        let mut widget = Widget::new(&self.tag, self.kind.clone());
+        widget.layout = self.layout;
+        widget.background = self.background;
+        widget.visible = self.visible;
+        widget.enabled = self.enabled;
+        widget.tab_index = self.tab_index;
+        widget
+    }
+}
+
+/// A window built from a document, and the bindings of its widgets
+pub struct BuiltWindow {
+    pub window: UIWindow,
+    pub bindings: Vec<(Identity, Binding)>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct UIDocument {
+    /// The windows' theme, the scene's when the document has no style
+    pub theme: Option<Theme>,
+    /// A .ttf or .otf file, made the default font when the document is loaded
+    pub font: Option<String>,
+    /// The root of each window
+    pub windows: Vec<WidgetSpec>,
+}
+
+#[derive(PartialEq)]
+enum Section {
+    Style,
+    Layout,
+}
+
+/// Defaults from the style section
+struct Defaults {
+    spacing: i32,
+    padding: Insets,
+}
+
+impl UIDocument {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let config = fs::read_to_string(path)?;
+        Self::parse(&config)
+    }
+
+    pub fn parse(config: &str) -> Result<Self, Box<dyn Error>> {
+        let mut document = UIDocument::default();
+        let mut defaults = Defaults {
+            spacing: 0,
+            padding: Insets::default(),
+        };
+        let mut section = None;
+        // widgets not closed yet, with their indent
+        let mut open: Vec<(usize, WidgetSpec)> = Vec::new();
+
+        for (n, line) in config.lines().enumerate() {
+            let content = strip_comment(line);
+            let trimmed = content.trim();
+            if trimmed.is_empty() {
+                continue;
+            }
+            let err = |msg: String| format!("line {}: {}", n + 1, msg);
+
+            if trimmed.starts_with('[') && trimmed.ends_with(']') {
+                section = match &trimmed[1..trimmed.len() - 1] {
+                    "style" => Some(Section::Style),
+                    "layout" => Some(Section::Layout),
+                    _ => return Err(err(format!("unknown section '{}'", trimmed)).into()),
+                };
+                continue;
+            }
+
+            match section {
+                None => return Err(err("setting outside of a section".to_string()).into()),
+                Some(Section::Style) => {
+                    let mut parts = trimmed.splitn(2, '=');
+                    let name = parts.next().unwrap_or("").trim();
+                    let value = parts
+                        .next()
+                        .ok_or_else(|| err(format!("expected '=' after '{}'", name)))?
+                        .trim();
+                    if name == "font" {
+                        document.font = Some(value.trim_matches('"').to_string());
+                        continue;
+                    }
+                    let theme = document.theme.get_or_insert_with(Theme::default);
+                    set_style(theme, &mut defaults, name, value).map_err(err)?;
+                }
+                Some(Section::Layout) => {
+                    let indent = content.len() - content.trim_start().len();
+                    let spec = parse_widget(content.trim(), indent == 0, &defaults).map_err(err)?;
+                    while matches!(open.last(), Some((i, _)) if *i >= indent) {
+                        close(&mut open, &mut document.windows);
+                    }
+                    if indent > 0 && open.is_empty() {
+                        return Err(err("widget outside of a window".to_string()).into());
+                    }
+                    open.push((indent, spec));
+                }
+            }
+        }
+        while !open.is_empty() {
+            close(&mut open, &mut document.windows);
+        }
+        Ok(document)
+    }
+
+    /// UI windows for the document, not shown in a host yet
+    pub fn build(&self) -> Result<Vec<BuiltWindow>, String> {
+        let mut built = Vec::new();
+        for spec in self.windows.iter() {
+            let mut window = UIWindow::new(spec.tag.clone(), ScreenRect::new(0, 0, 0, 0));
+            window.theme = self.theme.clone();
+            let mut bindings = Vec::new();
+            let root = window.widgets.root();
+            if let Some(widget) = window.widgets.get_mut(root) {
+                widget.layout = spec.layout;
+                widget.background = spec.background;
+                widget.visible = spec.visible;
+                widget.enabled = spec.enabled;
+            }
+            if !spec.binding.is_empty() {
+                bindings.push((root, spec.binding.clone()));
+            }
+            for child in spec.children.iter() {
+                add_widget(&mut window, root, child, &mut bindings)?;
+            }
+            built.push(BuiltWindow { window, bindings });
+        }
+        Ok(built)
+    }
+}
+
+fn add_widget(
+    window: &mut UIWindow,
+    parent: Identity,
+    spec: &WidgetSpec,
+    bindings: &mut Vec<(Identity, Binding)>,
+) -> Result<(), String> {
+    let id = window.widgets.add(parent, spec.widget())?;
+    if !spec.binding.is_empty() {
+        bindings.push((id, spec.binding.clone()));
+    }
+    for child in spec.children.iter() {
+        add_widget(window, id, child, bindings)?;
+    }
+    Ok(())
+}
+
+/// Moves the last open widget into its parent, or the windows when it's a window
+fn close(open: &mut Vec<(usize, WidgetSpec)>, windows: &mut Vec<WidgetSpec>) {
+    if let Some((_, spec)) = open.pop() {
+        match open.last_mut() {
+            Some((_, parent)) => parent.children.push(spec),
+            None => windows.push(spec),
+        }
+    }
+}
+
+/// The line up to a '#' starting a comment, one not in quotes or starting a color
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted && !is_color(line, i) => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Whether the '#' at `i` starts a value of exactly 3, 6 or 8 hex digits, e.g. `accent = #fff`
+fn is_color(line: &str, i: usize) -> bool {
+    let starts_value = line[..i].trim_end().ends_with('=');
+    let digits = line[i + 1..]
+        .split(char::is_whitespace)
+        .next()
+        .unwrap_or("");
+    starts_value
+        && matches!(digits.len(), 3 | 6 | 8)
+        && digits.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn set_style(
+    theme: &mut Theme,
+    defaults: &mut Defaults,
+    name: &str,
+    value: &str,
+) -> Result<(), String> {
+    let color = || parse_color(value).ok_or_else(|| format!("invalid color '{}'", value));
+    let number = || {
+        value
+            .parse::<f32>()
+            .map_err(|_| format!("invalid number '{}'", value))
+    };
+    match name {
+        "text" => theme.text = color()?,
+        "text_dim" => theme.text_dim = color()?,
+        "button" => theme.button = color()?,
+        "button_hovered" => theme.button_hovered = color()?,
+        "button_pressed" => theme.button_pressed = color()?,
+        "field" => theme.field = color()?,
+        "accent" => theme.accent = color()?,
+        "focus" => theme.focus = color()?,
+        "font_size" => theme.font_size = number()?,
+        "disabled_alpha" => theme.disabled_alpha = number()?,
+        "spacing" => defaults.spacing = number()? as i32,
+        "padding" => defaults.padding = parse_insets(value)?,
+        _ => return Err(format!("unknown style '{}'", name)),
+    }
+    Ok(())
+}
+
+fn parse_widget(line: &str, is_window: bool, defaults: &Defaults) -> Result<WidgetSpec, String> {
+    let words = split_words(line);
+    let (kind, tag) = match (words.first(), words.get(1)) {
+        (Some(kind), Some(tag)) => (kind.as_str(), tag.clone()),
+        _ => return Err(format!("expected '<kind> <tag>', got '{}'", line)),
+    };
+    if (kind == "window") != is_window {
+        return Err("windows, and only windows, are at the start of a line".to_string());
+    }
+    let kind = match kind {
+        "window" | "container" => WidgetKind::Container,
+        "label" => WidgetKind::Label {
+            text: String::new(),
+        },
+        "button" => WidgetKind::Button {
+            text: String::new(),
+        },
+        "checkbox" => WidgetKind::Checkbox {
+            text: String::new(),
+            checked: false,
+        },
+        "slider" => WidgetKind::Slider {
+            min: 0.0,
+            max: 1.0,
+            value: 0.0,
+        },
+        "text_field" => WidgetKind::TextField {
+            text: String::new(),
+            placeholder: String::new(),
+            cursor: 0,
+        },
+        "scroll_panel" => WidgetKind::ScrollPanel {
+            offset: 0,
+            content_height: 0,
+        },
+        other => return Err(format!("unknown widget kind '{}'", other)),
+    };
+    let mut spec = WidgetSpec {
+        layout: if is_window {
+            Layout {
+                padding: defaults.padding,
+                ..Default::default()
+            }
+        } else {
+            Widget::new(&tag, kind.clone()).layout
+        },
+        tag,
+        kind,
+        background: None,
+        visible: true,
+        enabled: true,
+        tab_index: 0,
+        binding: Binding::default(),
+        children: Vec::new(),
+    };
+
+    let mut arrange = None;
+    let mut spacing = defaults.spacing;
+    let mut columns = 1;
+    for word in words.iter().skip(2) {
+        let mut parts = word.splitn(2, '=');
+        let key = parts.next().unwrap_or("");
+        let value = parts
+            .next()
+            .ok_or_else(|| format!("expected <property>=<value>, got '{}'", word))?;
+        let invalid = || format!("invalid {} '{}'", key, value);
+        let int = || value.parse::<i32>().map_err(|_| invalid());
+        let float = || value.parse::<f32>().map_err(|_| invalid());
+        let boolean = || value.parse::<bool>().map_err(|_| invalid());
+        let name = || Some(value.to_string());
+        let layout = &mut spec.layout;
+        match (key, &mut spec.kind) {
+            ("size", _) => {
+                let (w, h) = parse_pair(value).ok_or_else(invalid)?;
+                layout.size = (w, h);
+            }
+            ("width", _) => layout.size.0 = int()?,
+            ("height", _) => layout.size.1 = int()?,
+            ("anchor", _) => layout.anchor = parse_anchor(value).ok_or_else(invalid)?,
+            ("padding", _) => layout.padding = parse_insets(value)?,
+            ("margin", _) => layout.margin = parse_insets(value)?,
+            ("arrange", _) => arrange = Some(value),
+            ("spacing", _) => spacing = int()?,
+            ("columns", _) => columns = int()?.max(1) as usize,
+            ("grow", _) => layout.grow = float()?,
+            ("shrink", _) => layout.shrink = float()?,
+            ("background", _) => spec.background = Some(parse_color(value).ok_or_else(invalid)?),
+            ("visible", _) => spec.visible = boolean()?,
+            ("enabled", _) => spec.enabled = boolean()?,
+            ("tab_index", _) => spec.tab_index = int()?,
+            ("on_click", _) => spec.binding.on_click = name(),
+            ("on_change", _) => spec.binding.on_change = name(),
+            ("on_submit", _) => spec.binding.on_submit = name(),
+            ("bind", _) => spec.binding.variable = name(),
+            ("show_if", _) => spec.binding.show_if = name(),
+            ("text", WidgetKind::Label { text })
+            | ("text", WidgetKind::Button { text })
+            | ("text", WidgetKind::Checkbox { text, .. }) => *text = value.to_string(),
+            ("placeholder", WidgetKind::TextField { placeholder, .. }) => {
+                *placeholder = value.to_string()
+            }
+            ("checked", WidgetKind::Checkbox { checked, .. }) => *checked = boolean()?,
+            ("min", WidgetKind::Slider { min, .. }) => *min = float()?,
+            ("max", WidgetKind::Slider { max, .. }) => *max = float()?,
+            ("value", WidgetKind::Slider { value: v, .. }) => *v = float()?,
+            _ => return Err(format!("unknown property '{}' for {}", key, spec.tag)),
+        }
+    }
+    spec.layout.arrangement = match arrange {
+        None | Some("free") => Arrangement::Free,
+        Some("hstack") => Arrangement::HStack { spacing },
+        Some("vstack") => Arrangement::VStack { spacing },
+        Some("grid") => Arrangement::Grid { columns, spacing },
+        Some(other) => return Err(format!("invalid arrange '{}'", other)),
+    };
+    Ok(spec)
+}
+
+fn parse_pair(value: &str) -> Option<(i32, i32)> {
+    let mut parts = value.split(',');
+    let a = parts.next()?.trim().parse().ok()?;
+    let b = parts.next()?.trim().parse().ok()?;
+    Some((a, b))
+}
+
+fn parse_insets(value: &str) -> Result<Insets, String> {
+    let values = value
+        .split(',')
+        .map(|v| v.trim().parse::<i32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| format!("invalid insets '{}'", value))?;
+    match values.as_slice() {
+        [all] => Ok(Insets::new(*all, *all, *all, *all)),
+        [v, h] => Ok(Insets::new(*v, *h, *v, *h)),
+        [t, r, b, l] => Ok(Insets::new(*t, *r, *b, *l)),
+        _ => Err(format!("invalid insets '{}'", value)),
+    }
+}
+
+fn parse_anchor(value: &str) -> Option<Anchor> {
+    Some(match value {
+        "top_left" => Anchor::TOP_LEFT,
+        "top" => Anchor::TOP,
+        "top_right" => Anchor::TOP_RIGHT,
+        "left" => Anchor::LEFT,
+        "center" => Anchor::CENTER,
+        "right" => Anchor::RIGHT,
+        "bottom_left" => Anchor::BOTTOM_LEFT,
+        "bottom" => Anchor::BOTTOM,
+        "bottom_right" => Anchor::BOTTOM_RIGHT,
+        "fill" => Anchor::FILL,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAUSE: &str = r#"
+# a comment
+[style]
+accent = #ff8000
+font = assets/fonts/menu.ttf
+spacing = 6
+
+[layout]
+window pause anchor=center size=300,200 padding=8,16 arrange=vstack show_if=paused background=#101010e0
+  label title text="Paused, press Esc"   # trailing comment
+  container options arrange=hstack spacing=2
+    checkbox grab text="Grab mouse" bind=mouse_grabbed
+    slider volume min=0 max=10 value=5 on_change=set_volume
+  button resume text=Resume on_click=resume
+window hud anchor=top_right
+"#;
+
+    #[test]
+    fn colors_are_values_of_three_six_or_eight_digits() {
+        assert_eq!(strip_comment("accent = #fff # white"), "accent = #fff ");
+        assert_eq!(
+            strip_comment("background=#101010e0"),
+            "background=#101010e0"
+        );
+        assert_eq!(
+            strip_comment("label a text=Hi #add more"),
+            "label a text=Hi "
+        );
+        assert_eq!(strip_comment("#fff"), "");
+        assert_eq!(strip_comment("accent = #ff80"), "accent = ");
+        assert_eq!(strip_comment("text=\"#1 #abc\""), "text=\"#1 #abc\"");
+        assert_eq!(parse_color("#f80"), parse_color("#ff8800"));
+    }
+
+    #[test]
+    fn parses_windows_and_style() {
+        let document = UIDocument::parse(PAUSE).unwrap();
+        let theme = document.theme.as_ref().unwrap();
+        assert_eq!(theme.accent, [1.0, 128.0 / 255.0, 0.0, 1.0]);
+        assert_eq!(document.font.as_deref(), Some("assets/fonts/menu.ttf"));
+        assert_eq!(document.windows.len(), 2);
+
+        let pause = &document.windows[0];
+        assert_eq!(pause.layout.anchor, Anchor::CENTER);
+        assert_eq!(pause.layout.size, (300, 200));
+        assert_eq!(pause.layout.padding, Insets::new(8, 16, 8, 16));
+        assert_eq!(pause.layout.arrangement, Arrangement::VStack { spacing: 6 });
+        assert_eq!(pause.binding.show_if.as_deref(), Some("paused"));
+        assert_eq!(pause.children.len(), 3);
+
+        let title = &pause.children[0];
+        assert_eq!(
+            title.kind,
+            WidgetKind::Label {
+                text: "Paused, press Esc".to_string()
+            }
+        );
+        let options = &pause.children[1];
+        assert_eq!(
+            options.layout.arrangement,
+            Arrangement::HStack { spacing: 2 }
+        );
+        assert_eq!(
+            options.children[0].binding.variable.as_deref(),
+            Some("mouse_grabbed")
+        );
+        assert!(
+            matches!(options.children[1].kind, WidgetKind::Slider { max, value, .. } if max == 10.0 && value == 5.0)
+        );
+        assert_eq!(
+            pause.children[2].binding.on_click.as_deref(),
+            Some("resume")
+        );
+    }
+
+    #[test]
+    fn reports_the_line_at_fault() {
+        let errors = [
+            ("[layout]\n  label orphan", "line 2"),
+            (
+                "[layout]\nwindow w\n  label l colour=red",
+                "unknown property",
+            ),
+            ("[layout]\nwindow w\n  wheel w", "unknown widget kind"),
+            ("[style]\naccent = red", "invalid color"),
+            ("label l", "outside of a section"),
+        ];
+        for (config, expected) in errors.iter() {
+            let err = UIDocument::parse(config).unwrap_err().to_string();
+            assert!(err.contains(expected), "{}: {}", config, err);
+        }
+    }
+
+    #[test]
+    fn builds_windows_with_their_bindings() {
+        let document = UIDocument::parse(PAUSE).unwrap();
+        let built = document.build().unwrap();
+        assert_eq!(built.len(), 2);
+        let pause = &built[0];
+        assert_eq!(pause.window.tag, "pause");
+        assert!(pause.window.theme.is_some());
+        // the window, checkbox, slider and button
+        assert_eq!(pause.bindings.len(), 4);
+        let resume = pause.window.widgets.find("resume").unwrap();
+        assert!(pause
+            .bindings
+            .iter()
+            .any(|(id, b)| *id == resume && b.on_click.as_deref() == Some("resume")));
+        assert_eq!(pause.window.widgets.len(), 6);
+    }
+}
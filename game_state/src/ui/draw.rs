@@ -0,0 +1,480 @@
+use std::ops::Range;
+use std::sync::{Arc, RwLock};
+
+use crate::input::screen::ScreenRect;
+use crate::ui::layout::Anchor;
+use crate::ui::widget::{WidgetKind, WidgetTree};
+use crate::Identity;
+
+//
+// UI drawing: each host window gets a draw list of screen space commands (quads, textured quads,
+// text runs and clip rects) in physical pixels, drawn over the 3D render layers. The list knows
+// nothing about the renderer drawing it, `tessellate` turns it into one indexed triangle mesh with
+// a batch per clip rect and texture, for any backend to upload.
+//
+
+/// Linear RGBA, 0.0 - 1.0
+pub type Color = [f32; 4];
+
+/// Pixels for textured quads, shared with whoever changes them. Renderers upload them keyed by
+/// id, and again when the version moves on: only the dirty rect if they have the version before.
+#[derive(Debug, Clone)]
+pub struct UITexture {
+    pub id: Identity,
+    pub version: u64,
+    /// Pixels changed since the previous version, None when any may have
+    pub dirty: Option<ScreenRect>,
+    pub image: Arc<RwLock<image::RgbaImage>>,
+}
+
+impl UITexture {
+    pub fn new(id: Identity, image: image::RgbaImage) -> Self {
+        UITexture {
+            id,
+            version: 0,
+            dirty: None,
+            image: Arc::new(RwLock::new(image)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum DrawCommand {
+    Quad {
+        rect: ScreenRect,
+        color: Color,
+    },
+    Image {
+        rect: ScreenRect,
+        texture: UITexture,
+        /// u0, v0, u1, v1
+        uv: [f32; 4],
+        color: Color,
+    },
+    /// A run of text placed within `bounds`, which may hold color markup (see `ui::text`)
+    Text {
+        bounds: ScreenRect,
+        text: String,
+        /// Height in pixels
+        size: f32,
+        color: Color,
+        anchor: Anchor,
+        /// Wrap lines at the width of the bounds
+        wrap: bool,
+    },
+    /// Nothing is drawn outside the rect, nor outside any clip rect pushed before it
+    PushClip(ScreenRect),
+    PopClip,
+}
+
+#[derive(Debug, Clone)]
+pub struct DrawList {
+    /// The host window drawn over
+    pub window: Identity,
+    pub commands: Vec<DrawCommand>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UIVertex {
+    pub position: [f32; 2],
+    pub uv: [f32; 2],
+    pub color: Color,
+}
+
+/// Indices drawn with the same clip rect and texture
+#[derive(Debug, Clone)]
+pub struct DrawBatch {
+    pub clip: Option<ScreenRect>,
+    pub texture: Option<UITexture>,
+    pub indices: Range<u32>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct UIMesh {
+    pub vertices: Vec<UIVertex>,
+    pub indices: Vec<u32>,
+    pub batches: Vec<DrawBatch>,
+}
+
+impl UIMesh {
+    fn add_quad(
+        &mut self,
+        rect: &ScreenRect,
+        uv: [f32; 4],
+        color: Color,
+        texture: Option<&UITexture>,
+        clip: Option<&ScreenRect>,
+    ) {
+        if rect.is_empty() || matches!(clip, Some(c) if c.intersection(rect).is_empty()) {
+            return;
+        }
+        let base = self.vertices.len() as u32;
+        let (x0, y0) = (rect.x as f32, rect.y as f32);
+        let (x1, y1) = ((rect.x + rect.w) as f32, (rect.y + rect.h) as f32);
+        for (position, uv) in [
+            ([x0, y0], [uv[0], uv[1]]),
+            ([x1, y0], [uv[2], uv[1]]),
+            ([x1, y1], [uv[2], uv[3]]),
+            ([x0, y1], [uv[0], uv[3]]),
+        ]
+        .iter()
+        {
+            self.vertices.push(UIVertex {
+                position: *position,
+                uv: *uv,
+                color,
+            });
+        }
+        let start = self.indices.len() as u32;
+        self.indices
+            .extend([0, 1, 2, 0, 2, 3].iter().map(|i| base + i));
+        let end = self.indices.len() as u32;
+
+        let texture_id = texture.map(|t| t.id);
+        match self.batches.last_mut() {
+            Some(batch)
+                if batch.clip.as_ref() == clip
+                    && batch.texture.as_ref().map(|t| t.id) == texture_id =>
+            {
+                batch.indices.end = end
+            }
+            _ => self.batches.push(DrawBatch {
+                clip: clip.cloned(),
+                texture: texture.cloned(),
+                indices: start..end,
+            }),
+        }
+    }
+}
+
+impl DrawList {
+    pub fn new(window: Identity) -> Self {
+        DrawList {
+            window,
+            commands: Vec::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    pub fn quad(&mut self, rect: ScreenRect, color: Color) {
+        self.commands.push(DrawCommand::Quad { rect, color });
+    }
+
+    pub fn image(&mut self, rect: ScreenRect, texture: UITexture, uv: [f32; 4], color: Color) {
+        self.commands.push(DrawCommand::Image {
+            rect,
+            texture,
+            uv,
+            color,
+        });
+    }
+
+    pub fn text(
+        &mut self,
+        bounds: ScreenRect,
+        text: &str,
+        size: f32,
+        color: Color,
+        anchor: Anchor,
+        wrap: bool,
+    ) {
+        self.commands.push(DrawCommand::Text {
+            bounds,
+            text: text.to_string(),
+            size,
+            color,
+            anchor,
+            wrap,
+        });
+    }
+
+    /// A rect's border, `width` pixels wide, drawn inside it
+    pub fn outline(&mut self, rect: &ScreenRect, width: i32, color: Color) {
+        let w = width.min(rect.w / 2).min(rect.h / 2).max(0);
+        let (x, y, right, bottom) = (rect.x, rect.y, rect.x + rect.w, rect.y + rect.h);
+        self.quad(ScreenRect::new(x, y, rect.w, w), color);
+        self.quad(ScreenRect::new(x, bottom - w, rect.w, w), color);
+        self.quad(ScreenRect::new(x, y + w, w, rect.h - 2 * w), color);
+        self.quad(ScreenRect::new(right - w, y + w, w, rect.h - 2 * w), color);
+    }
+
+    pub fn push_clip(&mut self, rect: ScreenRect) {
+        self.commands.push(DrawCommand::PushClip(rect));
+    }
+
+    pub fn pop_clip(&mut self) {
+        self.commands.push(DrawCommand::PopClip);
+    }
+
+    /// Quads and images as triangles, batched by clip rect and texture. Text runs are
+    /// skipped, `ui::text::typeset` turns them into textured quads first.
+    pub fn tessellate(&self) -> UIMesh {
+        let mut mesh = UIMesh::default();
+        let mut clips: Vec<ScreenRect> = Vec::new();
+        for command in self.commands.iter() {
+            match command {
+                DrawCommand::Quad { rect, color } => {
+                    mesh.add_quad(rect, [0.0, 0.0, 1.0, 1.0], *color, None, clips.last())
+                }
+                DrawCommand::Image {
+                    rect,
+                    texture,
+                    uv,
+                    color,
+                } => mesh.add_quad(rect, *uv, *color, Some(texture), clips.last()),
+                DrawCommand::Text { .. } => {}
+                DrawCommand::PushClip(rect) => {
+                    let clip = match clips.last() {
+                        Some(outer) => outer.intersection(rect),
+                        None => rect.clone(),
+                    };
+                    clips.push(clip);
+                }
+                DrawCommand::PopClip => {
+                    clips.pop();
+                }
+            }
+        }
+        mesh
+    }
+}
+
+/// Colors and sizes widgets are drawn with
+#[derive(Debug, Clone)]
+pub struct Theme {
+    pub text: Color,
+    pub text_dim: Color,
+    pub button: Color,
+    pub button_hovered: Color,
+    pub button_pressed: Color,
+    pub field: Color,
+    pub accent: Color,
+    pub focus: Color,
+    /// Text height in logical pixels
+    pub font_size: f32,
+    /// Multiplies the alpha of disabled widgets
+    pub disabled_alpha: f32,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            text: [0.92, 0.92, 0.92, 1.0],
+            text_dim: [0.6, 0.6, 0.6, 1.0],
+            button: [0.22, 0.24, 0.28, 0.9],
+            button_hovered: [0.3, 0.33, 0.38, 0.9],
+            button_pressed: [0.16, 0.17, 0.2, 0.9],
+            field: [0.1, 0.1, 0.12, 0.9],
+            accent: [0.3, 0.55, 0.9, 1.0],
+            focus: [0.95, 0.75, 0.3, 1.0],
+            font_size: 16.0,
+            disabled_alpha: 0.4,
+        }
+    }
+}
+
+/// Widgets drawn differently while the pointer is on them, pressed or focused
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Highlight {
+    pub hovered: Option<Identity>,
+    pub pressed: Option<Identity>,
+    pub focused: Option<Identity>,
+}
+
+/// Adds a tree's visible widgets to the draw list, parents under their children
+pub fn draw_tree(list: &mut DrawList, tree: &WidgetTree, theme: &Theme, highlight: &Highlight) {
+    draw_widget(list, tree, tree.root(), theme, highlight);
+}
+
+fn draw_widget(
+    list: &mut DrawList,
+    tree: &WidgetTree,
+    id: Identity,
+    theme: &Theme,
+    highlight: &Highlight,
+) {
+    let widget = match tree.get(id) {
+        Some(w) if w.visible => w,
+        _ => return,
+    };
+    let bounds = &widget.view.bounds;
+    let scale = tree.scale();
+    let size = theme.font_size * scale;
+    let alpha = if widget.enabled {
+        1.0
+    } else {
+        theme.disabled_alpha
+    };
+    let fade = |c: Color| [c[0], c[1], c[2], c[3] * alpha];
+    let px = |v: f32| (v * scale).round() as i32;
+
+    if let Some(background) = widget.background {
+        list.quad(bounds.clone(), fade(background));
+    }
+    let button_color = if highlight.pressed == Some(id) {
+        theme.button_pressed
+    } else if highlight.hovered == Some(id) {
+        theme.button_hovered
+    } else {
+        theme.button
+    };
+
+    match &widget.kind {
+        WidgetKind::Container => {}
+        WidgetKind::Label { text } => list.text(
+            bounds.clone(),
+            text,
+            size,
+            fade(theme.text),
+            Anchor::LEFT,
+            true,
+        ),
+        WidgetKind::Button { text } => {
+            list.quad(bounds.clone(), fade(button_color));
+            list.text(
+                bounds.clone(),
+                text,
+                size,
+                fade(theme.text),
+                Anchor::CENTER,
+                false,
+            );
+        }
+        WidgetKind::Checkbox { text, checked } => {
+            let side = bounds.h;
+            let check_box = ScreenRect::new(bounds.x, bounds.y, side, side);
+            list.quad(check_box.clone(), fade(button_color));
+            if *checked {
+                let inset = side / 4;
+                let mark = ScreenRect::new(
+                    bounds.x + inset,
+                    bounds.y + inset,
+                    side - 2 * inset,
+                    side - 2 * inset,
+                );
+                list.quad(mark, fade(theme.accent));
+            }
+            let gap = side + px(6.0);
+            let label = ScreenRect::new(bounds.x + gap, bounds.y, bounds.w - gap, bounds.h);
+            list.text(label, text, size, fade(theme.text), Anchor::LEFT, false);
+        }
+        WidgetKind::Slider { min, max, value } => {
+            let track_h = px(4.0).max(1);
+            let track_y = bounds.y + (bounds.h - track_h) / 2;
+            let t = if max > min {
+                (value - min) / (max - min)
+            } else {
+                0.0
+            };
+            let filled = (bounds.w as f32 * t).round() as i32;
+            list.quad(
+                ScreenRect::new(bounds.x, track_y, bounds.w, track_h),
+                fade(theme.field),
+            );
+            list.quad(
+                ScreenRect::new(bounds.x, track_y, filled, track_h),
+                fade(theme.accent),
+            );
+            let knob_w = px(10.0);
+            let knob = ScreenRect::new(bounds.x + filled - knob_w / 2, bounds.y, knob_w, bounds.h);
+            list.quad(knob, fade(button_color));
+        }
+        WidgetKind::TextField {
+            text, placeholder, ..
+        } => {
+            list.quad(bounds.clone(), fade(theme.field));
+            let inner = ScreenRect::new(bounds.x + px(4.0), bounds.y, bounds.w - px(8.0), bounds.h);
+            list.push_clip(inner.clone());
+            if text.is_empty() {
+                list.text(
+                    inner,
+                    placeholder,
+                    size,
+                    fade(theme.text_dim),
+                    Anchor::LEFT,
+                    false,
+                );
+            } else {
+                list.text(inner, text, size, fade(theme.text), Anchor::LEFT, false);
+            }
+            list.pop_clip();
+        }
+        WidgetKind::ScrollPanel { .. } => {}
+    }
+
+    let clips = matches!(widget.kind, WidgetKind::ScrollPanel { .. });
+    if clips {
+        list.push_clip(bounds.clone());
+    }
+    for child in widget.children() {
+        draw_widget(list, tree, *child, theme, highlight);
+    }
+    if clips {
+        list.pop_clip();
+    }
+
+    if highlight.focused == Some(id) {
+        list.outline(bounds, px(2.0).max(1), theme.focus);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui::widget::Widget;
+
+    #[test]
+    fn batches_split_on_clip_and_texture() {
+        let texture = UITexture::new(7, image::RgbaImage::new(1, 1));
+        let mut list = DrawList::new(1);
+        list.quad(ScreenRect::new(0, 0, 10, 10), [1.0; 4]);
+        list.quad(ScreenRect::new(10, 0, 10, 10), [1.0; 4]);
+        list.push_clip(ScreenRect::new(0, 0, 50, 50));
+        list.push_clip(ScreenRect::new(40, 40, 50, 50));
+        // outside the clip rect
+        list.quad(ScreenRect::new(0, 0, 10, 10), [1.0; 4]);
+        list.image(
+            ScreenRect::new(45, 45, 4, 4),
+            texture,
+            [0.0, 0.0, 1.0, 1.0],
+            [1.0; 4],
+        );
+        list.pop_clip();
+        list.pop_clip();
+
+        let mesh = list.tessellate();
+        assert_eq!(mesh.vertices.len(), 12);
+        assert_eq!(mesh.batches.len(), 2);
+        assert_eq!(mesh.batches[0].indices, 0..12);
+        assert_eq!(mesh.batches[1].clip, Some(ScreenRect::new(40, 40, 10, 10)));
+        assert_eq!(mesh.batches[1].texture.as_ref().map(|t| t.id), Some(7));
+        assert_eq!(mesh.vertices[8].position, [45.0, 45.0]);
+    }
+
+    #[test]
+    fn scroll_panels_clip_their_children() {
+        let mut tree = WidgetTree::new(ScreenRect::new(0, 0, 100, 100));
+        let root = tree.root();
+        let panel = tree.add(root, Widget::scroll_panel("list")).unwrap();
+        let ok = tree.add(panel, Widget::button("ok", "OK")).unwrap();
+        tree.layout_in(&ScreenRect::new(0, 0, 100, 100), 1.0);
+
+        let mut list = DrawList::new(1);
+        let highlight = Highlight {
+            focused: Some(ok),
+            ..Default::default()
+        };
+        draw_tree(&mut list, &tree, &Theme::default(), &highlight);
+        assert!(matches!(list.commands[0], DrawCommand::PushClip(_)));
+        assert!(matches!(
+            &list.commands[2],
+            DrawCommand::Text { text, anchor, .. } if text == "OK" && *anchor == Anchor::CENTER
+        ));
+        // the focus outline, then the end of the panel
+        assert_eq!(list.commands.len(), 8);
+        assert!(matches!(list.commands[7], DrawCommand::PopClip));
+    }
+}
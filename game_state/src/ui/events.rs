@@ -1,8 +1,45 @@
+use crate::input::screen::ScreenPoint;
 use crate::Identity;
 
-#[derive(Debug, Copy, Clone)]
+/// Every event targets a widget (or window) by identity
+#[derive(Debug, Clone, PartialEq)]
 pub enum UIEvent {
     Clicked(Identity),
     GainedFocus(Identity),
     LeftFocus(Identity),
+    Toggled(Identity, bool),
+    ValueChanged(Identity, f32),
+    TextChanged(Identity, String),
+    /// Enter pressed in a text field
+    Submitted(Identity, String),
+    Scrolled(Identity, i32),
+    /// The pointer moved onto or off an interactive widget
+    HoverEntered(Identity),
+    HoverLeft(Identity),
+    /// The left button went down or up on the widget, or up anywhere after a press on it.
+    /// Points are in physical pixels, like widget bounds.
+    Pressed(Identity, ScreenPoint),
+    Released(Identity, ScreenPoint),
+    /// The pointer moved while the widget was pressed, wherever it is
+    Dragged(Identity, ScreenPoint),
+}
+
+impl UIEvent {
+    pub fn target(&self) -> Identity {
+        match self {
+            UIEvent::Clicked(id)
+            | UIEvent::GainedFocus(id)
+            | UIEvent::LeftFocus(id)
+            | UIEvent::Toggled(id, _)
+            | UIEvent::ValueChanged(id, _)
+            | UIEvent::TextChanged(id, _)
+            | UIEvent::Submitted(id, _)
+            | UIEvent::Scrolled(id, _)
+            | UIEvent::HoverEntered(id)
+            | UIEvent::HoverLeft(id)
+            | UIEvent::Pressed(id, _)
+            | UIEvent::Released(id, _)
+            | UIEvent::Dragged(id, _) => *id,
+        }
+    }
 }
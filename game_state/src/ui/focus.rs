@@ -0,0 +1,271 @@
+use sdl2::keyboard::Keycode;
+
+use crate::input::events::{InputEvent, JoyAxis, JoyButton};
+use crate::input::screen::ScreenRect;
+use crate::ui::widget::WidgetTree;
+use crate::Identity;
+
+//
+// Focus navigation: keyboard focus moves through a window's widgets in tab order with Tab and
+// Shift+Tab, or to the nearest widget on screen in a direction with the arrow keys, D-pad or
+// left stick. Enter, Space or the south face button activate the focused widget. Focus never
+// leaves the window it is in, each window is its own focus scope.
+//
+
+/// How far a stick is pushed before it moves focus, it must come back inside to move again
+const STICK_THRESHOLD: f32 = 0.5;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FocusDirection {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Navigation {
+    Next,
+    Previous,
+    Direction(FocusDirection),
+    Activate,
+    /// Leave the window, e.g. Escape or the east face button
+    Cancel,
+}
+
+/// Turns raw keys, buttons and axes into navigation
+#[derive(Default)]
+pub struct NavigationInput {
+    shift: bool,
+    /// Stick (or D-pad axis) direction last pushed past the threshold, per axis pair
+    stick: Option<FocusDirection>,
+}
+
+impl NavigationInput {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn navigation(&mut self, event: &InputEvent) -> Option<Navigation> {
+        match event {
+            InputEvent::KeyDown(_, code) => {
+                let key = Keycode::from_i32(*code as i32)?;
+                match key {
+                    Keycode::LShift | Keycode::RShift => {
+                        self.shift = true;
+                        None
+                    }
+                    Keycode::Tab if self.shift => Some(Navigation::Previous),
+                    Keycode::Tab => Some(Navigation::Next),
+                    Keycode::Up => Some(Navigation::Direction(FocusDirection::Up)),
+                    Keycode::Down => Some(Navigation::Direction(FocusDirection::Down)),
+                    Keycode::Left => Some(Navigation::Direction(FocusDirection::Left)),
+                    Keycode::Right => Some(Navigation::Direction(FocusDirection::Right)),
+                    Keycode::Return | Keycode::KpEnter | Keycode::Space => {
+                        Some(Navigation::Activate)
+                    }
+                    Keycode::Escape => Some(Navigation::Cancel),
+                    _ => None,
+                }
+            }
+            InputEvent::KeyUp(_, code) => {
+                if let Some(Keycode::LShift) | Some(Keycode::RShift) =
+                    Keycode::from_i32(*code as i32)
+                {
+                    self.shift = false;
+                }
+                None
+            }
+            InputEvent::LostFocus(_) => {
+                self.shift = false;
+                None
+            }
+            InputEvent::JoyButtonDown(_, _, button)
+            | InputEvent::JoyButtonRepeated(_, _, button) => match button {
+                JoyButton::DPadUp => Some(Navigation::Direction(FocusDirection::Up)),
+                JoyButton::DPadDown => Some(Navigation::Direction(FocusDirection::Down)),
+                JoyButton::DPadLeft => Some(Navigation::Direction(FocusDirection::Left)),
+                JoyButton::DPadRight => Some(Navigation::Direction(FocusDirection::Right)),
+                JoyButton::South => Some(Navigation::Activate),
+                JoyButton::East => Some(Navigation::Cancel),
+                _ => None,
+            },
+            // positive y is up, as gilrs reports it
+            InputEvent::JoyAxisChanged(_, _, axis, value) => {
+                let direction = match axis {
+                    JoyAxis::LeftStickX | JoyAxis::DPadX => {
+                        axis_direction(*value, FocusDirection::Right, FocusDirection::Left)
+                    }
+                    JoyAxis::LeftStickY | JoyAxis::DPadY => {
+                        axis_direction(*value, FocusDirection::Up, FocusDirection::Down)
+                    }
+                    _ => return None,
+                };
+                let horizontal = matches!(axis, JoyAxis::LeftStickX | JoyAxis::DPadX);
+                match direction {
+                    Some(d) if self.stick == Some(d) => None,
+                    Some(d) => {
+                        self.stick = Some(d);
+                        Some(Navigation::Direction(d))
+                    }
+                    // back inside on this axis
+                    None => {
+                        let on_axis = matches!(
+                            self.stick,
+                            Some(FocusDirection::Left) | Some(FocusDirection::Right)
+                        ) == horizontal;
+                        if on_axis {
+                            self.stick = None;
+                        }
+                        None
+                    }
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+fn axis_direction(
+    value: f32,
+    positive: FocusDirection,
+    negative: FocusDirection,
+) -> Option<FocusDirection> {
+    if value > STICK_THRESHOLD {
+        Some(positive)
+    } else if value < -STICK_THRESHOLD {
+        Some(negative)
+    } else {
+        None
+    }
+}
+
+/// Widgets that can take focus, ordered by tab index, then as they are drawn
+pub fn tab_order(tree: &WidgetTree) -> Vec<Identity> {
+    let mut order = tree
+        .walk()
+        .into_iter()
+        .filter(|id| tree.is_shown(*id))
+        .filter_map(|id| tree.get(id))
+        .filter(|w| w.is_focusable() && w.tab_index >= 0)
+        .map(|w| (w.tab_index, w.view.id))
+        .collect::<Vec<_>>();
+    // stable, so equal indices keep the tree's order
+    order.sort_by_key(|(index, _)| *index);
+    order.into_iter().map(|(_, id)| id).collect()
+}
+
+/// The next (or previous) widget in tab order, wrapping around
+pub fn step(tree: &WidgetTree, from: Option<Identity>, backwards: bool) -> Option<Identity> {
+    let order = tab_order(tree);
+    if order.is_empty() {
+        return None;
+    }
+    let last = order.len() - 1;
+    let i = match from.and_then(|f| order.iter().position(|id| *id == f)) {
+        Some(i) if backwards => i.checked_sub(1).unwrap_or(last),
+        Some(i) if i == last => 0,
+        Some(i) => i + 1,
+        None if backwards => last,
+        None => 0,
+    };
+    Some(order[i])
+}
+
+/// The closest focusable widget in a direction from another, favouring those in line with it
+pub fn nearest(tree: &WidgetTree, from: Identity, direction: FocusDirection) -> Option<Identity> {
+    let (x, y) = center(&tree.get(from)?.view.bounds);
+    tab_order(tree)
+        .into_iter()
+        .filter(|id| *id != from)
+        .filter_map(|id| {
+            let (cx, cy) = center(&tree.get(id)?.view.bounds);
+            let (dx, dy) = (cx - x, cy - y);
+            let (along, across) = match direction {
+                FocusDirection::Up => (-dy, dx),
+                FocusDirection::Down => (dy, dx),
+                FocusDirection::Left => (-dx, dy),
+                FocusDirection::Right => (dx, dy),
+            };
+            if along > 0 {
+                Some((along + 2 * across.abs(), id))
+            } else {
+                None
+            }
+        })
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, id)| id)
+}
+
+fn center(rect: &ScreenRect) -> (i64, i64) {
+    (
+        rect.x as i64 + rect.w as i64 / 2,
+        rect.y as i64 + rect.h as i64 / 2,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui::widget::Widget;
+
+    /// Two rows of two buttons
+    fn grid() -> (WidgetTree, Vec<Identity>) {
+        let mut tree = WidgetTree::new(ScreenRect::new(0, 0, 200, 100));
+        let root = tree.root();
+        let ids = [(0, 0), (100, 0), (0, 50), (100, 50)]
+            .iter()
+            .enumerate()
+            .map(|(i, (x, y))| {
+                let button = Widget::button(&format!("b{}", i), "")
+                    .with_bounds(ScreenRect::new(*x, *y, 80, 30));
+                tree.add(root, button).unwrap()
+            })
+            .collect();
+        (tree, ids)
+    }
+
+    #[test]
+    fn tab_order_wraps_and_skips() {
+        let (mut tree, ids) = grid();
+        tree.get_mut(ids[1]).unwrap().enabled = false;
+        tree.get_mut(ids[0]).unwrap().tab_index = 1;
+        assert_eq!(tab_order(&tree), vec![ids[2], ids[3], ids[0]]);
+        assert_eq!(step(&tree, Some(ids[0]), false), Some(ids[2]));
+        assert_eq!(step(&tree, Some(ids[2]), true), Some(ids[0]));
+        assert_eq!(step(&tree, None, false), Some(ids[2]));
+    }
+
+    #[test]
+    fn directional_navigation() {
+        let (tree, ids) = grid();
+        assert_eq!(nearest(&tree, ids[0], FocusDirection::Right), Some(ids[1]));
+        assert_eq!(nearest(&tree, ids[0], FocusDirection::Down), Some(ids[2]));
+        assert_eq!(nearest(&tree, ids[3], FocusDirection::Up), Some(ids[1]));
+        assert_eq!(nearest(&tree, ids[0], FocusDirection::Left), None);
+    }
+
+    #[test]
+    fn sticks_move_once_per_push() {
+        let mut input = NavigationInput::new();
+        let axis = |v| InputEvent::JoyAxisChanged(0, 1, JoyAxis::LeftStickY, v);
+        assert_eq!(
+            input.navigation(&axis(0.8)),
+            Some(Navigation::Direction(FocusDirection::Up))
+        );
+        assert_eq!(input.navigation(&axis(0.9)), None);
+        assert_eq!(input.navigation(&axis(0.1)), None);
+        assert_eq!(
+            input.navigation(&axis(-0.7)),
+            Some(Navigation::Direction(FocusDirection::Down))
+        );
+
+        let shift = Keycode::LShift as i32 as u32;
+        let tab = Keycode::Tab as i32 as u32;
+        input.navigation(&InputEvent::KeyDown(0, shift));
+        assert_eq!(
+            input.navigation(&InputEvent::KeyDown(0, tab)),
+            Some(Navigation::Previous)
+        );
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+
+use crate::ui::draw::DrawList;
+use crate::ui::text::{typeset, GlyphAtlas, GlyphBitmap, GlyphSource};
+
+//
+// Fonts: TrueType and OpenType fonts, loaded by name and rasterized into one shared glyph atlas.
+//
+
+/// Glyphs for every font share one texture this wide and high
+const ATLAS_SIZE: u32 = 1024;
+
+pub struct Font {
+    font: fontdue::Font,
+}
+
+impl Font {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Font, Box<dyn Error>> {
+        let font = fontdue::Font::from_bytes(bytes, fontdue::FontSettings::default())?;
+        Ok(Font { font })
+    }
+
+    /// A .ttf or .otf file
+    pub fn load(path: &str) -> Result<Font, Box<dyn Error>> {
+        let bytes = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+        Font::from_bytes(&bytes)
+    }
+}
+
+impl GlyphSource for Font {
+    fn advance(&self, c: char, size: f32) -> f32 {
+        self.font.metrics(c, size).advance_width
+    }
+
+    fn kern(&self, left: char, right: char, size: f32) -> f32 {
+        self.font.horizontal_kern(left, right, size).unwrap_or(0.0)
+    }
+
+    fn line_metrics(&self, size: f32) -> (f32, f32) {
+        match self.font.horizontal_line_metrics(size) {
+            Some(metrics) => (metrics.ascent, metrics.new_line_size),
+            None => (size * 0.8, size * 1.2),
+        }
+    }
+
+    fn rasterize(&self, c: char, size: f32) -> GlyphBitmap {
+        let (metrics, coverage) = self.font.rasterize(c, size);
+        GlyphBitmap {
+            xmin: metrics.xmin,
+            ymin: metrics.ymin,
+            width: metrics.width,
+            height: metrics.height,
+            coverage,
+        }
+    }
+}
+
+pub struct Fonts {
+    fonts: HashMap<String, Font>,
+    /// Text is drawn with the first font loaded, unless another is made the default
+    default: Option<String>,
+    atlas: GlyphAtlas,
+}
+
+impl Default for Fonts {
+    fn default() -> Self {
+        Fonts {
+            fonts: HashMap::new(),
+            default: None,
+            atlas: GlyphAtlas::new(ATLAS_SIZE),
+        }
+    }
+}
+
+impl Fonts {
+    pub fn add(&mut self, name: &str, font: Font) {
+        if self.default.is_none() {
+            self.default = Some(name.to_string());
+        }
+        // glyphs are cached by font name, the old ones would be drawn otherwise
+        if self.fonts.insert(name.to_string(), font).is_some() {
+            self.atlas.clear();
+        }
+    }
+
+    /// Text is drawn with the font from now on, if it's loaded
+    pub fn set_default(&mut self, name: &str) {
+        if self.fonts.contains_key(name) {
+            self.default = Some(name.to_string());
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Font> {
+        self.fonts.get(name)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fonts.is_empty()
+    }
+
+    /// Turns the list's text runs into glyph quads, with the default font
+    pub fn typeset(&mut self, list: &mut DrawList) {
+        let name = match &self.default {
+            Some(name) => name,
+            None => return,
+        };
+        if let Some(font) = self.fonts.get(name) {
+            typeset(name, font, &mut self.atlas, list);
+        }
+    }
+}
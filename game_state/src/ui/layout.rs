@@ -0,0 +1,335 @@
+use crate::input::screen::ScreenRect;
+
+//
+// Layout: each widget describes how it wants to be placed (its Layout) and containers arrange
+// their children freely by anchor, in horizontal or vertical stacks, or in a grid. Stacks share
+// out leftover space by `grow` and take back missing space by `shrink`, like flexbox.
+//
+// Sizes, padding, margins and spacing are in logical pixels and multiplied by the window's scale,
+// so layouts keep their size on HiDPI displays. Bounds are in physical (drawable) pixels.
+//
+
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Insets {
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+    pub left: i32,
+}
+
+impl Insets {
+    pub fn new(top: i32, right: i32, bottom: i32, left: i32) -> Self {
+        Insets {
+            top,
+            right,
+            bottom,
+            left,
+        }
+    }
+
+    pub fn all(v: i32) -> Self {
+        Self::new(v, v, v, v)
+    }
+
+    pub fn scaled(&self, scale: f32) -> Self {
+        let s = |v: i32| (v as f32 * scale).round() as i32;
+        Self::new(s(self.top), s(self.right), s(self.bottom), s(self.left))
+    }
+
+    pub fn shrink(&self, rect: &ScreenRect) -> ScreenRect {
+        ScreenRect::new(
+            rect.x + self.left,
+            rect.y + self.top,
+            (rect.w - self.left - self.right).max(0),
+            (rect.h - self.top - self.bottom).max(0),
+        )
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Align {
+    Start,
+    Center,
+    End,
+    Fill,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Anchor {
+    pub horizontal: Align,
+    pub vertical: Align,
+}
+
+impl Anchor {
+    pub const TOP_LEFT: Anchor = Anchor::new(Align::Start, Align::Start);
+    pub const TOP: Anchor = Anchor::new(Align::Center, Align::Start);
+    pub const TOP_RIGHT: Anchor = Anchor::new(Align::End, Align::Start);
+    pub const LEFT: Anchor = Anchor::new(Align::Start, Align::Center);
+    pub const CENTER: Anchor = Anchor::new(Align::Center, Align::Center);
+    pub const RIGHT: Anchor = Anchor::new(Align::End, Align::Center);
+    pub const BOTTOM_LEFT: Anchor = Anchor::new(Align::Start, Align::End);
+    pub const BOTTOM: Anchor = Anchor::new(Align::Center, Align::End);
+    pub const BOTTOM_RIGHT: Anchor = Anchor::new(Align::End, Align::End);
+    pub const FILL: Anchor = Anchor::new(Align::Fill, Align::Fill);
+
+    pub const fn new(horizontal: Align, vertical: Align) -> Self {
+        Anchor {
+            horizontal,
+            vertical,
+        }
+    }
+}
+
+/// How a widget arranges its children
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Arrangement {
+    /// Each child is placed by its own anchor
+    Free,
+    HStack {
+        spacing: i32,
+    },
+    VStack {
+        spacing: i32,
+    },
+    /// Children fill the cells left to right, then top to bottom
+    Grid {
+        columns: usize,
+        spacing: i32,
+    },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Layout {
+    pub arrangement: Arrangement,
+    /// Preferred width and height, 0 to take all the space available
+    pub size: (i32, i32),
+    pub padding: Insets,
+    pub margin: Insets,
+    /// Placement within the parent, or across a stack
+    pub anchor: Anchor,
+    /// Share of a stack's leftover space
+    pub grow: f32,
+    /// Share of a stack's missing space, relative to the preferred size
+    pub shrink: f32,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Layout {
+            arrangement: Arrangement::Free,
+            size: (0, 0),
+            padding: Insets::default(),
+            margin: Insets::default(),
+            anchor: Anchor::TOP_LEFT,
+            grow: 0.0,
+            shrink: 1.0,
+        }
+    }
+}
+
+impl Layout {
+    pub fn sized(w: i32, h: i32) -> Self {
+        Layout {
+            size: (w, h),
+            ..Default::default()
+        }
+    }
+}
+
+/// Position and length along one axis
+fn align(start: i32, available: i32, size: i32, align: Align) -> (i32, i32) {
+    if size <= 0 {
+        return (start, available);
+    }
+    match align {
+        Align::Start => (start, size),
+        Align::Center => (start + (available - size) / 2, size),
+        Align::End => (start + available - size, size),
+        Align::Fill => (start, available),
+    }
+}
+
+fn scaled(v: i32, scale: f32) -> i32 {
+    (v as f32 * scale).round() as i32
+}
+
+/// Bounds of a child placed by its anchor
+pub fn place_free(content: &ScreenRect, layout: &Layout, scale: f32) -> ScreenRect {
+    let outer = layout.margin.scaled(scale).shrink(content);
+    let (x, w) = align(
+        outer.x,
+        outer.w,
+        scaled(layout.size.0, scale),
+        layout.anchor.horizontal,
+    );
+    let (y, h) = align(
+        outer.y,
+        outer.h,
+        scaled(layout.size.1, scale),
+        layout.anchor.vertical,
+    );
+    ScreenRect::new(x, y, w, h)
+}
+
+/// Bounds of children placed one after another along x (horizontal) or y
+pub fn place_stack(
+    content: &ScreenRect,
+    layouts: &[Layout],
+    horizontal: bool,
+    spacing: i32,
+    scale: f32,
+) -> Vec<ScreenRect> {
+    if layouts.is_empty() {
+        return Vec::new();
+    }
+    let spacing = scaled(spacing, scale);
+    let available = if horizontal { content.w } else { content.h };
+    let main = |l: &Layout| {
+        let m = l.margin.scaled(scale);
+        let (size, margin) = if horizontal {
+            (l.size.0, m.left + m.right)
+        } else {
+            (l.size.1, m.top + m.bottom)
+        };
+        (scaled(size, scale) as f32, margin as f32)
+    };
+
+    let sizes = layouts.iter().map(main).collect::<Vec<_>>();
+    let used = sizes.iter().map(|(s, m)| s + m).sum::<f32>()
+        + (spacing * (layouts.len() as i32 - 1)) as f32;
+    let free = available as f32 - used;
+
+    let weights = layouts
+        .iter()
+        .zip(sizes.iter())
+        .map(|(l, (size, _))| if free > 0.0 { l.grow } else { l.shrink * size })
+        .collect::<Vec<_>>();
+    let total_weight = weights.iter().sum::<f32>();
+
+    let mut cursor = if horizontal { content.x } else { content.y } as f32;
+    let mut rects = Vec::new();
+    for ((layout, (size, margin)), weight) in layouts.iter().zip(sizes).zip(weights) {
+        let extra = if total_weight > 0.0 {
+            free * weight / total_weight
+        } else {
+            0.0
+        };
+        let length = (size + extra).max(0.0);
+        let slot = if horizontal {
+            ScreenRect::new(
+                cursor.round() as i32,
+                content.y,
+                (length + margin).round() as i32,
+                content.h,
+            )
+        } else {
+            ScreenRect::new(
+                content.x,
+                cursor.round() as i32,
+                content.w,
+                (length + margin).round() as i32,
+            )
+        };
+        cursor += length + margin + spacing as f32;
+
+        // the main axis is decided by the stack, the cross axis by the child's anchor
+        let mut cross = *layout;
+        if horizontal {
+            cross.size.0 = 0;
+        } else {
+            cross.size.1 = 0;
+        }
+        rects.push(place_free(&slot, &cross, scale));
+    }
+    rects
+}
+
+/// Bounds of children placed in equally wide columns, each row as tall as its tallest child
+pub fn place_grid(
+    content: &ScreenRect,
+    layouts: &[Layout],
+    columns: usize,
+    spacing: i32,
+    scale: f32,
+) -> Vec<ScreenRect> {
+    let columns = columns.max(1);
+    let spacing = scaled(spacing, scale);
+    let cell_w = (content.w - spacing * (columns as i32 - 1)).max(0) / columns as i32;
+
+    let mut rects = Vec::new();
+    let mut y = content.y;
+    for row in layouts.chunks(columns) {
+        let row_h = row
+            .iter()
+            .map(|l| {
+                let m = l.margin.scaled(scale);
+                match l.size.1 {
+                    0 => cell_w,
+                    h => scaled(h, scale) + m.top + m.bottom,
+                }
+            })
+            .max()
+            .unwrap_or(0);
+        for (i, layout) in row.iter().enumerate() {
+            let x = content.x + i as i32 * (cell_w + spacing);
+            let cell = ScreenRect::new(x, y, cell_w, row_h);
+            rects.push(place_free(&cell, layout, scale));
+        }
+        y += row_h + spacing;
+    }
+    rects
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(r: &ScreenRect) -> (i32, i32, i32, i32) {
+        (r.x, r.y, r.w, r.h)
+    }
+
+    #[test]
+    fn anchors_and_margins() {
+        let content = ScreenRect::new(0, 0, 100, 50);
+        let mut layout = Layout::sized(20, 10);
+        layout.anchor = Anchor::BOTTOM_RIGHT;
+        layout.margin = Insets::all(5);
+        assert_eq!(rect(&place_free(&content, &layout, 1.0)), (75, 35, 20, 10));
+
+        layout.anchor = Anchor::CENTER;
+        assert_eq!(rect(&place_free(&content, &layout, 2.0)), (30, 15, 40, 20));
+    }
+
+    #[test]
+    fn stacks_grow_and_shrink() {
+        let content = ScreenRect::new(0, 0, 100, 20);
+        let mut grow = Layout::sized(20, 0);
+        grow.grow = 1.0;
+        let fixed = Layout::sized(30, 10);
+        let rects = place_stack(&content, &[fixed, grow], true, 10, 1.0);
+        assert_eq!(rect(&rects[0]), (0, 0, 30, 10));
+        assert_eq!(rect(&rects[1]), (40, 0, 60, 20));
+
+        // 60 + 60 in 100: each gives up 10, in proportion to its size
+        let rects = place_stack(
+            &content,
+            &[Layout::sized(60, 0), Layout::sized(60, 0)],
+            true,
+            0,
+            1.0,
+        );
+        assert_eq!(rect(&rects[1]), (50, 0, 50, 20));
+
+        let rects = place_stack(&content, &[Layout::sized(0, 8)], false, 0, 1.0);
+        assert_eq!(rect(&rects[0]), (0, 0, 100, 8));
+    }
+
+    #[test]
+    fn grid_rows() {
+        let content = ScreenRect::new(0, 0, 110, 100);
+        let cells = vec![Layout::sized(0, 20); 3];
+        let rects = place_grid(&content, &cells, 2, 10, 1.0);
+        assert_eq!(rect(&rects[1]), (60, 0, 50, 20));
+        assert_eq!(rect(&rects[2]), (0, 30, 50, 20));
+    }
+}
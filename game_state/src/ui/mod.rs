@@ -1,2 +1,11 @@
+pub mod console;
+pub mod document;
+pub mod draw;
+pub mod events;
+pub mod focus;
+pub mod font;
+pub mod layout;
+pub mod scene;
+pub mod text;
 pub mod view;
-pub mod events;
\ No newline at end of file
+pub mod widget;
@@ -0,0 +1,686 @@
+use std::collections::HashMap;
+
+use sdl2::keyboard::Keycode;
+
+use crate::input::events::{InputEvent, MouseButton};
+use crate::input::screen::ScreenPoint;
+use crate::input::InputConsumer;
+use crate::ui::draw::{draw_tree, DrawList, Highlight, Theme};
+use crate::ui::events::UIEvent;
+use crate::ui::focus::{nearest, step, FocusDirection, Navigation, NavigationInput};
+use crate::ui::view::UIWindow;
+use crate::ui::widget::{Widget, WidgetKind};
+use crate::Identifyable;
+use crate::Identity;
+
+//
+// UI scene: every UIWindow, back to front, and the routing of input events to their widgets.
+// Pointer events hit the topmost window with an interactive widget under the pointer, and bubble
+// from the deepest widget hit up to the nearest one that handles them, e.g. a click on a button's
+// label goes to the button. The widget pressed captures the pointer until it is released, so drags
+// keep going to it. Keyboard and text go to the focused widget, and move focus, see `ui::focus`.
+//
+// Events the UI acts on are consumed, gameplay doesn't see them, see `InputConsumer`. Releases
+// are never consumed, or gameplay would think keys are still held.
+//
+
+/// Logical pixels the pointer moves before a press becomes a drag
+const DRAG_THRESHOLD: i32 = 4;
+/// Logical pixels scrolled per wheel step
+const SCROLL_STEP: f32 = 40.0;
+
+struct Capture {
+    window: Identity,
+    widget: Identity,
+    origin: ScreenPoint,
+    dragging: bool,
+}
+
+#[derive(Default)]
+pub struct UIScene {
+    /// The last window is drawn last, and hit first
+    windows: Vec<UIWindow>,
+    /// Last pointer position per host, in logical pixels
+    pointers: HashMap<Identity, ScreenPoint>,
+    /// (window, widget) pairs
+    hovered: Option<(Identity, Identity)>,
+    focused: Option<(Identity, Identity)>,
+    /// The widget last focused in each window, focus returns to it
+    scopes: HashMap<Identity, Identity>,
+    navigation: NavigationInput,
+    captured: Option<Capture>,
+    /// HiDPI scale per host, read from the host window as it's resized
+    host_scales: HashMap<Identity, f32>,
+    pub theme: Theme,
+}
+
+impl UIScene {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// The host's scale for the next layout, set before its Resized event is routed
+    pub fn set_host_scale(&mut self, host: Identity, scale: f32) {
+        self.host_scales.insert(host, scale);
+    }
+
+    /// Drops the handlers the mod added to any window, e.g. as it unloads
+    pub fn remove_handlers(&mut self, owner: &str) {
+        for window in self.windows.iter_mut() {
+            window.remove_handlers(owner);
+        }
+    }
+
+    /// Adds the window in front of the others
+    pub fn add_window(&mut self, window: UIWindow) -> Identity {
+        let id = window.identify();
+        self.windows.push(window);
+        id
+    }
+
+    pub fn remove_window(&mut self, id: Identity) -> Option<UIWindow> {
+        let i = self.windows.iter().position(|w| w.identify() == id)?;
+        if matches!(self.hovered, Some((w, _)) if w == id) {
+            self.hovered = None;
+        }
+        if matches!(self.focused, Some((w, _)) if w == id) {
+            self.focused = None;
+        }
+        self.scopes.remove(&id);
+        if matches!(&self.captured, Some(c) if c.window == id) {
+            self.captured = None;
+        }
+        Some(self.windows.remove(i))
+    }
+
+    pub fn window(&self, id: Identity) -> Option<&UIWindow> {
+        self.windows.iter().find(|w| w.identify() == id)
+    }
+
+    pub fn window_mut(&mut self, id: Identity) -> Option<&mut UIWindow> {
+        self.windows.iter_mut().find(|w| w.identify() == id)
+    }
+
+    pub fn find_window(&self, tag: &str) -> Option<Identity> {
+        self.windows
+            .iter()
+            .find(|w| w.tag == tag)
+            .map(|w| w.identify())
+    }
+
+    /// Back to front
+    pub fn windows(&self) -> impl Iterator<Item = &UIWindow> {
+        self.windows.iter()
+    }
+
+    pub fn windows_mut(&mut self) -> impl Iterator<Item = &mut UIWindow> {
+        self.windows.iter_mut()
+    }
+
+    /// Moves the window in front of the others
+    pub fn raise(&mut self, id: Identity) {
+        if let Some(i) = self.windows.iter().position(|w| w.identify() == id) {
+            let window = self.windows.remove(i);
+            self.windows.push(window);
+        }
+    }
+
+    /// The (window, widget) under the pointer
+    pub fn hovered(&self) -> Option<(Identity, Identity)> {
+        self.hovered
+    }
+
+    /// The (window, widget) receiving keyboard and text input
+    pub fn focused(&self) -> Option<(Identity, Identity)> {
+        self.focused
+    }
+
+    /// Moves keyboard focus, sending LeftFocus and GainedFocus
+    pub fn set_focus(&mut self, focus: Option<(Identity, Identity)>) {
+        if self.focused == focus {
+            return;
+        }
+        if let Some((window, widget)) = self.focused.take() {
+            self.dispatch(window, UIEvent::LeftFocus(widget));
+        }
+        if let Some((window, widget)) = focus {
+            self.dispatch(window, UIEvent::GainedFocus(widget));
+            self.scopes.insert(window, widget);
+        }
+        self.focused = focus;
+    }
+
+    /// Raises the window and focuses the widget it last had focused, or its first in tab order,
+    /// e.g. when opening a menu so it can be used without a mouse
+    pub fn focus_window(&mut self, window: Identity) {
+        self.raise(window);
+        let tree = match self.window(window) {
+            Some(w) => &w.widgets,
+            None => return,
+        };
+        let remembered = self.scopes.get(&window).cloned().filter(|id| {
+            tree.is_shown(*id) && matches!(tree.get(*id), Some(w) if w.is_focusable())
+        });
+        let widget = remembered.or_else(|| step(tree, None, false));
+        self.set_focus(widget.map(|widget| (window, widget)));
+    }
+
+    /// Whether the pointer is held on a widget
+    pub fn is_capturing(&self) -> bool {
+        self.captured.is_some()
+    }
+
+    /// Every event dispatched to any window since the last call, see `WidgetTree::take_events`
+    pub fn take_events(&mut self) -> Vec<UIEvent> {
+        self.windows
+            .iter_mut()
+            .flat_map(|w| w.widgets.take_events())
+            .collect()
+    }
+
+    /// Lays out again every window that changed
+    pub fn update_layout(&mut self) {
+        for window in self.windows.iter_mut() {
+            window.update_layout();
+        }
+    }
+
+    /// A draw list for each host window, with the UI windows in it back to front
+    pub fn draw_lists(&self) -> Vec<DrawList> {
+        let mut lists: Vec<DrawList> = Vec::new();
+        for window in self.windows.iter() {
+            let host = match window.host {
+                Some(host) => host,
+                None => continue,
+            };
+            let id = window.identify();
+            let in_window = |pair: Option<(Identity, Identity)>| match pair {
+                Some((w, widget)) if w == id => Some(widget),
+                _ => None,
+            };
+            let highlight = Highlight {
+                hovered: in_window(self.hovered),
+                pressed: in_window(self.captured.as_ref().map(|c| (c.window, c.widget))),
+                focused: in_window(self.focused),
+            };
+            let i = match lists.iter().position(|l| l.window == host) {
+                Some(i) => i,
+                None => {
+                    lists.push(DrawList::new(host));
+                    lists.len() - 1
+                }
+            };
+            let theme = window.theme.as_ref().unwrap_or(&self.theme);
+            draw_tree(&mut lists[i], &window.widgets, theme, &highlight);
+        }
+        lists
+    }
+
+    pub fn dispatch(&mut self, window: Identity, event: UIEvent) {
+        if let Some(window) = self.window_mut(window) {
+            window.widgets.dispatch(event);
+        }
+    }
+
+    /// Routes an input event to the widgets it concerns, returning whether it was consumed
+    pub fn route(&mut self, event: &InputEvent) -> bool {
+        if let Some(navigation) = self.navigation.navigation(event) {
+            if self.navigate(navigation) {
+                return true;
+            }
+        }
+        match event {
+            InputEvent::MouseMove(host, point) => {
+                self.pointers.insert(*host, point.clone());
+                self.pointer_moved(*host);
+                false
+            }
+            InputEvent::MouseMotion(host, ..) => {
+                self.is_capturing() || self.pointer_target(*host, Widget::is_interactive).is_some()
+            }
+            InputEvent::MouseDown(host, button) => {
+                let target = self.pointer_target(*host, Widget::is_interactive);
+                if *button == MouseButton::Left {
+                    self.press(*host, target);
+                }
+                target.is_some()
+            }
+            InputEvent::MouseUp(host, MouseButton::Left) => {
+                self.release(*host);
+                false
+            }
+            InputEvent::MouseWheel(host, delta) => {
+                let is_panel = |w: &Widget| matches!(w.kind, WidgetKind::ScrollPanel { .. });
+                match self.pointer_target(*host, is_panel) {
+                    Some((window, panel)) => {
+                        let scale = self.scale(window);
+                        let dy = -(delta.delta_y as f32 * SCROLL_STEP * scale).round() as i32;
+                        self.dispatch(window, UIEvent::Scrolled(panel, dy));
+                        true
+                    }
+                    None => false,
+                }
+            }
+            InputEvent::MouseLeft(host) => {
+                self.pointers.remove(host);
+                self.pointer_moved(*host);
+                false
+            }
+            InputEvent::LostFocus(_) => {
+                // the release won't arrive
+                if let Some(capture) = self.captured.take() {
+                    let point = self.physical_point(capture.window, &capture.origin);
+                    self.dispatch(capture.window, UIEvent::Released(capture.widget, point));
+                }
+                false
+            }
+            InputEvent::Resized(host, ..) => {
+                let host_scale = self.host_scales.get(host).copied();
+                for window in self.windows.iter_mut() {
+                    // a host without a scale set isn't a window State knows about
+                    let scale = host_scale.unwrap_or_else(|| window.widgets.scale());
+                    window.handle_input(event, scale);
+                }
+                false
+            }
+            InputEvent::TextInput(_, text) => match self.focused_text_field() {
+                Some((window, field)) => {
+                    let changed = self
+                        .window_mut(window)
+                        .and_then(|w| w.widgets.get_mut(field))
+                        .and_then(|w| w.insert_text(text));
+                    if let Some(changed) = changed {
+                        self.dispatch(window, UIEvent::TextChanged(field, changed));
+                    }
+                    true
+                }
+                None => false,
+            },
+            InputEvent::TextEditing(..) => self.focused_text_field().is_some(),
+            InputEvent::KeyDown(_, code) => match self.focused_text_field() {
+                Some((window, field)) => {
+                    self.edit_text(window, field, *code);
+                    true
+                }
+                None => false,
+            },
+            _ => false,
+        }
+    }
+
+    fn press(&mut self, host: Identity, target: Option<(Identity, Identity)>) {
+        let (window, widget) = match target {
+            Some(target) => target,
+            None => {
+                self.set_focus(None);
+                return;
+            }
+        };
+        self.raise(window);
+        let origin = self
+            .pointers
+            .get(&host)
+            .cloned()
+            .unwrap_or_else(|| ScreenPoint::new(0, 0));
+        let point = self.physical_point(window, &origin);
+        self.captured = Some(Capture {
+            window,
+            widget,
+            origin,
+            dragging: false,
+        });
+        self.dispatch(window, UIEvent::Pressed(widget, point));
+        let focusable = matches!(
+            self.window(window).and_then(|w| w.widgets.get(widget)),
+            Some(w) if w.is_focusable()
+        );
+        if focusable {
+            self.set_focus(Some((window, widget)));
+        }
+    }
+
+    fn release(&mut self, host: Identity) {
+        let capture = match self.captured.take() {
+            Some(capture) => capture,
+            None => return,
+        };
+        let pointer = self
+            .pointers
+            .get(&host)
+            .cloned()
+            .unwrap_or_else(|| capture.origin.clone());
+        let point = self.physical_point(capture.window, &pointer);
+        self.dispatch(capture.window, UIEvent::Released(capture.widget, point));
+        // a click is a press and release on the same widget
+        if self.pointer_target(host, Widget::is_interactive)
+            == Some((capture.window, capture.widget))
+        {
+            self.dispatch(capture.window, UIEvent::Clicked(capture.widget));
+        }
+    }
+
+    fn pointer_moved(&mut self, host: Identity) {
+        let hovered = self.pointer_target(host, Widget::is_interactive);
+        if hovered != self.hovered {
+            if let Some((window, widget)) = self.hovered {
+                self.dispatch(window, UIEvent::HoverLeft(widget));
+            }
+            if let Some((window, widget)) = hovered {
+                self.dispatch(window, UIEvent::HoverEntered(widget));
+            }
+            self.hovered = hovered;
+        }
+
+        let pointer = match self.pointers.get(&host) {
+            Some(pointer) => pointer.clone(),
+            None => return,
+        };
+        if let Some(capture) = self.captured.as_mut() {
+            let (dx, dy) = (pointer.x - capture.origin.x, pointer.y - capture.origin.y);
+            capture.dragging |= dx.abs() > DRAG_THRESHOLD || dy.abs() > DRAG_THRESHOLD;
+            if capture.dragging {
+                let (window, widget) = (capture.window, capture.widget);
+                let point = self.physical_point(window, &pointer);
+                self.dispatch(window, UIEvent::Dragged(widget, point));
+            }
+        }
+    }
+
+    /// The topmost widget under the host's pointer accepted by `handles`, bubbling up from the
+    /// deepest widget hit. Windows with nothing to handle it let it through to those below.
+    fn pointer_target<F>(&self, host: Identity, handles: F) -> Option<(Identity, Identity)>
+    where
+        F: Fn(&Widget) -> bool,
+    {
+        let pointer = self.pointers.get(&host)?;
+        self.windows
+            .iter()
+            .rev()
+            .filter(|w| w.host == Some(host))
+            .find_map(|window| {
+                let point = self.physical_point(window.identify(), pointer);
+                let hit = window.widgets.hit_test(&point)?;
+                let target = window.widgets.bubble(hit, &handles)?;
+                Some((window.identify(), target))
+            })
+    }
+
+    /// Moves focus within the focused window, or acts on the focused widget. Returns whether the
+    /// navigation was used, text fields keep the keys they edit with.
+    fn navigate(&mut self, navigation: Navigation) -> bool {
+        let (window, widget) = match self.focused {
+            Some(focused) => focused,
+            None => return false,
+        };
+        let tree = match self.window(window) {
+            Some(w) => &w.widgets,
+            None => return false,
+        };
+        let kind = match tree.get(widget) {
+            Some(w) if tree.is_shown(widget) => w.kind.clone(),
+            // hidden or removed since it was focused
+            _ => {
+                self.set_focus(None);
+                return false;
+            }
+        };
+        let is_text = matches!(kind, WidgetKind::TextField { .. });
+
+        let target = match navigation {
+            Navigation::Next => step(tree, Some(widget), false),
+            Navigation::Previous => step(tree, Some(widget), true),
+            Navigation::Direction(FocusDirection::Left)
+            | Navigation::Direction(FocusDirection::Right)
+                if is_text =>
+            {
+                return false;
+            }
+            Navigation::Direction(direction) => {
+                if let WidgetKind::Slider { min, max, value } = kind {
+                    let step = (max - min) / 10.0;
+                    let change = match direction {
+                        FocusDirection::Left => Some(-step),
+                        FocusDirection::Right => Some(step),
+                        _ => None,
+                    };
+                    if let Some(change) = change {
+                        self.dispatch(window, UIEvent::ValueChanged(widget, value + change));
+                        return true;
+                    }
+                }
+                // stays put at the edge of the window
+                nearest(tree, widget, direction).or(Some(widget))
+            }
+            Navigation::Activate if is_text => return false,
+            Navigation::Activate => {
+                self.dispatch(window, UIEvent::Clicked(widget));
+                return true;
+            }
+            // focus leaves the window either way, but only editing text is cancelled here, e.g.
+            // Escape on a menu button still reaches the pause action
+            Navigation::Cancel => {
+                self.set_focus(None);
+                return is_text;
+            }
+        };
+        self.set_focus(target.map(|target| (window, target)));
+        true
+    }
+
+    /// The focused widget, if it's a text field
+    pub fn focused_text_field(&self) -> Option<(Identity, Identity)> {
+        let (window, widget) = self.focused?;
+        match self.window(window)?.widgets.get(widget)?.kind {
+            WidgetKind::TextField { .. } => Some((window, widget)),
+            _ => None,
+        }
+    }
+
+    fn edit_text(&mut self, window: Identity, field: Identity, code: u32) {
+        let key = Keycode::from_i32(code as i32);
+        let widget = match self
+            .window_mut(window)
+            .and_then(|w| w.widgets.get_mut(field))
+        {
+            Some(widget) => widget,
+            None => return,
+        };
+        let event = match (key, &widget.kind) {
+            (Some(Keycode::Backspace), _) => {
+                widget.backspace().map(|t| UIEvent::TextChanged(field, t))
+            }
+            (Some(Keycode::Return), WidgetKind::TextField { text, .. })
+            | (Some(Keycode::KpEnter), WidgetKind::TextField { text, .. }) => {
+                Some(UIEvent::Submitted(field, text.clone()))
+            }
+            _ => None,
+        };
+        if let Some(event) = event {
+            self.dispatch(window, event);
+        }
+    }
+
+    fn scale(&self, window: Identity) -> f32 {
+        self.window(window)
+            .map(|w| w.widgets.scale())
+            .unwrap_or(1.0)
+    }
+
+    /// Pointer positions are in logical pixels, widget bounds in physical pixels
+    fn physical_point(&self, window: Identity, point: &ScreenPoint) -> ScreenPoint {
+        let scale = self.scale(window);
+        ScreenPoint::new(
+            (point.x as f32 * scale).round() as i32,
+            (point.y as f32 * scale).round() as i32,
+        )
+    }
+}
+
+impl InputConsumer for UIScene {
+    fn consume(&mut self, event: &InputEvent) -> bool {
+        self.route(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::events::JoyButton;
+    use crate::input::screen::{DeltaVector, ScreenRect};
+
+    const HOST: Identity = 1000;
+
+    /// A window at (0, 0, 200, 200) in HOST, holding a button at (10, 10, 100, 30) with a label
+    fn scene() -> (UIScene, Identity, Identity, Identity) {
+        let mut window = UIWindow::new("menu".to_string(), ScreenRect::new(0, 0, 200, 200));
+        window.host = Some(HOST);
+        let root = window.widgets.root();
+        let button = Widget::button("ok", "").with_bounds(ScreenRect::new(10, 10, 100, 30));
+        let button = window.widgets.add(root, button).unwrap();
+        let label = Widget::label("ok_text", "OK").with_bounds(ScreenRect::new(20, 15, 50, 20));
+        window.widgets.add(button, label).unwrap();
+
+        let mut scene = UIScene::new();
+        let id = scene.add_window(window);
+        (scene, id, button, root)
+    }
+
+    fn move_to(scene: &mut UIScene, x: i32, y: i32) -> bool {
+        scene.route(&InputEvent::MouseMove(HOST, ScreenPoint::new(x, y)))
+    }
+
+    #[test]
+    fn resized_hosts_are_laid_out_at_their_new_scale() {
+        let (mut scene, window, ..) = scene();
+        scene.route(&InputEvent::Resized(HOST, 400.0, 300.0));
+        let laid_out = |scene: &UIScene, scale| {
+            !scene
+                .window(window)
+                .unwrap()
+                .needs_host_layout(400.0, 300.0, scale)
+        };
+        assert!(laid_out(&scene, 1.0));
+
+        // e.g. moved to a HiDPI display
+        scene.set_host_scale(HOST, 2.0);
+        scene.route(&InputEvent::Resized(HOST, 400.0, 300.0));
+        assert!(laid_out(&scene, 2.0));
+        assert_eq!(scene.window(window).unwrap().widgets.scale(), 2.0);
+    }
+
+    #[test]
+    fn clicks_bubble_to_the_button() {
+        let (mut scene, _, button, _) = scene();
+        move_to(&mut scene, 30, 20);
+        assert!(scene.route(&InputEvent::MouseDown(HOST, MouseButton::Left)));
+        assert!(!scene.route(&InputEvent::MouseUp(HOST, MouseButton::Left)));
+
+        let events = scene.take_events();
+        assert_eq!(events[0], UIEvent::HoverEntered(button));
+        assert!(events.contains(&UIEvent::GainedFocus(button)));
+        assert_eq!(events.last(), Some(&UIEvent::Clicked(button)));
+        assert_eq!(scene.focused().map(|f| f.1), Some(button));
+
+        // nothing interactive there, gameplay gets the click and focus is lost
+        move_to(&mut scene, 150, 150);
+        assert!(!scene.route(&InputEvent::MouseDown(HOST, MouseButton::Left)));
+        assert!(!scene.route(&InputEvent::MouseMotion(HOST, 1.0, 0.0)));
+        let events = scene.take_events();
+        assert!(events.contains(&UIEvent::HoverLeft(button)));
+        assert!(events.contains(&UIEvent::LeftFocus(button)));
+    }
+
+    #[test]
+    fn pressed_widget_captures_the_drag() {
+        let (mut scene, window, _, root) = scene();
+        let slider =
+            Widget::slider("volume", 0.0, 1.0, 0.0).with_bounds(ScreenRect::new(0, 100, 100, 20));
+        let slider = scene
+            .window_mut(window)
+            .unwrap()
+            .widgets
+            .add(root, slider)
+            .unwrap();
+
+        move_to(&mut scene, 10, 110);
+        scene.route(&InputEvent::MouseDown(HOST, MouseButton::Left));
+        assert!(scene.route(&InputEvent::MouseMotion(HOST, 40.0, 60.0)));
+        // dragged out of the slider, and out of the window
+        move_to(&mut scene, 50, 300);
+        scene.route(&InputEvent::MouseUp(HOST, MouseButton::Left));
+
+        let events = scene.take_events();
+        assert!(events.contains(&UIEvent::Dragged(slider, ScreenPoint::new(50, 300))));
+        assert!(events.contains(&UIEvent::ValueChanged(slider, 0.5)));
+        assert!(events.contains(&UIEvent::Released(slider, ScreenPoint::new(50, 300))));
+        assert!(!events.contains(&UIEvent::Clicked(slider)));
+        assert!(!scene.is_capturing());
+    }
+
+    #[test]
+    fn focused_text_field_consumes_keys() {
+        let (mut scene, window, _, root) = scene();
+        let field = Widget::text_field("name", "").with_bounds(ScreenRect::new(0, 50, 100, 20));
+        let field = scene
+            .window_mut(window)
+            .unwrap()
+            .widgets
+            .add(root, field)
+            .unwrap();
+
+        let w = Keycode::W as i32 as u32;
+        assert!(!scene.route(&InputEvent::KeyDown(0, w)));
+        move_to(&mut scene, 5, 55);
+        scene.route(&InputEvent::MouseDown(HOST, MouseButton::Left));
+        scene.route(&InputEvent::MouseUp(HOST, MouseButton::Left));
+        scene.take_events();
+
+        assert!(scene.route(&InputEvent::TextInput(0, "wa".to_string())));
+        assert!(scene.route(&InputEvent::KeyDown(0, w)));
+        assert!(!scene.route(&InputEvent::KeyUp(0, w)));
+        scene.route(&InputEvent::KeyDown(0, Keycode::Backspace as i32 as u32));
+        scene.route(&InputEvent::KeyDown(0, Keycode::Return as i32 as u32));
+        let events = scene.take_events();
+        assert_eq!(events[0], UIEvent::TextChanged(field, "wa".to_string()));
+        assert_eq!(events[2], UIEvent::Submitted(field, "w".to_string()));
+
+        assert!(scene.route(&InputEvent::KeyDown(0, Keycode::Escape as i32 as u32)));
+        assert_eq!(scene.focused(), None);
+        assert!(!scene.route(&InputEvent::MouseWheel(HOST, DeltaVector::new(0, 1))));
+    }
+
+    #[test]
+    fn navigates_within_the_focused_window() {
+        let (mut scene, window, button, root) = scene();
+        let volume =
+            Widget::slider("volume", 0.0, 1.0, 0.5).with_bounds(ScreenRect::new(10, 60, 100, 20));
+        let volume = scene
+            .window_mut(window)
+            .unwrap()
+            .widgets
+            .add(root, volume)
+            .unwrap();
+        let key = |k: Keycode| InputEvent::KeyDown(0, k as i32 as u32);
+
+        // nothing focused, gameplay keeps its keys
+        assert!(!scene.route(&key(Keycode::Down)));
+        scene.focus_window(window);
+        assert_eq!(scene.focused(), Some((window, button)));
+
+        assert!(scene.route(&InputEvent::JoyButtonDown(0, 1, JoyButton::DPadDown)));
+        assert!(scene.route(&key(Keycode::Right)));
+        assert!(scene.route(&key(Keycode::Tab)));
+        assert!(scene.route(&key(Keycode::Return)));
+        let events = scene.take_events();
+        assert!(events.contains(&UIEvent::GainedFocus(volume)));
+        assert!(events.contains(&UIEvent::ValueChanged(volume, 0.6)));
+        assert_eq!(events.last(), Some(&UIEvent::Clicked(button)));
+
+        // leaving and coming back restores focus
+        scene.route(&InputEvent::KeyDown(0, Keycode::Tab as i32 as u32));
+        assert!(!scene.route(&InputEvent::JoyButtonDown(0, 1, JoyButton::East)));
+        assert_eq!(scene.focused(), None);
+        scene.focus_window(window);
+        assert_eq!(scene.focused(), Some((window, volume)));
+    }
+}
@@ -0,0 +1,645 @@
+use std::collections::HashMap;
+
+use crate::create_next_identity;
+use crate::input::screen::ScreenRect;
+use crate::ui::draw::{Color, DrawCommand, DrawList, UITexture};
+use crate::ui::layout::{Align, Anchor};
+
+//
+// Text: markup is parsed into colored spans, laid out into lines (wrapping at spaces, aligned,
+// kerned) and each glyph is rasterized once into an atlas texture. Typesetting a draw list turns
+// its text runs into textured quads from the atlas, so renderers only ever draw quads.
+//
+// Markup: `[color=#rrggbb]text[/color]` (or #rgb, #rrggbbaa), which nests, and `[[` for a
+// literal `[`.
+//
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextSpan {
+    pub text: String,
+    /// The run's own color when None
+    pub color: Option<Color>,
+}
+
+/// Coverage of one glyph, positioned relative to the pen on the baseline
+#[derive(Debug, Clone, Default)]
+pub struct GlyphBitmap {
+    pub xmin: i32,
+    /// Bottom of the bitmap, up from the baseline
+    pub ymin: i32,
+    pub width: usize,
+    pub height: usize,
+    /// 0 - 255, row by row from the top
+    pub coverage: Vec<u8>,
+}
+
+/// Measures and rasterizes glyphs, e.g. a `Font`. Sizes are heights in pixels.
+pub trait GlyphSource {
+    fn advance(&self, c: char, size: f32) -> f32;
+    fn kern(&self, left: char, right: char, size: f32) -> f32;
+    /// Ascent above the baseline, and the distance between baselines
+    fn line_metrics(&self, size: f32) -> (f32, f32);
+    fn rasterize(&self, c: char, size: f32) -> GlyphBitmap;
+}
+
+pub fn parse_color(hex: &str) -> Option<Color> {
+    let hex = hex.strip_prefix('#')?;
+    if !hex.is_ascii() {
+        return None;
+    }
+    // #rgb is short for #rrggbb
+    if hex.len() == 3 {
+        let long = hex.chars().flat_map(|c| vec![c, c]).collect::<String>();
+        return parse_color(&format!("#{}", long));
+    }
+    if !(hex.len() == 6 || hex.len() == 8) {
+        return None;
+    }
+    let channel = |i: usize| {
+        u8::from_str_radix(&hex[i..i + 2], 16)
+            .ok()
+            .map(|v| v as f32 / 255.0)
+    };
+    let alpha = if hex.len() == 8 { channel(6)? } else { 1.0 };
+    Some([channel(0)?, channel(2)?, channel(4)?, alpha])
+}
+
+/// Splits markup into spans of one color each. Tags that aren't understood are kept as text.
+pub fn parse_markup(markup: &str) -> Vec<TextSpan> {
+    let mut spans: Vec<TextSpan> = Vec::new();
+    let mut colors: Vec<Color> = Vec::new();
+    let mut text = String::new();
+    let mut rest = markup;
+
+    let flush = |text: &mut String, color: Option<Color>, spans: &mut Vec<TextSpan>| {
+        if !text.is_empty() {
+            spans.push(TextSpan {
+                text: std::mem::take(text),
+                color,
+            });
+        }
+    };
+
+    while let Some(open) = rest.find('[') {
+        text.push_str(&rest[..open]);
+        rest = &rest[open..];
+        if let Some(after) = rest.strip_prefix("[[") {
+            text.push('[');
+            rest = after;
+            continue;
+        }
+        let tag = rest.find(']').map(|close| (&rest[1..close], close));
+        match tag {
+            Some((tag, close)) if tag == "/color" && !colors.is_empty() => {
+                flush(&mut text, colors.last().cloned(), &mut spans);
+                colors.pop();
+                rest = &rest[close + 1..];
+            }
+            Some((tag, close)) if tag.starts_with("color=") => match parse_color(&tag[6..]) {
+                Some(color) => {
+                    flush(&mut text, colors.last().cloned(), &mut spans);
+                    colors.push(color);
+                    rest = &rest[close + 1..];
+                }
+                None => {
+                    text.push('[');
+                    rest = &rest[1..];
+                }
+            },
+            _ => {
+                text.push('[');
+                rest = &rest[1..];
+            }
+        }
+    }
+    text.push_str(rest);
+    flush(&mut text, colors.last().cloned(), &mut spans);
+    spans
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PositionedGlyph {
+    pub c: char,
+    /// Pen position on the baseline, relative to the top left of the text
+    pub x: f32,
+    pub baseline: f32,
+    pub color: Option<Color>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TextLayout {
+    pub glyphs: Vec<PositionedGlyph>,
+    pub width: f32,
+    pub height: f32,
+    pub lines: usize,
+}
+
+fn line_width<S: GlyphSource>(source: &S, line: &[(char, Option<Color>)], size: f32) -> f32 {
+    // trailing spaces don't count, they're where the line was wrapped
+    let end = line
+        .iter()
+        .rposition(|(c, _)| !c.is_whitespace())
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    let mut width = 0.0;
+    for (i, (c, _)) in line[..end].iter().enumerate() {
+        if i > 0 {
+            width += source.kern(line[i - 1].0, *c, size);
+        }
+        width += source.advance(*c, size);
+    }
+    width
+}
+
+/// Lays spans out in lines, wrapping at spaces (or anywhere in words too long for a line) when
+/// given a width. Lines are aligned within that width, or within the widest line.
+pub fn layout_text<S: GlyphSource>(
+    source: &S,
+    spans: &[TextSpan],
+    size: f32,
+    wrap_width: Option<f32>,
+    align: Align,
+) -> TextLayout {
+    let mut lines: Vec<Vec<(char, Option<Color>)>> = vec![Vec::new()];
+    for span in spans {
+        for c in span.text.chars() {
+            if c == '\n' {
+                lines.push(Vec::new());
+                continue;
+            }
+            let line = lines.last_mut().expect("there is always a line");
+            line.push((c, span.color));
+            let max = match wrap_width {
+                Some(max) if !c.is_whitespace() && line.len() > 1 => max,
+                _ => continue,
+            };
+            if line_width(source, line, size) > max {
+                let rest = match line.iter().rposition(|(c, _)| c.is_whitespace()) {
+                    Some(space) => line.split_off(space + 1),
+                    None => line.split_off(line.len() - 1),
+                };
+                lines.push(rest);
+            }
+        }
+    }
+
+    let (ascent, line_height) = source.line_metrics(size);
+    let widths = lines
+        .iter()
+        .map(|l| line_width(source, l, size))
+        .collect::<Vec<_>>();
+    let width = wrap_width.unwrap_or_else(|| widths.iter().cloned().fold(0.0, f32::max));
+
+    let mut glyphs = Vec::new();
+    for (i, (line, line_w)) in lines.iter().zip(widths.iter()).enumerate() {
+        let mut x = match align {
+            Align::Start | Align::Fill => 0.0,
+            Align::Center => (width - line_w) / 2.0,
+            Align::End => width - line_w,
+        };
+        let baseline = ascent + i as f32 * line_height;
+        for (j, (c, color)) in line.iter().enumerate() {
+            if j > 0 {
+                x += source.kern(line[j - 1].0, *c, size);
+            }
+            glyphs.push(PositionedGlyph {
+                c: *c,
+                x,
+                baseline,
+                color: *color,
+            });
+            x += source.advance(*c, size);
+        }
+    }
+    TextLayout {
+        glyphs,
+        width,
+        height: lines.len() as f32 * line_height,
+        lines: lines.len(),
+    }
+}
+
+/// Where a glyph is in the atlas, and how to place it
+#[derive(Debug, Clone, Copy)]
+pub struct AtlasGlyph {
+    pub uv: [f32; 4],
+    pub xmin: i32,
+    pub ymin: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+/// Glyphs rasterized into one texture, white with the coverage as alpha so quads are tinted
+/// by their color. Rows of glyphs are packed top to bottom, when full the atlas starts over.
+/// The texture keeps its id and pixels while glyphs are added, only its version and dirty rect
+/// move on, so renderers upload just the new glyphs.
+pub struct GlyphAtlas {
+    texture: UITexture,
+    size: u32,
+    /// font, glyph and pixel size
+    glyphs: HashMap<(String, char, u32), AtlasGlyph>,
+    cursor: (u32, u32),
+    row_height: u32,
+    /// Pixels drawn since the texture was last handed out
+    dirty: Option<ScreenRect>,
+    /// Bumped each time the atlas starts over
+    generation: u64,
+}
+
+impl GlyphAtlas {
+    pub fn new(size: u32) -> Self {
+        GlyphAtlas {
+            texture: UITexture::new(create_next_identity(), image::RgbaImage::new(size, size)),
+            size,
+            glyphs: HashMap::new(),
+            cursor: (0, 0),
+            row_height: 0,
+            dirty: None,
+            generation: 0,
+        }
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn len(&self) -> usize {
+        self.glyphs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.glyphs.is_empty()
+    }
+
+    /// Starts over with a new texture, lists already typeset keep drawing the old one
+    pub fn clear(&mut self) {
+        let image = image::RgbaImage::new(self.size, self.size);
+        self.texture = UITexture::new(create_next_identity(), image);
+        self.glyphs.clear();
+        self.cursor = (0, 0);
+        self.row_height = 0;
+        self.dirty = None;
+        self.generation += 1;
+    }
+
+    /// The atlas as a texture, a version on with the rect glyphs were drawn in since the last call
+    pub fn texture(&mut self) -> UITexture {
+        if let Some(dirty) = self.dirty.take() {
+            self.texture.version += 1;
+            self.texture.dirty = Some(dirty);
+        }
+        self.texture.clone()
+    }
+
+    /// The glyph, rasterizing it first if it isn't in the atlas
+    pub fn glyph<S: GlyphSource>(
+        &mut self,
+        font: &str,
+        source: &S,
+        c: char,
+        size: f32,
+    ) -> AtlasGlyph {
+        let key = (font.to_string(), c, size.round() as u32);
+        if let Some(glyph) = self.glyphs.get(&key) {
+            return *glyph;
+        }
+        let bitmap = source.rasterize(c, key.2 as f32);
+        let (w, h) = (bitmap.width as u32, bitmap.height as u32);
+        // a pixel of padding, so filtering doesn't bleed between glyphs
+        if self.cursor.0 + w + 1 > self.size {
+            self.cursor = (0, self.cursor.1 + self.row_height + 1);
+            self.row_height = 0;
+        }
+        if self.cursor.1 + h + 1 > self.size || w + 1 > self.size {
+            self.clear();
+        }
+        let (x, y) = self.cursor;
+        if w > 0 && h > 0 {
+            let mut image = self.texture.image.write().expect("atlas image poisoned");
+            for row in 0..h.min(self.size) {
+                for col in 0..w.min(self.size) {
+                    let alpha = bitmap.coverage[(row * w + col) as usize];
+                    image.put_pixel(x + col, y + row, image::Rgba([255, 255, 255, alpha]));
+                }
+            }
+            let drawn = ScreenRect::new(x as i32, y as i32, w as i32, h as i32);
+            self.dirty = Some(match self.dirty.take() {
+                Some(dirty) => dirty.union(&drawn),
+                None => drawn,
+            });
+        }
+        self.cursor.0 += w + 1;
+        self.row_height = self.row_height.max(h);
+
+        let s = self.size as f32;
+        let glyph = AtlasGlyph {
+            uv: [
+                x as f32 / s,
+                y as f32 / s,
+                (x + w) as f32 / s,
+                (y + h) as f32 / s,
+            ],
+            xmin: bitmap.xmin,
+            ymin: bitmap.ymin,
+            width: w as i32,
+            height: h as i32,
+        };
+        self.glyphs.insert(key, glyph);
+        glyph
+    }
+}
+
+/// Replaces the text runs in a draw list with glyph quads from the atlas
+pub fn typeset<S: GlyphSource>(
+    font: &str,
+    source: &S,
+    atlas: &mut GlyphAtlas,
+    list: &mut DrawList,
+) {
+    let generation = atlas.generation();
+    let commands = typeset_commands(font, source, atlas, &list.commands);
+    // the atlas started over part way, quads from before point at glyphs no longer there
+    list.commands = if atlas.generation() != generation {
+        typeset_commands(font, source, atlas, &list.commands)
+    } else {
+        commands
+    };
+}
+
+fn typeset_commands<S: GlyphSource>(
+    font: &str,
+    source: &S,
+    atlas: &mut GlyphAtlas,
+    commands: &[DrawCommand],
+) -> Vec<DrawCommand> {
+    // glyphs are all rasterized before the texture is taken, so every quad can share it
+    let mut placed = Vec::new();
+    for command in commands.iter() {
+        match command {
+            DrawCommand::Text {
+                bounds,
+                text,
+                size,
+                color,
+                anchor,
+                wrap,
+            } => {
+                let wrap_width = if *wrap && bounds.w > 0 {
+                    Some(bounds.w as f32)
+                } else {
+                    None
+                };
+                let spans = parse_markup(text);
+                let layout = layout_text(source, &spans, *size, wrap_width, anchor.horizontal);
+                let (x, y) = text_origin(bounds, &layout, anchor);
+                let mut glyphs = Vec::new();
+                for g in layout.glyphs.iter().filter(|g| !g.c.is_whitespace()) {
+                    let atlas_glyph = atlas.glyph(font, source, g.c, *size);
+                    let tint = g.color.unwrap_or(*color);
+                    glyphs.push((
+                        g.clone(),
+                        atlas_glyph,
+                        [tint[0], tint[1], tint[2], tint[3] * color[3]],
+                    ));
+                }
+                placed.push(Some((x, y, glyphs)));
+            }
+            _ => placed.push(None),
+        }
+    }
+
+    let texture = atlas.texture();
+    let mut typeset = Vec::new();
+    for (command, placed) in commands.iter().zip(placed) {
+        let (x, y, glyphs) = match placed {
+            Some(placed) => placed,
+            None => {
+                typeset.push(command.clone());
+                continue;
+            }
+        };
+        for (glyph, atlas_glyph, color) in glyphs {
+            if atlas_glyph.width == 0 || atlas_glyph.height == 0 {
+                continue;
+            }
+            let rect = ScreenRect::new(
+                (x + glyph.x).round() as i32 + atlas_glyph.xmin,
+                (y + glyph.baseline).round() as i32 - atlas_glyph.ymin - atlas_glyph.height,
+                atlas_glyph.width,
+                atlas_glyph.height,
+            );
+            typeset.push(DrawCommand::Image {
+                rect,
+                texture: texture.clone(),
+                uv: atlas_glyph.uv,
+                color,
+            });
+        }
+    }
+    typeset
+}
+
+/// Top left of laid out text placed in bounds by its anchor
+fn text_origin(bounds: &ScreenRect, layout: &TextLayout, anchor: &Anchor) -> (f32, f32) {
+    let place = |start: i32, available: i32, size: f32, align: Align| match align {
+        Align::Start | Align::Fill => start as f32,
+        Align::Center => start as f32 + (available as f32 - size) / 2.0,
+        Align::End => start as f32 + available as f32 - size,
+    };
+    (
+        place(bounds.x, bounds.w, layout.width, anchor.horizontal),
+        place(bounds.y, bounds.h, layout.height, anchor.vertical),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui::draw::{draw_tree, Highlight, Theme};
+    use crate::ui::layout::Arrangement;
+    use crate::ui::widget::{Widget, WidgetTree};
+    use std::sync::Arc;
+
+    /// Every glyph is half as wide as it is tall, "AV" kerns closer
+    struct Mono;
+
+    impl GlyphSource for Mono {
+        fn advance(&self, _c: char, size: f32) -> f32 {
+            size / 2.0
+        }
+        fn kern(&self, left: char, right: char, size: f32) -> f32 {
+            if left == 'A' && right == 'V' {
+                -size / 10.0
+            } else {
+                0.0
+            }
+        }
+        fn line_metrics(&self, size: f32) -> (f32, f32) {
+            (size * 0.8, size)
+        }
+        fn rasterize(&self, c: char, size: f32) -> GlyphBitmap {
+            let (width, height) = if c.is_whitespace() {
+                (0, 0)
+            } else {
+                ((size / 2.0) as usize, (size * 0.8) as usize)
+            };
+            GlyphBitmap {
+                xmin: 0,
+                ymin: 0,
+                width,
+                height,
+                coverage: vec![255; width * height],
+            }
+        }
+    }
+
+    #[test]
+    fn markup_colors_nest() {
+        let spans =
+            parse_markup("a [color=#ff0000]red [color=#00ff0080]green[/color][/color] [[b]");
+        let red = Some([1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(spans.len(), 4);
+        assert_eq!(
+            spans[0],
+            TextSpan {
+                text: "a ".to_string(),
+                color: None
+            }
+        );
+        assert_eq!(
+            spans[1],
+            TextSpan {
+                text: "red ".to_string(),
+                color: red
+            }
+        );
+        assert_eq!(spans[2].color.map(|c| c[3]), Some(128.0 / 255.0));
+        assert_eq!(spans[3].text, " [b]");
+
+        let unknown = parse_markup("[b]bold[/color]");
+        assert_eq!(unknown[0].text, "[b]bold[/color]");
+    }
+
+    #[test]
+    fn wraps_aligns_and_kerns() {
+        let spans = parse_markup("AV is ok\nnext");
+        // 10 pixels per glyph, 50 per line at most
+        let layout = layout_text(&Mono, &spans, 20.0, Some(50.0), Align::End);
+        assert_eq!(layout.lines, 3);
+        let v = &layout.glyphs[1];
+        assert_eq!((v.c, v.x), ('V', 2.0 + 10.0 - 2.0));
+        // "AV is" is 48 wide, its trailing space after the wrap doesn't count
+        assert_eq!(layout.glyphs[0].x, 2.0);
+        let o = layout.glyphs.iter().find(|g| g.c == 'o').unwrap();
+        assert_eq!((o.x, o.baseline), (30.0, 36.0));
+
+        let long = parse_markup("abcdefgh");
+        assert_eq!(
+            layout_text(&Mono, &long, 20.0, Some(50.0), Align::Start).lines,
+            2
+        );
+    }
+
+    #[test]
+    fn typesets_text_into_glyph_quads() {
+        let mut atlas = GlyphAtlas::new(64);
+        let mut list = DrawList::new(1);
+        list.quad(ScreenRect::new(0, 0, 100, 40), [1.0; 4]);
+        list.text(
+            ScreenRect::new(0, 0, 100, 40),
+            "[color=#00ff00]ab[/color] a",
+            20.0,
+            [1.0, 1.0, 1.0, 0.5],
+            Anchor::CENTER,
+            false,
+        );
+        typeset("mono", &Mono, &mut atlas, &mut list);
+
+        assert_eq!(list.commands.len(), 4);
+        assert_eq!(atlas.len(), 2);
+        match &list.commands[1] {
+            DrawCommand::Image { rect, color, .. } => {
+                // 40 wide and 20 high, centered
+                assert_eq!((rect.x, rect.y, rect.w, rect.h), (30, 10, 10, 16));
+                assert_eq!(*color, [0.0, 1.0, 0.0, 0.5]);
+            }
+            other => panic!("expected a glyph, got {:?}", other),
+        }
+
+        // too many glyphs for the atlas, it starts over and typesets again
+        let generation = atlas.generation();
+        let mut list = DrawList::new(1);
+        let text = "abcdefghijklmnopqrstuvwxyz0123456789";
+        list.text(
+            ScreenRect::new(0, 0, 400, 40),
+            text,
+            20.0,
+            [1.0; 4],
+            Anchor::LEFT,
+            false,
+        );
+        typeset("mono", &Mono, &mut atlas, &mut list);
+        assert!(atlas.generation() > generation);
+        assert!(matches!(&list.commands[35], DrawCommand::Image { .. }));
+    }
+
+    #[test]
+    fn atlas_texture_is_shared_and_marks_new_glyphs() {
+        let mut atlas = GlyphAtlas::new(64);
+        atlas.glyph("mono", &Mono, 'a', 20.0);
+        let first = atlas.texture();
+        assert_eq!(
+            (first.version, first.dirty),
+            (1, Some(ScreenRect::new(0, 0, 10, 16)))
+        );
+
+        // nothing new, nothing to upload again
+        assert_eq!(atlas.texture().version, 1);
+
+        atlas.glyph("mono", &Mono, 'b', 20.0);
+        atlas.glyph("mono", &Mono, 'c', 20.0);
+        let second = atlas.texture();
+        assert_eq!(second.id, first.id);
+        assert!(Arc::ptr_eq(&second.image, &first.image));
+        assert_eq!(second.version, 2);
+        assert_eq!(second.dirty, Some(ScreenRect::new(11, 0, 21, 16)));
+
+        atlas.clear();
+        assert_ne!(atlas.texture().id, first.id);
+    }
+
+    #[test]
+    fn labels_wrap_at_their_width() {
+        let mut tree = WidgetTree::new(ScreenRect::new(0, 0, 200, 100));
+        let root = tree.root();
+        tree.get_mut(root).unwrap().layout.arrangement = Arrangement::HStack { spacing: 0 };
+        tree.add(root, Widget::label("about", "one two three four five"))
+            .unwrap();
+        tree.layout_in(&ScreenRect::new(0, 0, 200, 100), 0.25);
+
+        let mut list = DrawList::new(1);
+        let theme = Theme {
+            font_size: 80.0,
+            ..Default::default()
+        };
+        draw_tree(&mut list, &tree, &theme, &Highlight::default());
+        // 240 logical pixels at a quarter scale, 6 glyphs of 10 pixels
+        assert!(matches!(
+            &list.commands[0],
+            DrawCommand::Text { bounds, wrap: true, .. } if bounds.w == 60
+        ));
+
+        let mut atlas = GlyphAtlas::new(256);
+        typeset("mono", &Mono, &mut atlas, &mut list);
+        let mut rows = list
+            .commands
+            .iter()
+            .filter_map(|c| match c {
+                DrawCommand::Image { rect, .. } => Some(rect.y),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        rows.dedup();
+        assert_eq!(rows.len(), 5);
+    }
+}
@@ -1,9 +1,11 @@
-use std::collections::HashMap;
-
 use crate::create_next_identity;
-use crate::event::{ArcEventHandler, CopyingEventProducer, EventProducer};
+use crate::event::{EventBus, Subscription};
+use crate::input::events::InputEvent;
 use crate::input::screen::{ScreenPoint, ScreenRect};
+use crate::ui::draw::Theme;
 use crate::ui::events::UIEvent;
+use crate::ui::layout::{Insets, Layout};
+use crate::ui::widget::WidgetTree;
 use crate::Identifyable;
 use crate::Identity;
 
@@ -21,7 +23,7 @@ impl UIView {
         }
     }
 
-    fn hit_test(&self, point: ScreenPoint) -> bool {
+    pub fn hit_test(&self, point: ScreenPoint) -> bool {
         self.bounds.intersects(&point)
     }
 
@@ -46,45 +48,122 @@ pub struct UIWindow {
     pub tag: String,
     //TODO: should make the view private
     pub view: UIView,
-    event_producer: CopyingEventProducer<UIEvent>,
-    active_handlers: HashMap<String, ArcEventHandler<UIEvent>>,
+    /// The window's contents, the root container covers the window's bounds
+    pub widgets: WidgetTree,
+    /// The SDL window this UI is shown in, it is laid out again when that is resized
+    pub host: Option<Identity>,
+    /// Overrides the scene's theme, e.g. for a window from a UI document
+    pub theme: Option<Theme>,
+    /// Host width, height and scale of the last layout in it
+    laid_out_in: Option<(f32, f32, f32)>,
+    clicks: EventBus<UIEvent>,
+    /// The window owns its handlers, by the mod which subscribed them, dropping them unsubscribes
+    click_handlers: Vec<(String, Subscription<UIEvent>)>,
 }
 
 impl UIWindow {
     pub fn new(tag: String, bounds: ScreenRect) -> Self {
+        let mut widgets = WidgetTree::new(bounds.clone());
+        // keeps its position in the host, until the root's layout is changed, e.g. to an anchor
+        if let Some(root) = widgets.get_mut(widgets.root()) {
+            root.layout = Layout {
+                margin: Insets::new(bounds.y, 0, 0, bounds.x),
+                ..Layout::sized(bounds.w, bounds.h)
+            };
+        }
         UIWindow {
             id: create_next_identity(),
             tag: tag.clone(),
+            widgets,
+            host: None,
+            theme: None,
+            laid_out_in: None,
             view: UIView::new(tag, bounds),
-            event_producer: CopyingEventProducer::<UIEvent>::new(),
-            active_handlers: HashMap::new(),
+            clicks: EventBus::new(),
+            click_handlers: Vec::new(),
+        }
+    }
+
+    /// Lays the window out in its host's drawable area, given the host's size in logical
+    /// pixels and its HiDPI scale
+    pub fn layout_in_host(&mut self, width: f32, height: f32, scale: f32) {
+        let area = ScreenRect::new(
+            0,
+            0,
+            (width * scale).round() as i32,
+            (height * scale).round() as i32,
+        );
+        self.widgets.layout_in(&area, scale);
+        self.laid_out_in = Some((width, height, scale));
+        self.sync_bounds();
+    }
+
+    /// Whether the window hasn't been laid out in a host of this size and scale yet
+    pub fn needs_host_layout(&self, width: f32, height: f32, scale: f32) -> bool {
+        self.laid_out_in != Some((width, height, scale))
+    }
+
+    /// Lays out again, if any widget changed since the last layout
+    pub fn update_layout(&mut self) {
+        if self.widgets.needs_layout() {
+            self.widgets.update_layout();
+            self.sync_bounds();
+        }
+    }
+
+    pub fn handle_input(&mut self, event: &InputEvent, scale: f32) {
+        if let InputEvent::Resized(id, width, height) = event {
+            if self.host == Some(*id) {
+                self.layout_in_host(*width, *height, scale);
+            }
+        }
+    }
+
+    fn sync_bounds(&mut self) {
+        if let Some(root) = self.widgets.get(self.widgets.root()) {
+            self.view.bounds = root.view.bounds.clone();
         }
     }
     pub fn perform_click(&mut self, p: ScreenPoint) {
         if self.view.hit_test(p) {
             let event = UIEvent::Clicked(self.id);
-            self.event_producer.publish(event);
+            self.clicks.publish(event);
         }
     }
 
-    pub fn subscribe_click<F: Fn(UIEvent) -> ()>(&mut self, func: F)
+    /// Hands the clicks queued since the last call to the click handlers
+    pub fn deliver_clicks(&mut self) {
+        self.clicks.dispatch();
+    }
+
+    /// Drops the click and widget handlers of the mod, their code goes away as it unloads
+    pub fn remove_handlers(&mut self, owner: &str) {
+        self.click_handlers.retain(|(o, _)| o != owner);
+        self.widgets.remove_handlers(owner);
+    }
+
+    /// Handlers with a higher priority are called first. The owner is the name of the mod
+    /// subscribing. Fails if the id is in use.
+    pub fn subscribe_click<F>(
+        &mut self,
+        owner: &str,
+        id: &str,
+        priority: i32,
+        func: F,
+    ) -> Result<(), String>
     where
-        F: 'static,
+        F: Fn(&UIEvent) + Send + Sync + 'static,
     {
-        let event_handler = CopyingEventProducer::<UIEvent>::create_handler(func);
-        let tag = format!("{}_onclick", self.tag);
-        // Window will own it's handlers:
-        self.active_handlers
-            .entry(tag.to_string())
-            .or_insert_with(|| event_handler.clone());
-        self.event_producer
-            .add_handler(tag.to_string(), &event_handler);
+        let subscription = self.clicks.subscribe(id, priority, func)?;
+        self.click_handlers.push((owner.to_string(), subscription));
+        Ok(())
     }
 }
 
-pub struct UIButton {
-    pub text: UIView,
-    pub background: UIView,
+impl Identifyable for UIWindow {
+    fn identify(&self) -> Identity {
+        self.id
+    }
 }
 
 #[cfg(test)]
@@ -111,15 +190,61 @@ mod tests {
         let flag = Arc::new(Mutex::new(0_u32));
         let cflag = flag.clone();
 
-        window.subscribe_click(move |_ /*event:UIEvent*/| {
-            *cflag.lock().unwrap() = 1;
-        });
+        window
+            .subscribe_click("gui", "flag", 0, move |_ /*event:UIEvent*/| {
+                *cflag.lock().unwrap() = 1;
+            })
+            .unwrap();
+        assert!(window.subscribe_click("gui", "flag", 0, |_| {}).is_err());
 
         assert!(*flag.lock().unwrap() == 0);
 
         let point = ScreenPoint::new(15, 15);
-        window.perform_click(point);
+        window.perform_click(point.clone());
+        // clicks are delivered once a frame
+        assert!(*flag.lock().unwrap() == 0);
+        window.deliver_clicks();
+        assert!(*flag.lock().unwrap() == 1);
 
+        // another mod unloading keeps them
+        *flag.lock().unwrap() = 0;
+        window.remove_handlers("dummy");
+        window.perform_click(point.clone());
+        window.deliver_clicks();
         assert!(*flag.lock().unwrap() == 1);
+
+        // as on unloading the mod that subscribed
+        *flag.lock().unwrap() = 0;
+        window.remove_handlers("gui");
+        window.perform_click(point);
+        window.deliver_clicks();
+        assert!(*flag.lock().unwrap() == 0);
+    }
+
+    #[test]
+    fn test_window_follows_host_resize() {
+        use crate::ui::layout::{Anchor, Arrangement};
+        use crate::ui::widget::Widget;
+
+        let mut window = UIWindow::new("hud".to_string(), ScreenRect::new(0, 0, 100, 40));
+        window.host = Some(7);
+        let root = window.widgets.root();
+        if let Some(root) = window.widgets.get_mut(root) {
+            root.layout.anchor = Anchor::TOP_RIGHT;
+            root.layout.margin = Insets::all(10);
+            root.layout.arrangement = Arrangement::HStack { spacing: 0 };
+        }
+        let fps = window
+            .widgets
+            .add(root, Widget::label("fps", "0 fps"))
+            .unwrap();
+
+        assert!(window.needs_host_layout(400.0, 300.0, 2.0));
+        window.handle_input(&InputEvent::Resized(7, 400.0, 300.0), 2.0);
+        assert!(!window.needs_host_layout(400.0, 300.0, 2.0));
+        let bounds = &window.view.bounds;
+        assert_eq!((bounds.x, bounds.y, bounds.w, bounds.h), (580, 20, 200, 80));
+        let label = &window.widgets.get(fps).unwrap().view.bounds;
+        assert_eq!((label.x, label.y, label.w, label.h), (580, 20, 200, 48));
     }
 }
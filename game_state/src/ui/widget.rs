@@ -0,0 +1,689 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::input::screen::{ScreenPoint, ScreenRect};
+use crate::ui::draw::Color;
+use crate::ui::events::UIEvent;
+use crate::ui::layout::{
+    place_free, place_grid, place_stack, Align, Anchor, Arrangement, Layout,
+};
+use crate::ui::view::UIView;
+use crate::Identifyable;
+use crate::Identity;
+
+//
+// Retained widget tree: every widget wraps a UIView (identity, tag, bounds) and knows its parent
+// and children. Widgets react to the UIEvents dispatched to them, e.g. a checkbox toggles when
+// clicked, and every dispatched or resulting event is queued for mods to take.
+//
+// Mods may also handle events as they're dispatched, with handlers on a widget. An event bubbles
+// from its target up through the ancestors' handlers until one returns true, marking it handled.
+// Handlers are kept by the name of the mod which added them, which drops them as it unloads, so
+// no closure from a mod outlives a hot reload.
+//
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WidgetKind {
+    Container,
+    Label {
+        text: String,
+    },
+    Button {
+        text: String,
+    },
+    Checkbox {
+        text: String,
+        checked: bool,
+    },
+    Slider {
+        min: f32,
+        max: f32,
+        value: f32,
+    },
+    TextField {
+        text: String,
+        placeholder: String,
+        /// in characters
+        cursor: usize,
+    },
+    /// Scrolls its children vertically by `offset`
+    ScrollPanel {
+        offset: i32,
+        content_height: i32,
+    },
+}
+
+pub struct Widget {
+    pub view: UIView,
+    pub kind: WidgetKind,
+    pub layout: Layout,
+    pub visible: bool,
+    pub enabled: bool,
+    /// Drawn behind the widget, e.g. a panel, nothing by default
+    pub background: Option<Color>,
+    /// Position in tab order, lower first, ties in tree order. Negative to skip the widget.
+    pub tab_index: i32,
+    parent: Option<Identity>,
+    children: Vec<Identity>,
+}
+
+impl Widget {
+    pub fn new(tag: &str, kind: WidgetKind) -> Self {
+        // preferred sizes until text can be measured, containers take what they are given
+        let layout = match kind {
+            WidgetKind::Container | WidgetKind::ScrollPanel { .. } => Layout {
+                grow: 1.0,
+                ..Default::default()
+            },
+            // as wide as there is room for, text wraps at the width
+            WidgetKind::Label { .. } => Layout {
+                anchor: Anchor::new(Align::Fill, Align::Start),
+                ..Layout::sized(240, 24)
+            },
+            WidgetKind::Button { .. } => Layout::sized(120, 32),
+            WidgetKind::Checkbox { .. } | WidgetKind::Slider { .. } => Layout::sized(160, 24),
+            WidgetKind::TextField { .. } => Layout::sized(200, 28),
+        };
+        Widget {
+            view: UIView::new(tag.to_string(), ScreenRect::new(0, 0, 0, 0)),
+            kind,
+            layout,
+            visible: true,
+            enabled: true,
+            background: None,
+            tab_index: 0,
+            parent: None,
+            children: Vec::new(),
+        }
+    }
+
+    pub fn container(tag: &str) -> Self {
+        Self::new(tag, WidgetKind::Container)
+    }
+
+    pub fn label(tag: &str, text: &str) -> Self {
+        let text = text.to_string();
+        Self::new(tag, WidgetKind::Label { text })
+    }
+
+    pub fn button(tag: &str, text: &str) -> Self {
+        let text = text.to_string();
+        Self::new(tag, WidgetKind::Button { text })
+    }
+
+    pub fn checkbox(tag: &str, text: &str, checked: bool) -> Self {
+        let text = text.to_string();
+        Self::new(tag, WidgetKind::Checkbox { text, checked })
+    }
+
+    pub fn slider(tag: &str, min: f32, max: f32, value: f32) -> Self {
+        let value = value.clamp(min, max);
+        Self::new(tag, WidgetKind::Slider { min, max, value })
+    }
+
+    pub fn text_field(tag: &str, placeholder: &str) -> Self {
+        Self::new(
+            tag,
+            WidgetKind::TextField {
+                text: String::new(),
+                placeholder: placeholder.to_string(),
+                cursor: 0,
+            },
+        )
+    }
+
+    pub fn scroll_panel(tag: &str) -> Self {
+        Self::new(
+            tag,
+            WidgetKind::ScrollPanel {
+                offset: 0,
+                content_height: 0,
+            },
+        )
+    }
+
+    pub fn with_bounds(mut self, bounds: ScreenRect) -> Self {
+        self.view.bounds = bounds;
+        self
+    }
+
+    pub fn with_layout(mut self, layout: Layout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    pub fn tag(&self) -> &str {
+        self.view.tag()
+    }
+
+    pub fn parent(&self) -> Option<Identity> {
+        self.parent
+    }
+
+    pub fn children(&self) -> &[Identity] {
+        &self.children
+    }
+
+    /// Whether the widget reacts to input, rather than only displaying something
+    pub fn is_interactive(&self) -> bool {
+        !matches!(self.kind, WidgetKind::Container | WidgetKind::Label { .. })
+    }
+
+    /// Whether the widget can take keyboard focus
+    pub fn is_focusable(&self) -> bool {
+        self.enabled
+            && self.is_interactive()
+            && !matches!(self.kind, WidgetKind::ScrollPanel { .. })
+    }
+
+    /// The widget's own reaction to an event targeting it, and the event that results, if any
+    /// Values the widget can't take, e.g. beyond a slider's range, are corrected in the event.
+    fn react(&mut self, event: &mut UIEvent) -> Option<UIEvent> {
+        let id = self.identify();
+        match (&mut self.kind, event) {
+            (WidgetKind::Checkbox { checked, .. }, UIEvent::Clicked(_)) => {
+                *checked = !*checked;
+                Some(UIEvent::Toggled(id, *checked))
+            }
+            (WidgetKind::Slider { min, max, value }, UIEvent::Pressed(_, p))
+            | (WidgetKind::Slider { min, max, value }, UIEvent::Dragged(_, p)) => {
+                let bounds = &self.view.bounds;
+                let t = (p.x - bounds.x) as f32 / bounds.w.max(1) as f32;
+                *value = *min + t.clamp(0.0, 1.0) * (*max - *min);
+                Some(UIEvent::ValueChanged(id, *value))
+            }
+            (WidgetKind::Slider { min, max, value }, UIEvent::ValueChanged(_, v)) => {
+                *v = v.clamp(*min, *max);
+                *value = *v;
+                None
+            }
+            (WidgetKind::TextField { text, cursor, .. }, UIEvent::TextChanged(_, t)) => {
+                *text = t.clone();
+                *cursor = (*cursor).min(text.chars().count());
+                None
+            }
+            (
+                WidgetKind::ScrollPanel {
+                    offset,
+                    content_height,
+                },
+                UIEvent::Scrolled(_, dy),
+            ) => {
+                let max = (*content_height - self.view.bounds.h).max(0);
+                *offset = (*offset + *dy).clamp(0, max);
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Inserts text at the cursor of a text field, returning the changed text
+    pub fn insert_text(&mut self, input: &str) -> Option<String> {
+        match &mut self.kind {
+            WidgetKind::TextField { text, cursor, .. } => {
+                let at = byte_offset(text, *cursor);
+                text.insert_str(at, input);
+                *cursor += input.chars().count();
+                Some(text.clone())
+            }
+            _ => None,
+        }
+    }
+
+    /// Removes the character before the cursor of a text field, returning the changed text
+    pub fn backspace(&mut self) -> Option<String> {
+        match &mut self.kind {
+            WidgetKind::TextField { text, cursor, .. } if *cursor > 0 => {
+                *cursor -= 1;
+                let at = byte_offset(text, *cursor);
+                text.remove(at);
+                Some(text.clone())
+            }
+            _ => None,
+        }
+    }
+}
+
+fn byte_offset(text: &str, chars: usize) -> usize {
+    text.char_indices()
+        .nth(chars)
+        .map(|(i, _)| i)
+        .unwrap_or_else(|| text.len())
+}
+
+impl Identifyable for Widget {
+    fn identify(&self) -> Identity {
+        self.view.identify()
+    }
+}
+
+/// Called with each event bubbling through its widget, returns true once the event is handled
+pub type UIHandler = Box<dyn FnMut(&UIEvent) -> bool + Send>;
+
+struct OwnedHandler {
+    owner: String,
+    handler: UIHandler,
+}
+
+/// Widgets of one UIWindow, under a root container
+pub struct WidgetTree {
+    root: Identity,
+    widgets: HashMap<Identity, Widget>,
+    events: VecDeque<UIEvent>,
+    /// By widget, called in the order they were added
+    handlers: HashMap<Identity, Vec<OwnedHandler>>,
+    /// the layout is out of date
+    dirty: bool,
+    scale: f32,
+}
+
+impl WidgetTree {
+    pub fn new(bounds: ScreenRect) -> Self {
+        let root = Widget::container("root").with_bounds(bounds);
+        let id = root.identify();
+        let mut widgets = HashMap::new();
+        widgets.insert(id, root);
+        WidgetTree {
+            root: id,
+            widgets,
+            events: VecDeque::new(),
+            handlers: HashMap::new(),
+            dirty: true,
+            scale: 1.0,
+        }
+    }
+
+    pub fn root(&self) -> Identity {
+        self.root
+    }
+
+    pub fn get(&self, id: Identity) -> Option<&Widget> {
+        self.widgets.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: Identity) -> Option<&mut Widget> {
+        self.widgets.get_mut(&id)
+    }
+
+    pub fn find(&self, tag: &str) -> Option<Identity> {
+        self.walk()
+            .into_iter()
+            .find(|id| self.widgets[id].tag() == tag)
+    }
+
+    pub fn len(&self) -> usize {
+        self.widgets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.widgets.is_empty()
+    }
+
+    pub fn add(&mut self, parent: Identity, mut widget: Widget) -> Result<Identity, String> {
+        let id = widget.identify();
+        let parent_widget = self
+            .widgets
+            .get_mut(&parent)
+            .ok_or_else(|| format!("no widget {} to add {} to", parent, widget.tag()))?;
+        parent_widget.children.push(id);
+        widget.parent = Some(parent);
+        self.widgets.insert(id, widget);
+        self.dirty = true;
+        Ok(id)
+    }
+
+    /// Removes the widget and all of its children
+    pub fn remove(&mut self, id: Identity) -> Result<(), String> {
+        if id == self.root {
+            return Err("cannot remove the root widget".to_string());
+        }
+        let widget = self
+            .widgets
+            .get(&id)
+            .ok_or_else(|| format!("no widget {}", id))?;
+        if let Some(parent) = widget.parent.and_then(|p| self.widgets.get_mut(&p)) {
+            parent.children.retain(|c| *c != id);
+        }
+        for removed in self.walk_from(id) {
+            self.widgets.remove(&removed);
+            self.handlers.remove(&removed);
+        }
+        self.dirty = true;
+        Ok(())
+    }
+
+    pub fn reparent(&mut self, id: Identity, parent: Identity) -> Result<(), String> {
+        if !self.widgets.contains_key(&id) || !self.widgets.contains_key(&parent) {
+            return Err(format!("cannot reparent {} to {}", id, parent));
+        }
+        if id == self.root || self.walk_from(id).contains(&parent) {
+            return Err("cannot make a widget a child of itself".to_string());
+        }
+        if let Some(old) = self.widgets[&id].parent {
+            if let Some(old) = self.widgets.get_mut(&old) {
+                old.children.retain(|c| *c != id);
+            }
+        }
+        if let Some(w) = self.widgets.get_mut(&parent) {
+            w.children.push(id);
+        }
+        if let Some(w) = self.widgets.get_mut(&id) {
+            w.parent = Some(parent);
+        }
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Every widget, parents before their children, in the order they are drawn
+    pub fn walk(&self) -> Vec<Identity> {
+        self.walk_from(self.root)
+    }
+
+    pub fn walk_from(&self, id: Identity) -> Vec<Identity> {
+        let mut order = Vec::new();
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            if let Some(widget) = self.widgets.get(&id) {
+                order.push(id);
+                stack.extend(widget.children.iter().rev());
+            }
+        }
+        order
+    }
+
+    /// Whether the widget and all of its ancestors are visible
+    pub fn is_shown(&self, id: Identity) -> bool {
+        if !self.widgets.contains_key(&id) {
+            return false;
+        }
+        let mut current = self.widgets.get(&id);
+        while let Some(widget) = current {
+            if !widget.visible {
+                return false;
+            }
+            current = widget.parent.and_then(|p| self.widgets.get(&p));
+        }
+        true
+    }
+
+    /// The deepest visible widget under the point, the last drawn where siblings overlap.
+    /// Children are clipped to their parents.
+    pub fn hit_test(&self, point: &ScreenPoint) -> Option<Identity> {
+        let is_hit = |id: &Identity| matches!(self.widgets.get(id), Some(w) if w.visible && w.view.hit_test(point.clone()));
+        let mut hit = Some(self.root).filter(is_hit)?;
+        while let Some(child) = self.widgets[&hit].children.iter().rev().find(|c| is_hit(c)) {
+            hit = *child;
+        }
+        Some(hit)
+    }
+
+    /// The widget itself or its nearest ancestor that `handles` an event, e.g. the button a
+    /// click on its label goes to
+    pub fn bubble<F>(&self, id: Identity, handles: F) -> Option<Identity>
+    where
+        F: Fn(&Widget) -> bool,
+    {
+        let mut current = self.widgets.get(&id);
+        while let Some(widget) = current {
+            if handles(widget) {
+                return Some(widget.identify());
+            }
+            current = widget.parent.and_then(|p| self.widgets.get(&p));
+        }
+        None
+    }
+
+    /// Delivers an event to its target widget, which may react with another event. Both bubble
+    /// through the handlers and are queued, see `take_events`.
+    pub fn dispatch(&mut self, mut event: UIEvent) {
+        let reaction = match self.widgets.get_mut(&event.target()) {
+            Some(widget) if widget.enabled => widget.react(&mut event),
+            Some(_) => return,
+            None => None,
+        };
+        if let UIEvent::Scrolled(..) = event {
+            self.dirty = true;
+        }
+        self.handle(&event);
+        self.events.push_back(event);
+        if let Some(reaction) = reaction {
+            self.handle(&reaction);
+            self.events.push_back(reaction);
+        }
+    }
+
+    /// Calls the handlers of the event's target, then of each ancestor in turn, until one
+    /// handles it. Returns the widget whose handler did.
+    fn handle(&mut self, event: &UIEvent) -> Option<Identity> {
+        let mut current = Some(event.target());
+        while let Some(id) = current {
+            let handled = match self.handlers.get_mut(&id) {
+                Some(handlers) => handlers.iter_mut().any(|h| (h.handler)(event)),
+                None => false,
+            };
+            if handled {
+                return Some(id);
+            }
+            current = self.widgets.get(&id).and_then(|w| w.parent);
+        }
+        None
+    }
+
+    /// Adds a handler for the events reaching the widget, see `dispatch`. The owner is the name
+    /// of the mod adding it.
+    pub fn add_handler<F>(&mut self, id: Identity, owner: &str, handler: F) -> Result<(), String>
+    where
+        F: FnMut(&UIEvent) -> bool + Send + 'static,
+    {
+        if !self.widgets.contains_key(&id) {
+            return Err(format!("no widget {} to handle events of", id));
+        }
+        self.handlers.entry(id).or_default().push(OwnedHandler {
+            owner: owner.to_string(),
+            handler: Box::new(handler),
+        });
+        Ok(())
+    }
+
+    /// Drops the handlers the mod added, e.g. as it unloads
+    pub fn remove_handlers(&mut self, owner: &str) {
+        for handlers in self.handlers.values_mut() {
+            handlers.retain(|h| h.owner != owner);
+        }
+        self.handlers.retain(|_, handlers| !handlers.is_empty());
+    }
+
+    pub fn take_events(&mut self) -> Vec<UIEvent> {
+        self.events.drain(..).collect()
+    }
+
+    /// Call after changing a widget's layout or visibility
+    pub fn invalidate_layout(&mut self) {
+        self.dirty = true;
+    }
+
+    pub fn needs_layout(&self) -> bool {
+        self.dirty
+    }
+
+    /// Physical pixels per logical pixel, as of the last layout
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Places the root by its layout within `area` (e.g. the host window), then lays out
+    /// everything under it
+    pub fn layout_in(&mut self, area: &ScreenRect, scale: f32) {
+        let bounds = place_free(area, &self.widgets[&self.root].layout, scale);
+        self.scale = scale;
+        self.place(self.root, bounds);
+        self.dirty = false;
+    }
+
+    /// Lays out again within the root's current bounds, if anything changed
+    pub fn update_layout(&mut self) {
+        if self.dirty {
+            let bounds = self.widgets[&self.root].view.bounds.clone();
+            self.place(self.root, bounds);
+            self.dirty = false;
+        }
+    }
+
+    fn place(&mut self, id: Identity, bounds: ScreenRect) {
+        let scale = self.scale;
+        let (layout, children) = match self.widgets.get_mut(&id) {
+            Some(widget) => {
+                widget.view.bounds = bounds.clone();
+                (widget.layout, widget.children.clone())
+            }
+            None => return,
+        };
+        let padding = layout.padding.scaled(scale);
+        let content = padding.shrink(&bounds);
+
+        // hidden widgets take no space, and keep their last bounds
+        let shown = children
+            .into_iter()
+            .filter(|c| matches!(self.widgets.get(c), Some(w) if w.visible))
+            .collect::<Vec<_>>();
+        let layouts = shown
+            .iter()
+            .map(|c| self.widgets[c].layout)
+            .collect::<Vec<_>>();
+        let mut rects = match layout.arrangement {
+            Arrangement::Free => layouts
+                .iter()
+                .map(|l| place_free(&content, l, scale))
+                .collect(),
+            Arrangement::HStack { spacing } => {
+                place_stack(&content, &layouts, true, spacing, scale)
+            }
+            Arrangement::VStack { spacing } => {
+                place_stack(&content, &layouts, false, spacing, scale)
+            }
+            Arrangement::Grid { columns, spacing } => {
+                place_grid(&content, &layouts, columns, spacing, scale)
+            }
+        };
+
+        // scroll panels remember how tall their contents are, and move them by the offset
+        if let Some(WidgetKind::ScrollPanel {
+            offset,
+            content_height,
+        }) = self.widgets.get_mut(&id).map(|w| &mut w.kind)
+        {
+            let bottom = rects.iter().map(|r| r.y + r.h).max().unwrap_or(content.y);
+            *content_height = bottom - bounds.y + padding.bottom;
+            *offset = (*offset).clamp(0, (*content_height - bounds.h).max(0));
+            for rect in rects.iter_mut() {
+                rect.y -= *offset;
+            }
+        }
+
+        for (child, rect) in shown.into_iter().zip(rects) {
+            self.place(child, rect);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_remove_and_reparent() {
+        let mut tree = WidgetTree::new(ScreenRect::new(0, 0, 100, 100));
+        let root = tree.root();
+        let panel = tree.add(root, Widget::container("panel")).unwrap();
+        let ok = tree.add(panel, Widget::button("ok", "OK")).unwrap();
+        assert_eq!(tree.find("ok"), Some(ok));
+        assert_eq!(tree.walk(), vec![root, panel, ok]);
+
+        assert!(tree.reparent(panel, ok).is_err());
+        tree.reparent(ok, root).unwrap();
+        assert_eq!(tree.get(ok).unwrap().parent(), Some(root));
+
+        tree.add(panel, Widget::label("title", "Options")).unwrap();
+        tree.remove(panel).unwrap();
+        assert_eq!(tree.len(), 2);
+        assert!(tree.find("title").is_none());
+    }
+
+    #[test]
+    fn widgets_react_to_dispatched_events() {
+        let mut tree = WidgetTree::new(ScreenRect::new(0, 0, 100, 100));
+        let root = tree.root();
+        let vsync = tree
+            .add(root, Widget::checkbox("vsync", "VSync", false))
+            .unwrap();
+        let volume = tree
+            .add(root, Widget::slider("volume", 0.0, 1.0, 0.5))
+            .unwrap();
+
+        tree.dispatch(UIEvent::Clicked(vsync));
+        tree.dispatch(UIEvent::ValueChanged(volume, 2.0));
+        let events = tree.take_events();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[1], UIEvent::Toggled(vsync, true));
+        assert_eq!(events[2], UIEvent::ValueChanged(volume, 1.0));
+        assert_eq!(
+            tree.get(volume).unwrap().kind,
+            WidgetKind::Slider {
+                min: 0.0,
+                max: 1.0,
+                value: 1.0
+            }
+        );
+
+        tree.get_mut(vsync).unwrap().enabled = false;
+        tree.dispatch(UIEvent::Clicked(vsync));
+        assert!(tree.take_events().is_empty());
+    }
+
+    #[test]
+    fn events_bubble_until_handled() {
+        use std::sync::{Arc, Mutex};
+
+        let mut tree = WidgetTree::new(ScreenRect::new(0, 0, 100, 100));
+        let root = tree.root();
+        let panel = tree.add(root, Widget::container("panel")).unwrap();
+        let ok = tree.add(panel, Widget::button("ok", "OK")).unwrap();
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let handler = |name: &'static str, handles: bool| {
+            let calls = calls.clone();
+            move |_: &UIEvent| {
+                calls.lock().unwrap().push(name);
+                handles
+            }
+        };
+        tree.add_handler(ok, "gui", handler("ok", false)).unwrap();
+        tree.add_handler(panel, "menu", handler("panel", true))
+            .unwrap();
+        tree.add_handler(root, "gui", handler("root", true))
+            .unwrap();
+        assert!(tree
+            .add_handler(12345, "gui", handler("none", true))
+            .is_err());
+
+        // the button doesn't handle it, the panel does, the root never sees it
+        tree.dispatch(UIEvent::Clicked(ok));
+        assert_eq!(*calls.lock().unwrap(), vec!["ok", "panel"]);
+
+        // e.g. the mod which added the panel's handler unloaded
+        calls.lock().unwrap().clear();
+        tree.remove_handlers("menu");
+        tree.dispatch(UIEvent::Clicked(ok));
+        assert_eq!(*calls.lock().unwrap(), vec!["ok", "root"]);
+        assert_eq!(tree.take_events().len(), 2);
+    }
+
+    #[test]
+    fn text_field_editing() {
+        let mut field = Widget::text_field("name", "your name");
+        field.insert_text("héllo");
+        assert_eq!(field.backspace(), Some("héll".to_string()));
+        if let WidgetKind::TextField { cursor, .. } = &mut field.kind {
+            *cursor = 2;
+        }
+        assert_eq!(field.insert_text("é"), Some("hééll".to_string()));
+    }
+}
@@ -6,6 +6,12 @@ pub struct FPS {
     last_frame: Instant,
 }
 
+impl Default for FPS {
+    fn default() -> Self {
+        FPS::new()
+    }
+}
+
 impl FPS {
     pub fn new() -> Self {
         FPS {
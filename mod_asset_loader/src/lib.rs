@@ -2,15 +2,17 @@ use std::sync::Arc;
 use std::time::Duration;
 
 // TODO: switch to nalgebra
-use game_state::nalgebra::{Matrix4, Vector3};
+use game_state::nalgebra::Vector3;
 
 use game_state::model::Model;
+use game_state::state::DrawMode;
 use game_state::state::ModelAccess;
 use game_state::state::RenderLayerAccess;
 use game_state::state::SceneGraph;
 use game_state::state::State;
+use game_state::state::WindowAccess;
 use game_state::state::WorldAccess;
-use game_state::thing::CameraFacet;
+use game_state::thing::{CameraFacet, TransformFacet};
 use game_state::tree::Node;
 
 #[no_mangle]
@@ -18,11 +20,10 @@ pub extern "C" fn mod_asset_loader_load(state: &mut State) {
     assert!(state.get_render_layers().is_empty());
 
     let origin = Vector3::new(0.0, 0.0, 0.0);
-    let mx = Matrix4::new_translation(&origin) * Matrix4::new_scaling(1.0);
 
     let model_path = "assets/models/plane.obj";
     println!(" loading model: {}", model_path);
-    let helper = Model::load(model_path, mx).unwrap().pop().unwrap();
+    let helper = Model::load(model_path).unwrap().pop().unwrap();
 
     let am = Arc::new(helper);
     state.add_model(am.clone());
@@ -31,27 +32,72 @@ pub extern "C" fn mod_asset_loader_load(state: &mut State) {
     // build the actual entity within the world
     let _camera = world
         .start_thing()
-        .with_camera(CameraFacet::new(
-            Vector3::new(0.0, 0.0, -2.0), // pos
-            -1.5,                         // pitch
-            0.0,                          // yaw
-        ))
+        .with_camera(CameraFacet::new())
+        .with_transform(
+            TransformFacet::at(Vector3::new(0.0, 0.0, -2.0)).with_rotation(
+                CameraFacet::orientation(
+                    -1.5, // pitch
+                    0.0,  // yaw
+                ),
+            ),
+        )
         .build();
 
-    let _helper_cube = world.start_thing().with_model(mx, am.clone()).build();
+    // a second, fixed camera looking down on the scene from above
+    let overview_camera = world
+        .start_thing()
+        .with_camera(CameraFacet::new())
+        .with_transform(
+            TransformFacet::at(Vector3::new(0.0, -4.0, -4.0)).with_rotation(
+                CameraFacet::orientation(
+                    -0.8, // pitch
+                    0.0,  // yaw
+                ),
+            ),
+        )
+        .build();
+    let overview_camera_id = overview_camera.lock().unwrap().id;
+
+    // the helpers are placed by a group thing, moving it moves both
+    let group = world
+        .start_thing()
+        .with_transform(TransformFacet::at(origin))
+        .build();
+    let group_id = group.lock().unwrap().id;
+    let mut helper_ids = Vec::new();
+    for _ in 0..2 {
+        let helper = world
+            .start_thing()
+            .with_model(am.clone())
+            .with_transform(TransformFacet::at(origin))
+            .build();
+        let helper_id = helper.lock().unwrap().id;
+        world
+            .set_parent(helper_id, Some(group_id))
+            .expect("the helpers have transforms");
+        helper_ids.push(helper_id);
+    }
 
     let root = Node::create(None, None);
 
     // TODO make this tree api better... currently returns the node, instead make a builder?
     // or maybe load from a file format (yaml?)
     let _ = Node::create(None, Some(root.clone()));
-    let helpers = Node::create(None, Some(root.clone()));
-    let _ = Node::create(Some(am.clone()), Some(helpers.clone()));
-    let _ = Node::create(Some(am), Some(helpers.clone()));
+    let helpers = Node::create(Some(group_id), Some(root.clone()));
+    for helper_id in helper_ids {
+        let _ = Node::create(Some(helper_id), Some(helpers.clone()));
+    }
 
     // NOTE: there's some index-mirroring happening here, we probably want to associate somehow
     // other than this - it's going to be easy to get wrong
     state.add_render_layer(Arc::new(SceneGraph { root }));
+
+    // wireframe windows render from the overview camera, the rest from the first camera
+    for (window_id, _, draw_mode) in state.get_windows() {
+        if let DrawMode::Wireframe(_) = draw_mode {
+            state.set_window_camera(window_id, overview_camera_id);
+        }
+    }
 }
 
 #[no_mangle]
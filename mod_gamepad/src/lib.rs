@@ -1,32 +1,71 @@
-use std::collections::VecDeque;
 use std::time::Duration;
 
-use gilrs::{Axis, Button, Event, EventType, Gilrs};
+use game_state::gilrs::{Axis, Button, Event, EventType, Gilrs};
 
 use game_state::input::events::{DeviceId, InputEvent, JoyAxis, JoyButton};
-//use game_state::input::InputSource;
 use game_state::state::{InputAccess, State};
-use game_state::thing::Direction;
-use game_state::{Identifyable, Identity};
+use game_state::Identity;
 
-struct GamepadInput {
-    id: Identity,
-    gilrs: Gilrs,
-}
+// Gamepad input through gilrs, the only source of controller events.
+//
+// The Gilrs instance is State's, made along with it rather than by this mod: gilrs runs a thread
+// of its own code, which can't be this mod's once it's unloaded. So gamepads stay connected
+// while the mod is reloaded, their events wait in gilrs's queue meanwhile. Each update those
+// events are sent to State, tagged with the window State says has keyboard focus.
+
+/// Every gilrs event since the last poll, gamepads State doesn't know of yet connected first
+fn poll(gilrs: &mut Gilrs, mut connected: Vec<DeviceId>, window: Identity) -> Vec<InputEvent> {
+    let mut events = Vec::new();
 
-impl GamepadInput {
-    pub fn new() -> Self {
-        GamepadInput {
-            id: game_state::create_next_identity(),
-            gilrs: Gilrs::new().unwrap(), // TODO: bubble error
+    // gilrs doesn't send Connected for gamepads present at startup
+    for (id, gamepad) in gilrs.gamepads() {
+        if !connected.contains(&id) {
+            println!("mod_gamepad: found {} ({})", gamepad.name(), id);
+            connected.push(id);
+            let guid = gamepad.uuid().to_string();
+            events.push(InputEvent::JoyConnected(window, id, guid));
         }
     }
-}
 
-impl Identifyable for GamepadInput {
-    fn identify(&self) -> Identity {
-        self.id
+    while let Some(Event { id, event, .. }) = gilrs.next_event() {
+        match event {
+            EventType::Connected if !connected.contains(&id) => {
+                connected.push(id);
+                let guid = gilrs.gamepad(id).uuid().to_string();
+                events.push(InputEvent::JoyConnected(window, id, guid));
+            }
+            EventType::Disconnected if connected.contains(&id) => {
+                connected.retain(|c| *c != id);
+                events.push(InputEvent::JoyDisconnected(window, id));
+            }
+            event => events.extend(convert_event(id, event, window)),
+        }
     }
+    events
+}
+
+fn convert_event(id: DeviceId, event: EventType, window: Identity) -> Option<InputEvent> {
+    let event = match event {
+        EventType::ButtonPressed(button, _code) => {
+            InputEvent::JoyButtonDown(window, id, convert_button(&button))
+        }
+        EventType::ButtonRepeated(button, _code) => {
+            InputEvent::JoyButtonRepeated(window, id, convert_button(&button))
+        }
+        EventType::ButtonReleased(button, _code) => {
+            InputEvent::JoyButtonUp(window, id, convert_button(&button))
+        }
+        EventType::ButtonChanged(button, value, _code) => {
+            InputEvent::JoyButtonChanged(window, id, convert_button(&button), value)
+        }
+        EventType::AxisChanged(axis, value, _code) => {
+            InputEvent::JoyAxisChanged(window, id, convert_axis(&axis), value)
+        }
+        EventType::Dropped => InputEvent::JoyEventDropped(window, id),
+        // tracked, see poll
+        EventType::Connected | EventType::Disconnected => return None,
+    };
+    Some(event)
 }
 
 fn convert_button(b: &Button) -> JoyButton {
@@ -68,77 +107,28 @@ fn convert_axis(a: &Axis) -> JoyAxis {
     }
 }
 
-/*
-// TODO: implement from<T> instead
-// TODO: VecDeque -> (Event Channel)
-impl InputSource for GamepadInput {
-    fn get_input_events(&mut self) -> VecDeque<InputEvent> {
-        let mut converted_events = VecDeque::new();
-        //...
-        while let Some(Event { id, event, time }) = self.gilrs.next_event() {
-            let event = match event {
-                EventType::ButtonPressed(button, _code) => {
-                    let b = convert_button(&button);
-                    converted_events.push_back(InputEvent::JoyButtonDown(self.identify(), id, b));
-                }
-                EventType::ButtonRepeated(button, _code) => {
-                    let b = convert_button(&button);
-                    converted_events.push_back(InputEvent::JoyButtonRepeated(
-                        self.identify(),
-                        id,
-                        b,
-                    ));
-                }
-                EventType::ButtonReleased(button, _code) => {
-                    let b = convert_button(&button);
-                    converted_events.push_back(InputEvent::JoyButtonUp(self.identify(), id, b));
-                }
-                EventType::ButtonChanged(button, value, _code) => {
-                    let b = convert_button(&button);
-                    converted_events.push_back(InputEvent::JoyButtonChanged(
-                        self.identify(),
-                        id,
-                        b,
-                        value,
-                    ));
-                }
-                EventType::AxisChanged(axis, value, _code) => {
-                    let a = convert_axis(&axis);
-                    converted_events.push_back(InputEvent::JoyAxisChanged(
-                        self.identify(),
-                        id,
-                        a,
-                        value,
-                    ));
-                }
-                EventType::Connected => {
-                    converted_events.push_back(InputEvent::JoyConnected(self.identify(), id));
-                }
-                EventType::Disconnected => {
-                    converted_events.push_back(InputEvent::JoyDisconnected(self.identify(), id));
-                }
-                EventType::Dropped => {
-                    converted_events.push_back(InputEvent::JoyEventDropped(self.identify(), id));
-                }
-            };
-        }
-        converted_events
-    }
-}
-*/
-
 #[no_mangle]
 pub extern "C" fn mod_gamepad_load(state: &mut State) {
-  /*  let gamepad_input = GamepadInput::new();
-    let gamepad_input = Box::new(gamepad_input) as Box<InputSource>;
-    state.add_input_source(gamepad_input);
-  */
+    if state.gilrs.is_none() {
+        println!("mod_gamepad: gamepads unavailable");
+    }
 }
 
 #[no_mangle]
-pub extern "C" fn mod_gamepad_update(state: &mut State, dt: &Duration) {}
+pub extern "C" fn mod_gamepad_update(state: &mut State, _dt: &Duration) {
+    let window = state.get_focused_window();
+    let connected = state.get_joysticks();
+    let events = match state.gilrs.as_mut() {
+        Some(gilrs) => poll(gilrs, connected, window),
+        None => return,
+    };
+    for event in events {
+        if let Err(err) = state.send_input_event(event) {
+            println!("mod_gamepad: unable to send input event {}", err);
+        }
+    }
+}
 
+// the gamepads are State's, they stay connected until the mod is loaded again
 #[no_mangle]
-pub extern "C" fn mod_gamepad_unload(state: &mut State) {
-    unimplemented!()
-}
+pub extern "C" fn mod_gamepad_unload(_state: &mut State) {}
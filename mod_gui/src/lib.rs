@@ -0,0 +1,268 @@
+use std::fs;
+use std::time::Duration;
+
+use game_state::input::screen::ScreenRect;
+use game_state::state::{ConsoleAccess, InputAccess, State, UIAccess, WindowAccess, WindowId};
+use game_state::ui::draw::Color;
+use game_state::ui::events::UIEvent;
+use game_state::ui::layout::{Arrangement, Insets};
+use game_state::ui::view::UIWindow;
+use game_state::ui::widget::{Widget, WidgetKind, WidgetTree};
+use game_state::Identity;
+
+// this module drives the UI. Windows and their widgets live in State (see `UIAccess`), so they
+// stay open while this or any other mod is reloaded.
+//
+// Input reaches the widgets as it is sent, each frame this mod lays out the windows that changed,
+// hands the resulting UIEvents to the other mods, through `UIAccess::pending_ui_events`, and
+// leaves a draw list per window for the renderers to draw over the scene.
+//
+// The first host window also shows a debug overlay: the frame rate, then any lines other mods
+// added this frame with `UIAccess::add_debug_text`, and the developer console drops down over it
+// while open (see `ui::console`).
+//
+// Menus are UI documents (see `ui::document`), every .ui file in assets/ui is opened on the first
+// load and again whenever it's saved. Their callbacks reach the other mods through
+// `UIAccess::pending_ui_callbacks`.
+
+/// Tried in order when no font has been loaded yet
+const FONTS: [&str; 3] = [
+    "assets/fonts/default.ttf",
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/Library/Fonts/Arial.ttf",
+];
+
+/// UI documents, loaded in file name order
+const UI_DOCUMENTS: &str = "assets/ui";
+
+const DEBUG_WINDOW: &str = "debug";
+const CONSOLE_WINDOW: &str = "console";
+/// Lines of output shown above the input line
+const CONSOLE_LINES: usize = 14;
+/// Labels are 24 pixels high
+const CONSOLE_HEIGHT: i32 = (CONSOLE_LINES as i32 + 1) * 24 + 8;
+const CONSOLE_BACKGROUND: Color = [0.05, 0.05, 0.08, 0.9];
+
+#[no_mangle]
+pub extern "C" fn mod_gui_load(state: &mut State) {
+    state.on_ui_load();
+    if !state.has_fonts() {
+        load_default_font(state);
+    }
+    // documents already loaded keep their windows, and so their state, across a reload
+    if !state.has_ui_documents() {
+        load_ui_documents(state);
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn mod_gui_update(state: &mut State, _dt: &Duration) {
+    update_debug_text(state);
+    update_console(state);
+    for e in state.reload_ui_documents() {
+        println!("UI document not reloaded: {}", e);
+    }
+    state.layout_ui();
+    state.take_ui_events();
+    update_text_input(state);
+    state.draw_ui();
+}
+
+#[no_mangle]
+pub extern "C" fn mod_gui_unload(state: &mut State) {
+    state.on_ui_unload();
+}
+
+fn load_default_font(state: &mut State) {
+    for path in FONTS.iter() {
+        match state.load_font("default", path) {
+            Ok(()) => return,
+            Err(e) => println!("font not loaded: {}", e),
+        }
+    }
+    println!(
+        "no font found, UI text won't be drawn. Add one at {}",
+        FONTS[0]
+    );
+}
+
+fn load_ui_documents(state: &mut State) {
+    let entries = match fs::read_dir(UI_DOCUMENTS) {
+        Ok(entries) => entries,
+        Err(e) => {
+            println!("no UI documents in {}: {}", UI_DOCUMENTS, e);
+            return;
+        }
+    };
+    let mut paths = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().and_then(|e| e.to_str()) == Some("ui"))
+        .collect::<Vec<_>>();
+    paths.sort();
+    for path in paths {
+        let path = path.to_string_lossy();
+        if let Err(e) = state.load_ui_document(&path) {
+            println!("UI document not loaded: {}", e);
+        }
+    }
+}
+
+/// A label per line in the debug window, which is opened in the first host window
+fn update_debug_text(state: &mut State) {
+    state.get_ui_fps().update();
+    let mut lines = vec![format!("{:.0} fps", state.get_ui_fps().get())];
+    lines.extend(state.take_debug_text());
+
+    let host = match state.get_windows().first() {
+        Some((id, _, _)) => *id as Identity,
+        None => return,
+    };
+    let scene = state.get_ui_scene();
+    let window = match scene.find_window(DEBUG_WINDOW) {
+        Some(window) => window,
+        None => {
+            let mut window = UIWindow::new(DEBUG_WINDOW.to_string(), ScreenRect::new(8, 8, 400, 0));
+            window.host = Some(host);
+            let root = window.widgets.root();
+            if let Some(root) = window.widgets.get_mut(root) {
+                root.layout.arrangement = Arrangement::VStack { spacing: 0 };
+            }
+            scene.add_window(window)
+        }
+    };
+    if let Some(window) = scene.window_mut(window) {
+        show_lines(&mut window.widgets, &lines);
+    }
+}
+
+/// The console drops down over the top of the first host window while it is open
+fn update_console(state: &mut State) {
+    state.run_console();
+    let host = match state.get_windows().first() {
+        Some((id, _, _)) => *id as Identity,
+        None => return,
+    };
+    let console = state.get_console();
+    let open = console.is_open();
+    let mut lines = Vec::new();
+    if open {
+        // shown as typed, not as markup
+        let escape = |line: &str| line.replace('[', "[[");
+        let skip = console.output().len().saturating_sub(CONSOLE_LINES);
+        lines.extend(console.output().iter().skip(skip).map(|l| escape(l)));
+        lines.push(format!("> {}_", escape(console.input())));
+    }
+
+    let scene = state.get_ui_scene();
+    let window = match (scene.find_window(CONSOLE_WINDOW), open) {
+        (Some(window), true) => window,
+        (Some(window), false) => {
+            scene.remove_window(window);
+            return;
+        }
+        (None, true) => {
+            let bounds = ScreenRect::new(0, 0, 0, CONSOLE_HEIGHT);
+            let mut window = UIWindow::new(CONSOLE_WINDOW.to_string(), bounds);
+            window.host = Some(host);
+            let root = window.widgets.root();
+            if let Some(root) = window.widgets.get_mut(root) {
+                root.layout.arrangement = Arrangement::VStack { spacing: 0 };
+                root.layout.padding = Insets::new(4, 8, 4, 8);
+                root.background = Some(CONSOLE_BACKGROUND);
+            }
+            scene.add_window(window)
+        }
+        (None, false) => return,
+    };
+    if let Some(window) = scene.window_mut(window) {
+        show_lines(&mut window.widgets, &lines);
+    }
+}
+
+/// A label per line under the root, reusing the labels there
+fn show_lines(widgets: &mut WidgetTree, lines: &[String]) {
+    let root = widgets.root();
+    let labels = widgets
+        .get(root)
+        .map(|r| r.children().to_vec())
+        .unwrap_or_default();
+    for (i, line) in lines.iter().enumerate() {
+        match labels.get(i).and_then(|id| widgets.get_mut(*id)) {
+            Some(label) => {
+                if let WidgetKind::Label { text } = &mut label.kind {
+                    if *text != *line {
+                        *text = line.clone();
+                    }
+                }
+            }
+            None => {
+                let label = Widget::label(&format!("line{}", i), line);
+                if let Err(e) = widgets.add(root, label) {
+                    println!("show_lines: {}", e);
+                }
+            }
+        }
+    }
+    for id in labels.iter().skip(lines.len()) {
+        if let Err(e) = widgets.remove(*id) {
+            println!("show_lines: {}", e);
+        }
+    }
+}
+
+/// Text input is on while the console is open or a text field has focus, with the IME placed
+/// over the field
+fn update_text_input(state: &mut State) {
+    if state.get_console().is_open() {
+        let host = state.get_windows().first().map(|(id, _, _)| *id);
+        if let Some(host) = host {
+            if state.get_text_input_window() != Some(host) {
+                state.start_text_input(host);
+            }
+        }
+        return;
+    }
+    let focused = state.get_ui_scene().focused();
+    let field = focused.and_then(|(window, widget)| {
+        let window = state.get_ui_scene().window(window)?;
+        let host = window.host?;
+        let widget = window.widgets.get(widget)?;
+        match widget.kind {
+            WidgetKind::TextField { .. } => {
+                let scale = window.widgets.scale();
+                Some((
+                    host as WindowId,
+                    widget.view.id,
+                    logical(&widget.view.bounds, scale),
+                ))
+            }
+            _ => None,
+        }
+    });
+
+    match field {
+        Some((host, id, rect)) => {
+            let focus_moved = state
+                .pending_ui_events()
+                .iter()
+                .any(|e| *e == UIEvent::GainedFocus(id));
+            if state.get_text_input_window() != Some(host) {
+                state.start_text_input(host);
+            } else if !focus_moved {
+                return;
+            }
+            state.set_text_input_rect(rect);
+        }
+        None => {
+            if state.get_text_input_window().is_some() {
+                state.stop_text_input();
+            }
+        }
+    }
+}
+
+/// Widget bounds are in drawable pixels, SDL places the IME in window coordinates
+fn logical(rect: &ScreenRect, scale: f32) -> ScreenRect {
+    let s = |v: i32| (v as f32 / scale).round() as i32;
+    ScreenRect::new(s(rect.x), s(rect.y), s(rect.w), s(rect.h))
+}
@@ -0,0 +1,220 @@
+use std::time::Duration;
+
+use game_state::input::contexts::InputContext;
+use game_state::input::events::InputEvent;
+use game_state::nalgebra::{UnitQuaternion, Vector3};
+use game_state::sdl2::{
+    mouse::MouseUtil,
+    video::{FullscreenType, Window},
+};
+use game_state::state::{
+    InputAccess, State, UIAccess, VariableAccess, WindowAccess, WindowId, WorldAccess,
+};
+use game_state::thing::{CameraFacet, Direction, TransformFacet};
+use game_state::Identity;
+
+// Application controls, driven by the named actions bound in assets/config/bindings.cfg:
+// pausing, cursor grab, fullscreen and the first camera. Actions are read through the gameplay
+// context, and the menu context pushed while paused. The pause menu in assets/ui/pause.ui resumes
+// and quits through its callbacks.
+
+pub fn grab_cursor(grab: bool, mouse: &MouseUtil) {
+    mouse.show_cursor(!grab);
+    mouse.set_relative_mouse_mode(grab);
+}
+
+// digital movement actions and the direction each moves the camera
+const MOVEMENT_ACTIONS: [(&str, Direction); 6] = [
+    ("move_forward", Direction::Forward),
+    ("move_backward", Direction::Backward),
+    ("move_left", Direction::Left),
+    ("move_right", Direction::Right),
+    ("move_up", Direction::Up),
+    ("move_down", Direction::Down),
+];
+
+pub const GAMEPLAY_CONTEXT: &str = "gameplay";
+const MENU_CONTEXT: &str = "menu";
+
+pub fn gameplay_context() -> InputContext {
+    let mut actions = vec![
+        "pause",
+        "grab_cursor",
+        "fullscreen",
+        "look_x",
+        "look_y",
+        "print_fov",
+        "narrow_fov",
+    ];
+    actions.extend(MOVEMENT_ACTIONS.iter().map(|(action, _)| *action));
+    InputContext::new(GAMEPLAY_CONTEXT, &actions)
+}
+
+// pushed while paused, hiding every gameplay action. Back leaves the menu too, the UI only keeps
+// it while a text field is focused.
+fn menu_context() -> InputContext {
+    InputContext::new(MENU_CONTEXT, &["pause", "back", "quit", "fullscreen"]).blocking()
+}
+
+pub fn update(state: &mut State, events: &[InputEvent], focused_window: Identity, dt: &Duration) {
+    let mut paused = state.get_bool("paused").unwrap_or(false);
+    let mouse = state.sdl_context.mouse();
+    let mut mouse_grabbed = state.get_bool("mouse_grabbed").unwrap_or(true);
+    let callbacks = state
+        .pending_ui_callbacks()
+        .iter()
+        .map(|c| c.name.clone())
+        .collect::<Vec<_>>();
+    let called = |name: &str| callbacks.iter().any(|c| c == name);
+
+    for event in events {
+        match event {
+            InputEvent::QuitRequested => {
+                println!("quitting...");
+                std::process::exit(0);
+            }
+            InputEvent::CloseRequested(window) => {
+                state.close_window(*window as WindowId);
+                if state.get_windows().is_empty() {
+                    println!("last window closed, quitting...");
+                    std::process::exit(0);
+                }
+            }
+            InputEvent::MouseMove(_, point) => state.set_mouse_pos(point.clone()),
+            _ => {}
+        }
+    }
+
+    if state
+        .get_context_action(GAMEPLAY_CONTEXT, "pause")
+        .just_pressed
+    {
+        println!("user pressed 'pause' : paused.");
+        paused = true;
+        state.push_input_context(menu_context());
+
+        // un-grab the cursor if we are paused
+        if mouse_grabbed {
+            grab_cursor(false, &mouse);
+        }
+    } else if state.get_context_action(MENU_CONTEXT, "pause").just_pressed
+        || state.get_context_action(MENU_CONTEXT, "back").just_pressed
+        || (paused && called("resume"))
+    {
+        println!("user pressed 'pause' : unpaused.");
+        paused = false;
+        state.pop_input_context(MENU_CONTEXT);
+
+        // re-grab the cursor if we are unpausing and it was grabbed
+        if mouse_grabbed {
+            grab_cursor(true, &mouse);
+        }
+    }
+
+    if state.get_context_action(MENU_CONTEXT, "quit").just_pressed || called("quit") {
+        println!("user pressed 'quit' while paused : hard exit.");
+        std::process::exit(0);
+    }
+
+    if state
+        .get_context_action(GAMEPLAY_CONTEXT, "grab_cursor")
+        .just_pressed
+    {
+        mouse_grabbed = !mouse_grabbed;
+        grab_cursor(mouse_grabbed, &mouse);
+    }
+
+    //
+    // TODO: pausing should prevent changes to the world, rather than guard input
+    //
+    // every held movement action contributes, the camera normalizes the sum. While paused the
+    // menu blocks these, so the camera stops.
+    let movement = MOVEMENT_ACTIONS
+        .iter()
+        .filter(|(action, _)| state.get_context_action(GAMEPLAY_CONTEXT, action).pressed)
+        .map(|(_, direction)| *direction)
+        .collect::<Vec<_>>();
+
+    // look sensitivity is part of the input profiles, see assets/config/input_profiles.cfg
+    let xa = state.get_context_action(GAMEPLAY_CONTEXT, "look_x").value;
+    let ya = state.get_context_action(GAMEPLAY_CONTEXT, "look_y").value;
+
+    move_camera(state, &movement);
+    if xa != 0.0 || ya != 0.0 {
+        with_camera(state, |_, transform| rotate_camera(transform, xa, ya));
+    }
+
+    let fullscreen = [MENU_CONTEXT, GAMEPLAY_CONTEXT]
+        .iter()
+        .any(|context| state.get_context_action(context, "fullscreen").just_pressed);
+    if fullscreen {
+        toggle_fullscreen(state, focused_window);
+    }
+    if state
+        .get_context_action(GAMEPLAY_CONTEXT, "print_fov")
+        .just_pressed
+    {
+        with_camera(state, |camera, _| println!("{}", camera.perspective.fovy()));
+    }
+    if state
+        .get_context_action(GAMEPLAY_CONTEXT, "narrow_fov")
+        .just_pressed
+    {
+        with_camera(state, |camera, _| {
+            camera.perspective.set_fovy(camera.perspective.fovy() - 1.0)
+        });
+    }
+
+    with_camera(state, |camera, transform| camera.update(transform, dt));
+    state.set_bool("paused", paused);
+    state.set_bool("mouse_grabbed", mouse_grabbed);
+}
+
+// By convention the first camera in the world is the one the player controls
+fn with_camera<F: FnOnce(&mut CameraFacet, &mut TransformFacet)>(state: &mut State, func: F) {
+    let mut cameras = state
+        .get_world()
+        .query_mut::<(&mut CameraFacet, &mut TransformFacet)>();
+    let first = cameras.iter().next();
+    if let Some((_, (camera, transform))) = first {
+        func(camera, transform);
+    }
+}
+
+fn move_camera(state: &mut State, directions: &[Direction]) {
+    with_camera(state, |camera, _| camera.set_movement(directions));
+}
+
+fn rotate_camera(transform: &mut TransformFacet, xa: f32, ya: f32) {
+    const HALF_PI: f32 = 0.5 * std::f32::consts::PI;
+
+    // Clamp up/down rotation of the camera
+    let pitch = CameraFacet::pitch(transform.rotation());
+    let tilt = (pitch - ya).clamp(-HALF_PI, HALF_PI) - pitch;
+
+    // yaw turns around the world's up, pitch around the camera's own x axis. Turning about +y
+    // is to the left, so moving the mouse right turns by -x.
+    let yaw = UnitQuaternion::from_axis_angle(&Vector3::y_axis(), -xa);
+    let tilt = UnitQuaternion::from_axis_angle(&Vector3::x_axis(), tilt);
+    transform.set_rotation(yaw * *transform.rotation() * tilt);
+}
+
+fn toggle_fullscreen(state: &mut State, window_id: Identity) {
+    let window = state
+        .get_windows()
+        .into_iter()
+        .find(|(id, _, _)| *id as Identity == window_id);
+
+    if let Some((_, context, _)) = window {
+        // TODO: wrap unsafe call in State, particularly WindowAccess
+        let mut window = unsafe { Window::from_ref(context) };
+        match window.fullscreen_state() {
+            FullscreenType::Off => window
+                .set_fullscreen(FullscreenType::Desktop)
+                .expect("unable to set fs"),
+            _ => window
+                .set_fullscreen(FullscreenType::Off)
+                .expect("unable to set fs"),
+        }
+    }
+}
@@ -1,34 +1,89 @@
 use std::time::Duration;
 
-use game_state::sdl2::video::Window;
-use game_state::state::{InputAccess, State, VariableAccess, WindowAccess, WorldAccess};
-use game_state::thing::{CameraFacet, Direction};
+use game_state::input::actions::ActionMap;
+use game_state::input::processing::InputProfiles;
+use game_state::state::{InputAccess, State, VariableAccess};
 
-use game_state::sdl2::{
-    event::Event as SdlEvent, keyboard::Keycode, mouse::MouseUtil, video::FullscreenType,
-};
+mod controls;
+mod translate;
 
 // this module's purpose is to turn input events into meaningful application input
 // this might include closing windows, keyboard presses, mouse drags
 // mapping user settings to keyboard and mouse bindings
+//
+// Each frame, raw SDL events are translated into InputEvents and published to State,
+// then read back (like any other consumer would) to drive the application controls.
 
 //
 // TODO:
-// 1. simultaneous keypresses
-// 2. FPS camera rotation, clamp camera angles
+// 1. FPS camera rotation, clamp camera angles
 //
-fn grab_cursor(grab: bool, mouse: &MouseUtil) {
-    mouse.show_cursor(!grab);
-    mouse.set_relative_mouse_mode(grab);
+
+const INPUT_READER: &str = "mod_input";
+// the controls only see presses while gameplay receives events, the action map sees every event
+// since actions are delivered through the contexts anyway
+const CONTROLS_READER: &str = "mod_input_controls";
+
+const BINDINGS_PATH: &str = "assets/config/bindings.cfg";
+const DEFAULT_BINDINGS: &str = include_str!("../../assets/config/bindings.cfg");
+
+const PROFILES_PATH: &str = "assets/config/input_profiles.cfg";
+const DEFAULT_PROFILES: &str = include_str!("../../assets/config/input_profiles.cfg");
+
+fn load_bindings() -> ActionMap {
+    ActionMap::load(BINDINGS_PATH).unwrap_or_else(|err| {
+        println!(
+            "mod_input: unable to load {}, using default bindings. {}",
+            BINDINGS_PATH, err
+        );
+        ActionMap::parse(DEFAULT_BINDINGS).expect("default bindings are invalid")
+    })
+}
+
+fn load_profiles() -> InputProfiles {
+    InputProfiles::load(PROFILES_PATH).unwrap_or_else(|err| {
+        println!(
+            "mod_input: unable to load {}, using default input profiles. {}",
+            PROFILES_PATH, err
+        );
+        InputProfiles::parse(DEFAULT_PROFILES).expect("default input profiles are invalid")
+    })
 }
 
 #[no_mangle]
 pub extern "C" fn mod_input_load(state: &mut State) {
     state.on_input_load();
+    state.register_input_reader(INPUT_READER);
+    state.register_input_reader(CONTROLS_READER);
+    state.set_input_reader_context(CONTROLS_READER, Some(controls::GAMEPLAY_CONTEXT));
+
+    // bindings and profiles live in State, so runtime changes survive reloading this mod
+    if state.get_action_map().is_empty() {
+        *state.get_action_map() = load_bindings();
+        // as with the profiles, only rebinds made from here on are saved
+        state.take_changed_bindings();
+    }
+    if state.get_input_profiles().is_default() {
+        *state.get_input_profiles() = load_profiles();
+        // only changes made from here on are saved, a file that failed to load is kept
+        state.take_changed_input_profiles();
+    }
+    // the stack survives reloads too, pushing gameplay again would put it above a paused menu
+    if !state
+        .get_input_contexts()
+        .contains(controls::GAMEPLAY_CONTEXT)
+    {
+        state.push_input_context(controls::gameplay_context());
+    }
+
+    // SDL starts text input on init, only keep it for a window that asked for it
+    if state.get_text_input_window().is_none() {
+        state.stop_text_input();
+    }
 
     let mouse = state.sdl_context.mouse();
     let mouse_grabbed = state.get_bool("mouse_grabbed").unwrap_or(true);
-    grab_cursor(mouse_grabbed, &mouse);
+    controls::grab_cursor(mouse_grabbed, &mouse);
 }
 
 #[no_mangle]
@@ -39,143 +94,37 @@ pub extern "C" fn mod_input_update(state: &mut State, dt: &Duration) {
         .poll_iter()
         .collect::<Vec<_>>();
 
-    // TODO: wrap unsafe call in State, particularly WindowAccess
-    let (sdlwin, _) = state.get_windows()[0].clone();
-    let mut window = unsafe { Window::from_ref(sdlwin) };
-    //
+    let focused_window = state.get_focused_window();
 
-    let mut paused = state.get_bool("paused").unwrap_or(false);
-    let mouse = state.sdl_context.mouse();
-    let mut mouse_grabbed = state.get_bool("mouse_grabbed").unwrap_or(true);
-    let mut camera = &mut state.get_world().get_facets().cameras[0];
-
-    for event in frame_events {
-        match event {
-            SdlEvent::Quit { .. } => {
-                println!("quitting...");
-                std::process::exit(0);
-            }
-            SdlEvent::KeyDown {
-                keycode: Some(code),
-                ..
-            } => match code {
-                Keycode::Escape => {
-                    if paused {
-                        println!("user pressed 'Esc' : unpaused.");
-                        paused = false;
-
-                        // re-grab the cursor if we are unpausing and it was grabbed
-                        if mouse_grabbed {
-                            grab_cursor(true, &mouse);
-                        }
-                    } else {
-                        println!("user pressed 'Esc' : paused.");
-                        camera.movement_dir = None;
-                        paused = true;
-
-                        // un-grab the cursor if we are paused
-                        if mouse_grabbed {
-                            grab_cursor(false, &mouse);
-                        }
-                    }
-                }
-
-                Keycode::Q => {
-                    if paused {
-                        println!("user pressed 'q' while paused : hard exit.");
-                        std::process::exit(0);
-                    }
-                }
-
-                //
-                // TODO: pausing should prevent changes to the world, rather than guard input
-                //
-                Keycode::E if !paused => camera.movement_dir = Some(Direction::Up),
-                Keycode::C if !paused => camera.movement_dir = Some(Direction::Down),
-                Keycode::W if !paused => camera.movement_dir = Some(Direction::Forward),
-                Keycode::A if !paused => camera.movement_dir = Some(Direction::Left),
-                Keycode::S if !paused => camera.movement_dir = Some(Direction::Backward),
-                Keycode::D if !paused => camera.movement_dir = Some(Direction::Right),
-                Keycode::G if !paused => {
-                    mouse_grabbed = !mouse_grabbed;
-                    grab_cursor(mouse_grabbed, &mouse);
-                }
-                Keycode::F => match window.fullscreen_state() {
-                    FullscreenType::Off => window
-                        .set_fullscreen(FullscreenType::Desktop)
-                        .expect("unable to set fs"),
-                    _ => window
-                        .set_fullscreen(FullscreenType::Off)
-                        .expect("unable to set fs"),
-                },
-
-                Keycode::Num9 => {
-                    println!("{}", camera.perspective.fovy());
-                }
-                Keycode::Num0 => camera.perspective.set_fovy(camera.perspective.fovy() - 1.0),
-
-                _ => {}
-            },
-            SdlEvent::KeyUp {
-                keycode: Some(code),
-                ..
-            } => {
-                match code {
-                    // TODO: support multiple keypresses
-                    Keycode::E | Keycode::C | Keycode::W | Keycode::A | Keycode::S | Keycode::D => {
-                        camera.movement_dir = None;
-                    }
-                    _ => {}
-                }
-            }
-            SdlEvent::MouseMotion { xrel, yrel, .. } => {
-                if !paused {
-                    // println!( "mod_input: pos {:?} pitch {}, yaw {}", camera.pos, camera.pitch, camera.yaw);
-                    let sensitivity = 100.0;
-                    let (dx, dy) = (xrel as f32, yrel as f32);
-                    let xa = dx / sensitivity;
-                    let ya = dy / sensitivity;
-
-                    #[inline(always)]
-                    fn _rotate_camera(camera: &mut CameraFacet, xa: f32, ya: f32) {
-                        const TWO_PI: f32 = 2.0 * std::f32::consts::PI;
-                        const HALF_PI: f32 = 0.5 * std::f32::consts::PI;
-
-                        camera.yaw += xa;
-
-                        // round-robin yaw motion
-                        if camera.yaw.abs() > TWO_PI {
-                            camera.yaw = 0.0;
-                        }
-
-                        camera.pitch += -ya;
-                        // Clamp up/down rotation of the camera
-                        pub fn clamp(val: f32, min: f32, max: f32) -> f32 {
-                            assert!(min <= max);
-                            let mut x = val;
-                            if x < min {
-                                x = min;
-                            }
-                            if x > max {
-                                x = max;
-                            }
-                            x
-                        }
-                        camera.pitch = clamp(camera.pitch, -HALF_PI, HALF_PI);
-                    }
-
-                    _rotate_camera(camera, xa, ya);
-                }
-            }
-            _ => {}
+    let input_events = frame_events
+        .iter()
+        .flat_map(translate::translate_event)
+        .collect::<Vec<_>>();
+    for input_event in input_events {
+        if let Err(err) = state.send_input_event(input_event) {
+            println!("mod_input: unable to send input event {}", err);
+        }
+    }
+
+    let events = state.read_input_events(INPUT_READER);
+    state.get_action_map().update(&events);
+    let events = state.read_input_events(CONTROLS_READER);
+    controls::update(state, &events, focused_window, dt);
+
+    if let Some(bindings) = state.take_changed_bindings() {
+        if let Err(err) = bindings.save(BINDINGS_PATH) {
+            println!("mod_input: unable to save {}. {}", BINDINGS_PATH, err);
+        }
+    }
+    if let Some(profiles) = state.take_changed_input_profiles() {
+        if let Err(err) = profiles.save(PROFILES_PATH) {
+            println!("mod_input: unable to save {}. {}", PROFILES_PATH, err);
         }
     }
-    camera.update(dt);
-    state.set_bool("paused", paused);
-    state.set_bool("mouse_grabbed", mouse_grabbed);
 }
 
 #[no_mangle]
 pub extern "C" fn mod_input_unload(state: &mut State) {
+    // the reader stays registered, so events published while reloading are not lost
     state.on_input_unload();
 }
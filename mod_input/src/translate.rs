@@ -0,0 +1,126 @@
+use game_state::input::events::{InputEvent, MouseButton};
+use game_state::input::screen::{DeltaVector, ScreenPoint};
+use game_state::Identity;
+
+use game_state::sdl2::{
+    event::{Event as SdlEvent, WindowEvent},
+    mouse::MouseButton as SdlMouseButton,
+};
+
+// Translation of raw SDL events into the engine's InputEvent.
+// Window events are tagged with the SDL window id. Controllers are left to mod_gamepad, SDL's are
+// never opened so each pad's events are sent once.
+
+pub fn translate_event(event: &SdlEvent) -> Vec<InputEvent> {
+    match event {
+        SdlEvent::Quit { .. } => vec![InputEvent::QuitRequested],
+
+        SdlEvent::Window {
+            window_id,
+            win_event,
+            ..
+        } => translate_window_event(*window_id as Identity, win_event)
+            .into_iter()
+            .collect(),
+
+        // TODO: key repeats are dropped for now, toggles bound to a key would flip on every repeat
+        SdlEvent::KeyDown { repeat: true, .. } => vec![],
+        SdlEvent::KeyDown {
+            window_id,
+            keycode: Some(code),
+            ..
+        } => vec![InputEvent::KeyDown(
+            *window_id as Identity,
+            *code as i32 as u32,
+        )],
+        SdlEvent::KeyUp {
+            window_id,
+            keycode: Some(code),
+            ..
+        } => vec![InputEvent::KeyUp(
+            *window_id as Identity,
+            *code as i32 as u32,
+        )],
+
+        SdlEvent::TextInput {
+            window_id, text, ..
+        } => vec![InputEvent::TextInput(*window_id as Identity, text.clone())],
+        SdlEvent::TextEditing {
+            window_id,
+            text,
+            start,
+            length,
+            ..
+        } => vec![InputEvent::TextEditing(
+            *window_id as Identity,
+            text.clone(),
+            *start,
+            *length,
+        )],
+
+        SdlEvent::MouseMotion {
+            window_id,
+            x,
+            y,
+            xrel,
+            yrel,
+            ..
+        } => {
+            let id = *window_id as Identity;
+            vec![
+                InputEvent::MouseMove(id, ScreenPoint::new(*x, *y)),
+                InputEvent::MouseMotion(id, *xrel as f32, *yrel as f32),
+            ]
+        }
+        SdlEvent::MouseButtonDown {
+            window_id,
+            mouse_btn,
+            ..
+        } => vec![InputEvent::MouseDown(
+            *window_id as Identity,
+            convert_mouse_button(mouse_btn),
+        )],
+        SdlEvent::MouseButtonUp {
+            window_id,
+            mouse_btn,
+            ..
+        } => vec![InputEvent::MouseUp(
+            *window_id as Identity,
+            convert_mouse_button(mouse_btn),
+        )],
+        SdlEvent::MouseWheel {
+            window_id, x, y, ..
+        } => vec![InputEvent::MouseWheel(
+            *window_id as Identity,
+            DeltaVector::new(*x, *y),
+        )],
+
+        _ => vec![],
+    }
+}
+
+fn translate_window_event(id: Identity, win_event: &WindowEvent) -> Option<InputEvent> {
+    match win_event {
+        WindowEvent::Close => Some(InputEvent::CloseRequested(id)),
+        WindowEvent::Resized(w, h) | WindowEvent::SizeChanged(w, h) => {
+            Some(InputEvent::Resized(id, *w as f32, *h as f32))
+        }
+        WindowEvent::Moved(x, y) => Some(InputEvent::Moved(id, ScreenPoint::new(*x, *y))),
+        WindowEvent::FocusGained => Some(InputEvent::GainedFocus(id)),
+        WindowEvent::FocusLost => Some(InputEvent::LostFocus(id)),
+        WindowEvent::Enter => Some(InputEvent::MouseEntered(id)),
+        WindowEvent::Leave => Some(InputEvent::MouseLeft(id)),
+        _ => None,
+    }
+}
+
+fn convert_mouse_button(b: &SdlMouseButton) -> MouseButton {
+    match b {
+        SdlMouseButton::Left => MouseButton::Left,
+        SdlMouseButton::Middle => MouseButton::Middle,
+        SdlMouseButton::Right => MouseButton::Right,
+        SdlMouseButton::X1 => MouseButton::Other(4),
+        SdlMouseButton::X2 => MouseButton::Other(5),
+        SdlMouseButton::Unknown => MouseButton::Other(0),
+    }
+}
@@ -5,7 +5,8 @@ use glutin;
 use glutin::GlContext;
 
 use game_state;
-use game_state::state::SceneGraph;
+use game_state::state::{CameraView, DrawMode, ModelView, SceneGraph, WindowId};
+use game_state::ui::draw::DrawList;
 use game_state::winit;
 use game_state::Renderer;
 
@@ -13,7 +14,6 @@ use game_state::input;
 
 use std::sync::{Arc, Mutex};
 
-use game_state::thing::CameraFacet;
 use game_state::Identity;
 
 use std::collections::VecDeque;
@@ -23,6 +23,7 @@ pub struct OpenGLRenderer {
     gl_window: glutin::GlWindow,
     el: glutin::EventsLoop,
     render_layer_queue: VecDeque<Arc<SceneGraph>>,
+    ui_layer_queue: VecDeque<Arc<DrawList>>,
 }
 impl OpenGLRenderer {
     pub fn new(
@@ -48,10 +49,13 @@ impl OpenGLRenderer {
             gl_window: gl_window,
             el,
             render_layer_queue: VecDeque::new(),
+            ui_layer_queue: VecDeque::new(),
         }
     }
 
-    fn render(&mut self, camera: &CameraFacet) {
+    // TODO: nothing is drawn yet, the views and the models' TransformFacet world matrices are
+    // what the vulkano renderer draws the layers with
+    fn render(&mut self, _views: &[CameraView], _models: &[ModelView]) {
         unsafe {
             gl::Clear(gl::COLOR_BUFFER_BIT);
         }
@@ -61,12 +65,15 @@ impl OpenGLRenderer {
                 Some(layer) => {
 
                     //TODO : gather needed resources from the layer and render
-
                 }
                 None => break,
             }
         }
 
+        // TODO: draw the UI over the layers, as the vulkano renderer does (tessellate each list,
+        // one draw per batch with its clip rect as the scissor)
+        self.ui_layer_queue.clear();
+
         self.gl_window
             .swap_buffers()
             .expect("failed to swap buffers");
@@ -80,7 +87,7 @@ impl game_state::Identifyable for OpenGLRenderer {
 }
 
 impl game_state::input::InputSource for OpenGLRenderer {
-    fn get_input_events(&mut self) -> VecDeque<input::events::InputEvent> {
+    fn get_input_events(&mut self, _window: Identity) -> VecDeque<input::events::InputEvent> {
         VecDeque::new()
     }
 }
@@ -94,7 +101,30 @@ impl Renderer for OpenGLRenderer {
         self.render_layer_queue.push_back(layer);
     }
 
-    fn present(&mut self, camera: &CameraFacet) {
-        self.render(camera);
+    fn queue_ui_layer(&mut self, layer: Arc<DrawList>) {
+        self.ui_layer_queue.push_back(layer);
+    }
+
+    // the polygon mode is context state, the UI has to be drawn with FILL once it is drawn
+    fn set_draw_mode(&mut self, mode: DrawMode) {
+        unsafe {
+            match mode {
+                DrawMode::Wireframe(line_width) => {
+                    gl::PolygonMode(gl::FRONT_AND_BACK, gl::LINE);
+                    gl::LineWidth(line_width);
+                }
+                DrawMode::Points => gl::PolygonMode(gl::FRONT_AND_BACK, gl::POINT),
+                DrawMode::Textured => gl::PolygonMode(gl::FRONT_AND_BACK, gl::FILL),
+            }
+        }
+    }
+
+    fn window_id(&self) -> WindowId {
+        // TODO: this renderer still creates its own glutin window rather than using an SDL one
+        0
+    }
+
+    fn present(&mut self, views: &[CameraView], models: &[ModelView]) {
+        self.render(views, models);
     }
 }
@@ -11,14 +11,15 @@ use renderer::vulkano::VulkanoRenderer;
 pub extern "C" fn mod_rendering_vulkano_load(state: &mut State) {
     let windows = state.get_windows();
 
-    for (w, draw_mode) in windows {
+    for (window_id, w, draw_mode) in windows {
         // hack for sdl to own this "window", but pass it's surface to the underlying swapchain
         let win_ptr = {
             let sdlwin = unsafe { Window::from_ref(w) };
             let c = unsafe { &*sdlwin.raw() };
             crate::renderer::vulkano::vulkano_sdl2::WinPtr { raw: c as *const _ }
         };
-        let maybe_renderer = VulkanoRenderer::new(win_ptr, draw_mode, state.get_models());
+        let maybe_renderer =
+            VulkanoRenderer::new(win_ptr, window_id, draw_mode, state.get_models());
 
         match maybe_renderer {
             Ok(mut renderer) => {
@@ -1,4 +1,4 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::mem;
 use std::sync::Arc;
 use std::time::Duration;
@@ -39,14 +39,17 @@ use game_state;
 use game_state::model::Model;
 use game_state::state::DrawMode;
 use game_state::state::SceneGraph;
-use game_state::thing::CameraFacet;
+use game_state::state::{CameraView, ModelView, WindowId};
 use game_state::tree::BreadthFirstIterator;
+use game_state::ui::draw::DrawList;
 use game_state::utils::fps;
 use game_state::{Identifyable, Identity, Renderer};
 
 use game_state::nalgebra::Matrix4;
 
+pub mod ui;
 pub mod vertex;
+use self::ui::UIRenderer;
 use self::vertex::Vertex;
 
 pub mod vulkano_sdl2;
@@ -121,6 +124,7 @@ type ThisFramebufferType = Arc<dyn FramebufferAbstract + Send + Sync + 'static>;
 
 pub struct VulkanoRenderer {
     id: Identity,
+    window_id: WindowId,
     instance: Arc<Instance>,
     surface: Arc<Surface<WinPtr>>,
     depth_buffer: Arc<dyn ImageViewAbstract + Send + Sync>,
@@ -134,11 +138,10 @@ pub struct VulkanoRenderer {
 
     renderpass: Arc<RenderPass>,
 
-    // TODO: camera
-    uniform_buffer: Arc<CpuAccessibleBuffer<vs::ty::Data>>,
-
     render_layer_queue: VecDeque<Arc<SceneGraph>>,
+    ui_layer_queue: VecDeque<Arc<DrawList>>,
     model_data: Vec<ModelData>,
+    ui: UIRenderer,
 
     // Enable vulkan debug layers? - need to install the vulkan sdk to get them
     #[allow(dead_code)]
@@ -146,7 +149,6 @@ pub struct VulkanoRenderer {
 
     previous_frame_end: Box<dyn GpuFuture>,
     recreate_swapchain: bool,
-    dynamic_state: DynamicState,
 }
 
 impl VulkanoRenderer {
@@ -198,7 +200,6 @@ impl VulkanoRenderer {
 
     fn create_descriptor_set(
         device: Arc<Device>,
-        uniform_buffer: Arc<CpuAccessibleBuffer<vs::ty::Data>>,
         pipeline: Arc<ThisPipelineType>,
         texture: Arc<dyn ImageViewAbstract + Send + Sync>,
     ) -> Arc<dyn DescriptorSet + Send + Sync> {
@@ -221,8 +222,6 @@ impl VulkanoRenderer {
         let ds = PersistentDescriptorSet::start(layout.clone())
             .add_sampled_image(texture, sampler)
             .expect("error loading texture")
-            .add_buffer(uniform_buffer)
-            .expect("error adding uniform buffer")
             .build()
             .unwrap();
 
@@ -253,8 +252,45 @@ impl VulkanoRenderer {
             .collect::<Vec<_>>()
     }
 
+    fn create_pipeline(
+        device: Arc<Device>,
+        renderpass: Arc<RenderPass>,
+        draw_mode: DrawMode,
+    ) -> eyre::Result<Arc<ThisPipelineType>> {
+        // TODO: as part of asset_loader, we should be loading all the shaders we expect to use in a scene
+        let vs = vs::Shader::load(device.clone()).expect("failed to create vs shader module");
+        let fs = fs::Shader::load(device.clone()).expect("failed to create fs shader module");
+
+        let p = GraphicsPipeline::start()
+            .vertex_input_single_buffer()
+            .polygon_mode_fill()
+            .depth_clamp(true)
+            .cull_mode_front()
+            .front_face_counter_clockwise()
+            .vertex_shader(vs.main_entry_point(), ())
+            .triangle_list()
+            .viewports_dynamic_scissors_irrelevant(1)
+            .fragment_shader(fs.main_entry_point(), ())
+            .depth_stencil_simple_depth()
+            .blend_alpha_blending();
+
+        let p = match draw_mode {
+            DrawMode::Wireframe(line_width) => p.line_width(line_width).polygon_mode_line(),
+            DrawMode::Points => p.polygon_mode_point(),
+
+            // add more variants here, but for now we default to Textured
+            _ => p.polygon_mode_fill(),
+        };
+        let p = p
+            .render_pass(Subpass::from(renderpass, 0).unwrap())
+            .build(device)?;
+
+        Ok(Arc::new(p))
+    }
+
     pub fn new(
         win_ptr: WinPtr,
+        window_id: WindowId,
         draw_mode: DrawMode,
         models: Vec<Arc<Model>>,
     ) -> eyre::Result<Self> {
@@ -310,35 +346,6 @@ impl VulkanoRenderer {
         let (swapchain, images) = Self::create_swapchain(surface.clone(), device.clone(), physical)
             .wrap_err_with(|| "unable to create swapchain")?;
 
-        // TODO: as part of asset_loader, we should be loading all the shaders we expect to use in a scene
-        let vs = vs::Shader::load(device.clone()).expect("failed to create vs shader module");
-        let fs = fs::Shader::load(device.clone()).expect("failed to create fs shader module");
-
-        // ----------------------------------
-        // Uniform buffer
-        // TODO: extract to the notion of a camera
-
-        let proj = Matrix4::new_perspective(
-            {
-                let d = ImageAccess::dimensions(&images[0]);
-                d.width() as f32 / d.height() as f32
-            }, // aspect
-            ::std::f32::consts::FRAC_PI_2,
-            0.01,
-            100.0, // depth used for culling!
-        );
-
-        let uniform_buffer = CpuAccessibleBuffer::<vs::ty::Data>::from_data(
-            device.clone(),
-            vulkano::buffer::BufferUsage::all(),
-            false,
-            // uniform projection matrix
-            vs::ty::Data { proj: proj.into() },
-        )
-        .wrap_err("failed to create uniform buffer")?;
-
-        // ----------------------------------
-
         let img_usage = ImageUsage {
             transient_attachment: true,
             input_attachment: true,
@@ -387,37 +394,15 @@ impl VulkanoRenderer {
             depth_buffer.clone(),
         );
 
-        let p = GraphicsPipeline::start()
-            .vertex_input_single_buffer()
-            .polygon_mode_fill()
-            .depth_clamp(true)
-            .cull_mode_front()
-            .front_face_counter_clockwise()
-            .vertex_shader(vs.main_entry_point(), ())
-            .triangle_list()
-            .viewports_dynamic_scissors_irrelevant(1)
-            .fragment_shader(fs.main_entry_point(), ())
-            .depth_stencil_simple_depth()
-            .blend_alpha_blending();
-
-        let p = match draw_mode {
-            DrawMode::Wireframe(line_width) => p.line_width(line_width).polygon_mode_line(),
-            DrawMode::Points => p.polygon_mode_point(),
-
-            // add more variants here, but for now we default to Textured
-            _ => p.polygon_mode_fill(),
-        };
-        let p = p
-            .render_pass(Subpass::from(renderpass.clone(), 0).unwrap())
-            .build(device.clone())?;
-
-        let pipeline = Arc::new(p);
+        let pipeline = Self::create_pipeline(device.clone(), renderpass.clone(), draw_mode)?;
+        let ui = UIRenderer::new(device.clone(), queue.clone(), renderpass.clone())?;
 
         let previous_frame_end = Box::new(now(device.clone())) as Box<dyn GpuFuture>;
         let instance = instance.clone();
 
         let mut renderer = VulkanoRenderer {
             id: game_state::create_next_identity(),
+            window_id,
             instance,
             surface,
             device,
@@ -427,23 +412,15 @@ impl VulkanoRenderer {
             pipeline,
             depth_buffer,
             framebuffers,
-            uniform_buffer,
             debug_callback,
             previous_frame_end,
             renderpass: renderpass as Arc<RenderPass>,
             recreate_swapchain: false, // flag indicating to rebuild the swapchain on the next frame
             model_data: Vec::with_capacity(models.len()),
             render_layer_queue: VecDeque::new(),
+            ui_layer_queue: VecDeque::new(),
+            ui,
             fps: fps::FPS::new(),
-            dynamic_state: DynamicState {
-                line_width: None,
-                viewports: Some(vec![vulkano::pipeline::viewport::Viewport {
-                    origin: [0.0, 0.0],
-                    dimensions: [dimensions.width() as f32, dimensions.height() as f32],
-                    depth_range: 0.0..1.0,
-                }]),
-                ..DynamicState::none()
-            },
         };
 
         for model in models {
@@ -506,7 +483,6 @@ impl VulkanoRenderer {
 
         let pipeline_set = Self::create_descriptor_set(
             self.device.clone(),
-            self.uniform_buffer.clone(),
             self.pipeline.clone(),
             ImageView::new(texture.clone()).unwrap(),
         );
@@ -586,7 +562,7 @@ impl VulkanoRenderer {
         self.recreate_swapchain = true;
     }
 
-    fn render(&mut self, camera: &CameraFacet) {
+    fn render(&mut self, views: &[CameraView], models: &[ModelView]) {
         self.previous_frame_end.cleanup_finished();
 
         if self.recreate_swapchain {
@@ -629,16 +605,6 @@ impl VulkanoRenderer {
                         self.depth_buffer.clone(),
                     );
 
-                    self.dynamic_state = DynamicState {
-                        line_width: None,
-                        viewports: Some(vec![vulkano::pipeline::viewport::Viewport {
-                            origin: [0.0, 0.0],
-                            dimensions: [dims[0] as f32, dims[1] as f32],
-                            depth_range: 0.0..1.0,
-                        }]),
-                        ..DynamicState::none()
-                    };
-
                     self.recreate_swapchain = false;
                 }
                 Err(SwapchainCreationError::UnsupportedDimensions) => {
@@ -681,6 +647,10 @@ impl VulkanoRenderer {
         .wrap_err("unable to start command buffer builder")
         .unwrap(); // catch oom error here
 
+        // UI textures are uploaded before the render pass, copies aren't allowed inside it
+        let ui_layers = self.ui_layer_queue.drain(..).collect::<Vec<_>>();
+        self.ui.prepare(&mut cmd_buffer_build, &ui_layers);
+
         cmd_buffer_build
             .begin_render_pass(
                 self.framebuffers[image_num].clone(),
@@ -692,82 +662,92 @@ impl VulkanoRenderer {
             )
             .expect("unable to begin renderpass");
 
-        let view = camera.view;
-
-        let scale = Matrix4::new_scaling(1.0);
-        let viewscale = view * scale;
-
-        // TODO: WIP implement a notion of a camera
-        // TODO: do we want to do this every frame?
-        let proj_mat = Matrix4::new_perspective(
-            {
-                let d = ImageAccess::dimensions(&self.images[0]);
-                d.width() as f32 / d.height() as f32
-            },
-            ::std::f32::consts::FRAC_PI_2,
-            0.01,
-            100.0, // depth used for culling!
-        );
+        let dimensions = ImageAccess::dimensions(&self.images[0]);
+        let layers = self.render_layer_queue.drain(..).collect::<Vec<_>>();
+        let models = models
+            .iter()
+            .map(|m| (m.thing, m))
+            .collect::<HashMap<_, _>>();
+
+        for CameraView { viewport, view, .. } in views {
+            let (origin, viewport_dims) =
+                viewport.to_pixels(dimensions.width(), dimensions.height());
+            if viewport_dims[0] <= 0.0 || viewport_dims[1] <= 0.0 {
+                continue;
+            }
 
-        // modify the data in the uniform buffer for this renderer == our camera
-        match self.uniform_buffer.write() {
-            Ok(mut uniform) => uniform.proj = proj_mat.into(),
-            Err(_err) => {} // println!("Error writing to uniform buffer {:?}", err),
-        }
+            let dynamic_state = DynamicState {
+                line_width: None,
+                viewports: Some(vec![vulkano::pipeline::viewport::Viewport {
+                    origin,
+                    dimensions: viewport_dims,
+                    depth_range: 0.0..1.0,
+                }]),
+                ..DynamicState::none()
+            };
 
-        while let Some(next_layer) = self.render_layer_queue.pop_front() {
-            // TODO: refactor this to use asset lookups
-            // TODO: refactor this to use WorldEntity collection -> SceneGraph Rc types
-            // TODO: asset lookups should store DescriptorSets with associated textures
-
-            let iterator = BreadthFirstIterator::new(next_layer.root.clone());
-            for (_node_id, rc) in iterator {
-                let node = &mut rc.borrow_mut();
-
-                // TODO: implement a per model -instance- matrix in the graph itself?
-                let model = match node.data.clone() {
-                    Some(model) => model.clone(),
-                    None => continue,
-                };
-                if let Some(md) = self.model_data.iter().find(|md| md.model.id == model.id) {
-                    let model_mat = model.model_mat;
-
-                    // TODO: update the world matrices from the parent * child's local matrix
-                    // eg. flag dirty a node, which means all children must be updated
-                    // actually save the data in each node
-                    let transform_mat = node
-                        .parent()
-                        .map(|parent| {
-                            let parent_model = parent.borrow().data.clone();
-                            if let Some(parent_model) = parent_model {
-                                parent_model.world_mat * model_mat
-                            } else {
-                                model_mat
-                            }
-                        })
-                        .unwrap_or_else(|| model_mat);
-
-                    // Push constants are leveraged here to send per-model
-                    // matrices into the shaders
-                    let push_constants = vs::ty::PushConstants {
-                        model_mat: (viewscale * transform_mat).into(),
+            let scale = Matrix4::new_scaling(1.0);
+            let viewscale = view * scale;
+
+            // TODO: use the camera's own perspective
+            let proj_mat = Matrix4::new_perspective(
+                viewport_dims[0] / viewport_dims[1], // aspect of this viewport
+                ::std::f32::consts::FRAC_PI_2,
+                0.01,
+                100.0, // depth used for culling!
+            );
+
+            for next_layer in layers.iter() {
+                // TODO: refactor this to use asset lookups
+                // TODO: asset lookups should store DescriptorSets with associated textures
+
+                let iterator = BreadthFirstIterator::new(next_layer.root.clone());
+                for (_node_id, rc) in iterator {
+                    let node = &mut rc.borrow_mut();
+
+                    let instance = match node.data {
+                        Some(thing) => match models.get(&thing) {
+                            Some(instance) => *instance,
+                            None => continue,
+                        },
+                        None => continue,
                     };
-
-                    cmd_buffer_build
-                        .draw_indexed(
-                            self.pipeline.clone(),
-                            &self.dynamic_state,
-                            md.vertices.clone(),
-                            md.indices.clone(),
-                            md.material_data.descriptor_set.clone(),
-                            push_constants, // or () - both leak on win32...
-                            vec![],
-                        )
-                        .expect("Unable to add command");
+                    let model = &instance.model;
+                    if let Some(md) = self.model_data.iter().find(|md| md.model.id == model.id) {
+                        // the thing's TransformFacet already includes its parents
+                        let transform_mat = instance.world;
+
+                        // Push constants are leveraged here to send per-model
+                        // matrices and the per-viewport projection into the shaders
+                        let push_constants = vs::ty::PushConstants {
+                            model_mat: (viewscale * transform_mat).into(),
+                            proj: proj_mat.into(),
+                        };
+
+                        cmd_buffer_build
+                            .draw_indexed(
+                                self.pipeline.clone(),
+                                &dynamic_state,
+                                md.vertices.clone(),
+                                md.indices.clone(),
+                                md.material_data.descriptor_set.clone(),
+                                push_constants, // or () - both leak on win32...
+                                vec![],
+                            )
+                            .expect("Unable to add command");
+                    }
                 }
             }
         }
 
+        // the UI goes over every view
+        for layer in ui_layers.iter() {
+            let dims = [dimensions.width(), dimensions.height()];
+            if let Err(e) = self.ui.draw(&mut cmd_buffer_build, layer, dims) {
+                println!("VulkanoRenderer::render() unable to draw ui: {}", e);
+            }
+        }
+
         cmd_buffer_build
             .end_render_pass()
             .expect("unable to end renderpass ");
@@ -843,8 +823,25 @@ impl Renderer for VulkanoRenderer {
         self.render_layer_queue.push_back(layer);
     }
 
-    fn present(&mut self, camera: &CameraFacet) {
-        self.render(camera);
+    fn queue_ui_layer(&mut self, layer: Arc<DrawList>) {
+        self.ui_layer_queue.push_back(layer);
+    }
+
+    // the polygon mode is baked into the pipeline, models keep their descriptor sets as the
+    // layout doesn't change
+    fn set_draw_mode(&mut self, draw_mode: DrawMode) {
+        match Self::create_pipeline(self.device.clone(), self.renderpass.clone(), draw_mode) {
+            Ok(pipeline) => self.pipeline = pipeline,
+            Err(err) => println!("Failed to change draw mode. {}", err),
+        }
+    }
+
+    fn window_id(&self) -> WindowId {
+        self.window_id
+    }
+
+    fn present(&mut self, views: &[CameraView], models: &[ModelView]) {
+        self.render(views, models);
     }
 }
 
@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use eyre::WrapErr;
+use vulkano::buffer::{BufferSlice, BufferUsage, CpuAccessibleBuffer};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, DynamicState, PrimaryAutoCommandBuffer};
+use vulkano::descriptor::descriptor_set::{DescriptorSet, PersistentDescriptorSet};
+use vulkano::descriptor::pipeline_layout::PipelineLayoutAbstract;
+use vulkano::device::{Device, Queue};
+use vulkano::image::{
+    view::ImageView, ImageCreateFlags, ImageDimensions, ImageUsage, StorageImage,
+};
+use vulkano::pipeline::vertex::SingleBufferDefinition;
+use vulkano::pipeline::viewport::{Scissor, Viewport};
+use vulkano::pipeline::GraphicsPipeline;
+use vulkano::render_pass::{RenderPass, Subpass};
+use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
+
+use game_state::input::screen::ScreenRect;
+use game_state::ui::draw::{DrawCommand, DrawList, UITexture};
+use game_state::Identity;
+
+use super::vertex::UIVertex;
+
+//
+// UI compositing: draw lists are tessellated into one mesh per window and drawn after the 3D
+// layers, in the same render pass, with alpha blending and without the depth test. Each batch of
+// the mesh is drawn with its clip rect as the scissor and its texture bound, plain quads sample
+// a white pixel. Textures are uploaded before the render pass, again only when their version
+// moves on, and only their dirty rect when this renderer has the version before.
+//
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "../assets/shaders/ui_vs.glsl"
+    }
+}
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "../assets/shaders/ui_fs.glsl"
+    }
+}
+
+type UIPipelineType = GraphicsPipeline<
+    SingleBufferDefinition<UIVertex>,
+    Box<dyn PipelineLayoutAbstract + Send + Sync>,
+>;
+
+struct UploadedTexture {
+    version: u64,
+    image: Arc<StorageImage>,
+    descriptor_set: Arc<dyn DescriptorSet + Send + Sync>,
+}
+
+pub struct UIRenderer {
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    pipeline: Arc<UIPipelineType>,
+    sampler: Arc<Sampler>,
+    /// Sampled by quads without a texture
+    white: UITexture,
+    textures: HashMap<Identity, UploadedTexture>,
+}
+
+impl UIRenderer {
+    pub fn new(
+        device: Arc<Device>,
+        queue: Arc<Queue>,
+        renderpass: Arc<RenderPass>,
+    ) -> eyre::Result<Self> {
+        let vs = vs::Shader::load(device.clone()).wrap_err("failed to create ui vs module")?;
+        let fs = fs::Shader::load(device.clone()).wrap_err("failed to create ui fs module")?;
+
+        let pipeline = GraphicsPipeline::start()
+            .vertex_input_single_buffer::<UIVertex>()
+            .vertex_shader(vs.main_entry_point(), ())
+            .triangle_list()
+            .viewports_scissors_dynamic(1)
+            .fragment_shader(fs.main_entry_point(), ())
+            .blend_alpha_blending()
+            .render_pass(Subpass::from(renderpass, 0).unwrap())
+            .build(device.clone())
+            .wrap_err("failed to create ui pipeline")?;
+
+        let sampler = Sampler::new(
+            device.clone(),
+            Filter::Linear,
+            Filter::Linear,
+            MipmapMode::Nearest,
+            SamplerAddressMode::ClampToEdge,
+            SamplerAddressMode::ClampToEdge,
+            SamplerAddressMode::ClampToEdge,
+            0.0,
+            1.0,
+            0.0,
+            0.0,
+        )
+        .wrap_err("failed to create ui sampler")?;
+
+        let white = image::RgbaImage::from_pixel(1, 1, image::Rgba([255, 255, 255, 255]));
+        Ok(UIRenderer {
+            device,
+            queue,
+            pipeline: Arc::new(pipeline),
+            sampler,
+            white: UITexture::new(game_state::create_next_identity(), white),
+            textures: HashMap::new(),
+        })
+    }
+
+    /// Uploads the textures the lists draw with, outside of the render pass. Textures no list
+    /// draws with anymore are dropped.
+    pub fn prepare(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        lists: &[Arc<DrawList>],
+    ) {
+        let mut used = HashMap::new();
+        used.insert(self.white.id, self.white.clone());
+        for list in lists {
+            for command in list.commands.iter() {
+                if let DrawCommand::Image { texture, .. } = command {
+                    let newest = used.get(&texture.id).map(|t: &UITexture| t.version);
+                    if newest.map(|v| v < texture.version).unwrap_or(true) {
+                        used.insert(texture.id, texture.clone());
+                    }
+                }
+            }
+        }
+        self.textures.retain(|id, _| used.contains_key(id));
+
+        for texture in used.values() {
+            if let Err(e) = self.upload(builder, texture) {
+                println!(
+                    "UIRenderer::prepare() unable to upload texture {}: {}",
+                    texture.id, e
+                );
+            }
+        }
+    }
+
+    fn upload(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        texture: &UITexture,
+    ) -> eyre::Result<()> {
+        let image = texture.image.read().map_err(|e| eyre::eyre!("{}", e))?;
+        let (width, height) = image.dimensions();
+
+        let region = match self.textures.get(&texture.id) {
+            Some(uploaded) if uploaded.version == texture.version => return Ok(()),
+            Some(uploaded) if uploaded.version + 1 == texture.version => texture
+                .dirty
+                .clone()
+                .unwrap_or_else(|| ScreenRect::new(0, 0, width as i32, height as i32)),
+            _ => {
+                let gpu_image = StorageImage::with_usage(
+                    self.device.clone(),
+                    ImageDimensions::Dim2d {
+                        width,
+                        height,
+                        array_layers: 1,
+                    },
+                    vulkano::format::Format::R8G8B8A8Unorm,
+                    ImageUsage {
+                        transfer_destination: true,
+                        sampled: true,
+                        ..ImageUsage::none()
+                    },
+                    ImageCreateFlags::none(),
+                    Some(self.queue.family()),
+                )
+                .wrap_err("could not create ui texture")?;
+                let layout = self.pipeline.layout().descriptor_set_layout(0).unwrap();
+                let descriptor_set = PersistentDescriptorSet::start(layout.clone())
+                    .add_sampled_image(ImageView::new(gpu_image.clone())?, self.sampler.clone())?
+                    .build()?;
+                self.textures.insert(
+                    texture.id,
+                    UploadedTexture {
+                        version: texture.version,
+                        image: gpu_image,
+                        descriptor_set: Arc::new(descriptor_set),
+                    },
+                );
+                ScreenRect::new(0, 0, width as i32, height as i32)
+            }
+        };
+
+        let region = region.intersection(&ScreenRect::new(0, 0, width as i32, height as i32));
+        let uploaded = self.textures.get_mut(&texture.id).expect("uploaded above");
+        uploaded.version = texture.version;
+        if region.is_empty() {
+            return Ok(());
+        }
+        let (x, y, w, h) = (
+            region.x as u32,
+            region.y as u32,
+            region.w as u32,
+            region.h as u32,
+        );
+        let pixels = (y..y + h)
+            .flat_map(|row| (x..x + w).map(move |col| (col, row)))
+            .map(|(col, row)| image.get_pixel(col, row).0)
+            .collect::<Vec<_>>();
+        let staging = CpuAccessibleBuffer::from_iter(
+            self.device.clone(),
+            BufferUsage::transfer_source(),
+            false,
+            pixels.into_iter(),
+        )?;
+        builder.copy_buffer_to_image_dimensions(
+            staging,
+            uploaded.image.clone(),
+            [x, y, 0],
+            [w, h, 1],
+            0,
+            1,
+            0,
+        )?;
+        Ok(())
+    }
+
+    /// Draws a list over whatever is in the framebuffer, inside the render pass
+    pub fn draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        list: &DrawList,
+        dimensions: [u32; 2],
+    ) -> eyre::Result<()> {
+        let mesh = list.tessellate();
+        if mesh.indices.is_empty() {
+            return Ok(());
+        }
+        let screen = ScreenRect::new(0, 0, dimensions[0] as i32, dimensions[1] as i32);
+        let vertices = CpuAccessibleBuffer::from_iter(
+            self.device.clone(),
+            BufferUsage::vertex_buffer(),
+            false,
+            mesh.vertices.iter().map(UIVertex::from_gs_vertex),
+        )?;
+        let indices = CpuAccessibleBuffer::from_iter(
+            self.device.clone(),
+            BufferUsage::index_buffer(),
+            false,
+            mesh.indices.iter().cloned(),
+        )?;
+        let push_constants = vs::ty::PushConstants {
+            screen_size: [dimensions[0] as f32, dimensions[1] as f32],
+        };
+
+        for batch in mesh.batches.iter() {
+            let id = batch
+                .texture
+                .as_ref()
+                .map(|t| t.id)
+                .unwrap_or(self.white.id);
+            let texture = match self.textures.get(&id) {
+                Some(texture) => texture,
+                None => continue,
+            };
+            let clip = match &batch.clip {
+                Some(clip) => clip.intersection(&screen),
+                None => screen.clone(),
+            };
+            if clip.is_empty() {
+                continue;
+            }
+            let dynamic_state = DynamicState {
+                viewports: Some(vec![Viewport {
+                    origin: [0.0, 0.0],
+                    dimensions: [dimensions[0] as f32, dimensions[1] as f32],
+                    depth_range: 0.0..1.0,
+                }]),
+                scissors: Some(vec![Scissor {
+                    origin: [clip.x, clip.y],
+                    dimensions: [clip.w as u32, clip.h as u32],
+                }]),
+                ..DynamicState::none()
+            };
+            let range = batch.indices.start as usize..batch.indices.end as usize;
+            let batch_indices = BufferSlice::from_typed_buffer_access(indices.clone())
+                .slice(range)
+                .ok_or_else(|| eyre::eyre!("batch indices out of range"))?;
+            builder.draw_indexed(
+                self.pipeline.clone(),
+                &dynamic_state,
+                vertices.clone(),
+                batch_indices,
+                texture.descriptor_set.clone(),
+                push_constants,
+                vec![],
+            )?;
+        }
+        Ok(())
+    }
+}
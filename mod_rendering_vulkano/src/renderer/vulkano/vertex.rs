@@ -1,4 +1,5 @@
 use game_state::model::Vertex as GSVertex;
+use game_state::ui::draw::UIVertex as GSUIVertex;
 
 #[derive(Default, Debug, Clone)]
 pub struct Vertex {
@@ -21,3 +22,23 @@ impl Vertex {
 // the reason for this copying is to put the data into a struct we can
 // impl_vertex! on, in this crate...
 vulkano::impl_vertex!(Vertex, position, normal, uv);
+
+/// A corner of a UI quad, in pixels from the top left of the window
+#[derive(Default, Debug, Clone, Copy)]
+pub struct UIVertex {
+    position: [f32; 2],
+    uv: [f32; 2],
+    color: [f32; 4],
+}
+
+impl UIVertex {
+    pub fn from_gs_vertex(v: &GSUIVertex) -> Self {
+        UIVertex {
+            position: v.position,
+            uv: v.uv,
+            color: v.color,
+        }
+    }
+}
+
+vulkano::impl_vertex!(UIVertex, position, uv, color);
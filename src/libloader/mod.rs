@@ -164,6 +164,16 @@ impl LibLoader {
         }
     }
 
+    ///
+    /// reload()
+    ///
+    /// Unload and load the lib again, even if it hasn't changed on disk
+    ///
+    pub fn reload(&mut self, state: &mut state::State) {
+        self.modified = Duration::from_millis(0);
+        self.check_update(state);
+    }
+
     ///
     /// update()
     ///
@@ -197,6 +207,7 @@ impl LibLoader {
         let method_name = format!("mod_{}_unload", self.mod_name);
         self.message("Unloaded");
         self.call(&method_name, state);
+        state.on_mod_unload(&self.mod_name);
     }
 
     ///
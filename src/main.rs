@@ -8,13 +8,16 @@ use std::time::{Duration, Instant};
 
 use std::thread;
 
-use game_state::state::WindowAccess;
+use game_state::state::{ConsoleAccess, InputAccess, MessageAccess, WindowAccess};
+use game_state::ui::console::{ArgKind, Command};
+
+/// Owner of the console commands run by the main loop
+const CONSOLE_OWNER: &str = "main";
 
 fn main() {
     let mut state = State::default();
 
     // TODO mod_audio
-    // TODO mod_gui
     // TODO mod_network
 
     state.add_window(
@@ -41,11 +44,27 @@ fn main() {
 
     mods.push(load_mod!(rendering_vulkano));
     mods.push(load_mod!(input));
+    // after input, so the UI events it queues include this frame's input
+    mods.push(load_mod!(gui));
 
     for m in mods.iter_mut() {
         m.check_update(&mut state);
     }
 
+    let names = mods
+        .iter()
+        .map(|m| m.get_name().to_string())
+        .collect::<Vec<_>>();
+    for name in names.iter() {
+        state.open_inbox(name);
+    }
+    let reload = Command::new("reload", CONSOLE_OWNER)
+        .with_help("unloads a mod and loads it again")
+        .with_arg("mod", ArgKind::OneOf(names));
+    if let Err(e) = state.register_console_command(reload) {
+        println!("{}", e);
+    }
+
     let mut frame = 0;
     let frame_budget = 16000u128; // for 60 fps
     let mut last_update = Instant::now();
@@ -67,6 +86,18 @@ fn main() {
             total_time += duration.as_micros();
         }
         last_update = Instant::now();
+        state.end_input_frame();
+        // what mods sent this tick, they read it next tick
+        state.deliver_messages();
+
+        for command in state.take_console_commands(CONSOLE_OWNER) {
+            if let Some(name) = command.text(0) {
+                if let Some(m) = mods.iter_mut().find(|m| m.get_name() == name) {
+                    state.print_to_console(&format!("reloading {}", name));
+                    m.reload(&mut state);
+                }
+            }
+        }
         if frame % 300 == 0 {
             println!(
                 "|>= total time: {total_time:>6} μs",